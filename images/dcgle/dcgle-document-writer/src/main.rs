@@ -8,12 +8,39 @@ use nats::jetstream::{Consumer, StreamConfig};
 use postgres::{Client, NoTls};
 
 fn upsert_gallery(client: &mut Client, gallery: &Gallery) -> anyhow::Result<()> {
+    let info = gallery.info.as_ref();
     client.execute(
         r#"
-        INSERT INTO dcinside_gallery (id, name, kind) 
-        VALUES ($1, $2, $3)
-        ON CONFLICT DO NOTHING"#,
-        &[&gallery.id, &gallery.name, &gallery.kind.name()],
+        INSERT INTO dcinside_gallery 
+            (id, name, kind, 
+            category, manager_id, sub_manager_ids, member_count, 
+            subjects, created_at, rank) 
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ON CONFLICT (id) DO UPDATE SET
+            name = EXCLUDED.name,
+            category = COALESCE(EXCLUDED.category, dcinside_gallery.category),
+            manager_id = COALESCE(EXCLUDED.manager_id, dcinside_gallery.manager_id),
+            sub_manager_ids = COALESCE(EXCLUDED.sub_manager_ids, dcinside_gallery.sub_manager_ids),
+            member_count = COALESCE(EXCLUDED.member_count, dcinside_gallery.member_count),
+            subjects = COALESCE(EXCLUDED.subjects, dcinside_gallery.subjects),
+            created_at = COALESCE(EXCLUDED.created_at, dcinside_gallery.created_at),
+            rank = EXCLUDED.rank"#,
+        &[
+            &gallery.id,
+            &gallery.name,
+            &gallery.kind.name(),
+            &info.and_then(|i| i.category.as_ref()),
+            &info.and_then(|i| i.manager_id.as_ref()),
+            // galleries without a manager box leave the stored ids alone
+            &info
+                .map(|i| &i.sub_manager_ids)
+                .filter(|ids| !ids.is_empty()),
+            &info.and_then(|i| i.member_count).map(|c| c as i32),
+            &info.map(|i| &i.subjects).filter(|s| !s.is_empty()),
+            &info.and_then(|i| i.created_at),
+            // off the hot lists is no rank, rather than the last one
+            &info.and_then(|i| i.rank).map(|r| r as i32),
+        ],
     )?;
     Ok(())
 }
@@ -21,25 +48,32 @@ fn upsert_gallery(client: &mut Client, gallery: &Gallery) -> anyhow::Result<()>
 fn upsert_document(client: &mut Client, doc: &Document) -> anyhow::Result<()> {
     client.execute(
         r#"
-        INSERT INTO dcinside_documents 
+        INSERT INTO dcinside_document 
             (gallery_id, id, title, subject, 
             author_nickname, author_ip, author_id, 
             comment_count, like_count, view_count, 
             kind, is_recommend, created_at) 
         VALUES 
             ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
-        ON CONFLICT DO NOTHING"#,
+        ON CONFLICT (gallery_id, id) DO UPDATE SET
+            title = EXCLUDED.title,
+            subject = EXCLUDED.subject,
+            comment_count = EXCLUDED.comment_count,
+            like_count = EXCLUDED.like_count,
+            view_count = EXCLUDED.view_count,
+            is_recommend = EXCLUDED.is_recommend"#,
         &[
             &doc.gallery_id,
-            &(doc.id as u32),
+            // INT columns. u32 would be sent as an OID
+            &(doc.id as i32),
             &doc.title,
             &doc.subject,
             &doc.author.nickname,
             &doc.author.ip,
             &doc.author.id,
-            &doc.comment_count,
-            &doc.like_count,
-            &doc.view_count,
+            &(doc.comment_count as i32),
+            &(doc.like_count as i32),
+            &(doc.view_count as i32),
             &doc.kind.name(),
             &doc.is_recommend,
             &doc.created_at,
@@ -48,6 +82,116 @@ fn upsert_document(client: &mut Client, doc: &Document) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn apply_update(client: &mut Client, update: &DocumentUpdate) -> anyhow::Result<()> {
+    client.execute(
+        r#"
+        UPDATE dcinside_document SET
+            comment_count = $3, like_count = $4, view_count = $5
+        WHERE gallery_id = $1 AND id = $2"#,
+        &[
+            &update.gallery_id,
+            &(update.id as i32),
+            &(update.comment_count as i32),
+            &(update.like_count as i32),
+            &(update.view_count as i32),
+        ],
+    )?;
+    for comment in update.new_comments.iter() {
+        insert_comment(client, &update.gallery_id, update.id, comment)?;
+    }
+    Ok(())
+}
+
+fn insert_comment(
+    client: &mut Client,
+    gallery_id: &str,
+    document_id: usize,
+    comment: &Comment,
+) -> anyhow::Result<()> {
+    client.execute(
+        r#"
+        INSERT INTO dcinside_comment
+            (gallery_id, id, document_id, contents,
+            author_nickname, author_ip, author_id, created_at, deleted_at)
+        VALUES
+            ($1, $2, $3, $4, $5, $6, $7, COALESCE($8, NOW()), CASE WHEN $9 THEN NOW() END)
+        ON CONFLICT (gallery_id, id) DO UPDATE SET
+            deleted_at = COALESCE(dcinside_comment.deleted_at, EXCLUDED.deleted_at)"#,
+        &[
+            &gallery_id,
+            &(comment.id as i64),
+            &(document_id as i64),
+            // markup of dccons and voice comments is kept as is
+            &comment.content.text().unwrap_or(&comment.contents),
+            &comment.author.nickname,
+            &comment.author.ip,
+            &comment.author.id,
+            &comment.created_at,
+            &comment.deleted,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Deletions of documents never written, like the ids of gaps, are kept in
+/// `dcinside_document_deletion` too.
+fn mark_document_deleted(client: &mut Client, deletion: &DocumentDeletion) -> anyhow::Result<()> {
+    client.execute(
+        r#"
+        INSERT INTO dcinside_document_deletion (gallery_id, id, detected_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING"#,
+        &[
+            &deletion.gallery_id,
+            &(deletion.id as i32),
+            &deletion.detected_at,
+        ],
+    )?;
+    client.execute(
+        r#"
+        UPDATE dcinside_document SET deleted_at = $3
+        WHERE gallery_id = $1 AND id = $2 AND deleted_at IS NULL"#,
+        &[
+            &deletion.gallery_id,
+            &(deletion.id as i32),
+            &deletion.detected_at,
+        ],
+    )?;
+    Ok(())
+}
+
+fn mark_comment_deleted(client: &mut Client, deletion: &CommentDeletion) -> anyhow::Result<()> {
+    client.execute(
+        r#"
+        UPDATE dcinside_comment SET deleted_at = $3
+        WHERE gallery_id = $1 AND id = $2 AND deleted_at IS NULL"#,
+        &[
+            &deletion.gallery_id,
+            &(deletion.id as i64),
+            &deletion.detected_at,
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_ranks(client: &mut Client, snapshot: &RankSnapshot) -> anyhow::Result<()> {
+    for rank in snapshot.ranks.iter() {
+        client.execute(
+            r#"
+            INSERT INTO dcinside_gallery_rank (gallery_id, endpoint, rank, ranked_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING"#,
+            &[
+                &rank.gallery_id,
+                &snapshot.endpoint.name(),
+                &(rank.rank as i32),
+                &snapshot.ranked_at,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
 /*fn upsert_comment(client: &mut Client, doc: &Comment) -> anyhow::Result<()> {
     client.execute(r#"
         INSERT INTO dcinside_documents
@@ -76,21 +220,89 @@ pub fn subscribe(url: &str, subject: &str, consumer: &str) -> anyhow::Result<Con
     Ok(Consumer::create_or_open(nc, subject, consumer)?)
 }
 
+fn write_document(client: &mut Client, doc: &Document) -> anyhow::Result<()> {
+    upsert_gallery(client, &doc.gallery)?;
+    upsert_document(client, doc)?;
+    for comment in doc.comments.iter().flatten() {
+        insert_comment(client, &doc.gallery_id, doc.id, comment)?;
+    }
+    Ok(())
+}
+
+fn write_event(client: &mut Client, event: &DocumentEvent) -> anyhow::Result<()> {
+    match event {
+        DocumentEvent::Updated(update) => apply_update(client, update),
+        DocumentEvent::DocumentDeleted(deletion) => mark_document_deleted(client, deletion),
+        DocumentEvent::CommentDeleted(deletion) => mark_comment_deleted(client, deletion),
+    }
+}
+
+/// A bad event is logged and acked, so it neither stops the writer nor comes back.
+fn write_updates(mut consumer: Consumer, mut client: Client) -> anyhow::Result<()> {
+    loop {
+        let msg = consumer.pull()?;
+        let res = bincode::deserialize::<DocumentEvent>(&msg.data)
+            .map_err(anyhow::Error::from)
+            .and_then(|event| write_event(&mut client, &event));
+        if let Err(e) = res {
+            eprintln!("skip a document event: {}", e);
+        }
+        msg.ack()?;
+    }
+}
+
+/// Like `write_updates`, a bad snapshot is logged and acked.
+fn write_ranks(mut consumer: Consumer, mut client: Client) -> anyhow::Result<()> {
+    loop {
+        let msg = consumer.pull()?;
+        let res = bincode::deserialize::<RankSnapshot>(&msg.data)
+            .map_err(anyhow::Error::from)
+            .and_then(|snapshot| insert_ranks(&mut client, &snapshot));
+        if let Err(e) = res {
+            eprintln!("skip a rank snapshot: {}", e);
+        }
+        msg.ack()?;
+    }
+}
+
 fn main() -> anyhow::Result<()> {
     let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL");
     let nats_url = std::env::var("NATS_URL").expect("NATS_URL");
     let nats_subject =
         std::env::var("NATS_SUBJECT").unwrap_or_else(|_| "crawled.dcinside.documents".to_string());
 
+    let nats_update_subject = std::env::var("NATS_UPDATE_SUBJECT")
+        .unwrap_or_else(|_| "crawled.dcinside.document-updates".to_string());
+
+    let nats_rank_subject = std::env::var("NATS_RANK_SUBJECT")
+        .unwrap_or_else(|_| "crawled.dcinside.gallery-ranks".to_string());
+
     let mut db_conn = Client::connect(&db_url, NoTls)?;
 
+    let update_db_conn = Client::connect(&db_url, NoTls)?;
+    let update_consumer = subscribe(&nats_url, &nats_update_subject, "dcgle_document_writer")?;
+    std::thread::spawn(move || {
+        if let Err(e) = write_updates(update_consumer, update_db_conn) {
+            eprintln!("update writer stopped: {}", e);
+            std::process::exit(1);
+        }
+    });
+
+    let rank_db_conn = Client::connect(&db_url, NoTls)?;
+    let rank_consumer = subscribe(&nats_url, &nats_rank_subject, "dcgle_document_writer")?;
+    std::thread::spawn(move || {
+        if let Err(e) = write_ranks(rank_consumer, rank_db_conn) {
+            eprintln!("rank writer stopped: {}", e);
+            std::process::exit(1);
+        }
+    });
+
     let mut consumer = subscribe(&nats_url, &nats_subject, "dcgle_document_writer")?;
 
     loop {
         let msg = consumer.pull()?;
         let doc: Document = bincode::deserialize(&msg.data)?;
-        upsert_gallery(&mut db_conn, &doc.gallery)?;
-        upsert_document(&mut db_conn, &doc)?;
+        write_document(&mut db_conn, &doc)?;
         msg.ack()?;
     }
     Ok(())
@@ -99,4 +311,215 @@ fn main() -> anyhow::Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::{DateTime, Utc};
+
+    /// A database migrated with dcgle-web/dcgle-model/migrations at `DATABASE_URL`.
+    /// Tests needing one pass without it.
+    fn setup(gallery_id: &str) -> Option<Client> {
+        let url = match std::env::var("DATABASE_URL") {
+            Ok(url) => url,
+            Err(_) => {
+                eprintln!("DATABASE_URL is not set. skip");
+                return None;
+            }
+        };
+        let mut client = Client::connect(&url, NoTls).unwrap();
+        for table in &[
+            "dcinside_document",
+            "dcinside_comment",
+            "dcinside_document_deletion",
+        ] {
+            client
+                .execute(
+                    format!("DELETE FROM {} WHERE gallery_id = $1", table).as_str(),
+                    &[&gallery_id],
+                )
+                .unwrap();
+        }
+        Some(client)
+    }
+
+    fn document(gallery_id: &str, counts: u32) -> Document {
+        Document {
+            gallery: Gallery {
+                id: gallery_id.to_string(),
+                name: "writer test".to_string(),
+                kind: GalleryKind::Major,
+                info: None,
+            },
+            gallery_id: gallery_id.to_string(),
+            id: 1595404,
+            title: "title".to_string(),
+            subject: None,
+            author: User {
+                id: None,
+                ip: Some("1.2".to_string()),
+                nickname: "ㅇㅇ".to_string(),
+                kind: UserKind::Dynamic,
+            },
+            comment_count: counts,
+            like_count: counts,
+            view_count: counts,
+            kind: DocumentKind::Text,
+            is_recommend: false,
+            created_at: Utc::now(),
+            comments: None,
+            body: None,
+        }
+    }
+
+    fn counts(client: &mut Client, gallery_id: &str) -> (i32, i32, i32) {
+        let row = client
+            .query_one(
+                "SELECT comment_count, like_count, view_count FROM dcinside_document
+                WHERE gallery_id = $1 AND id = 1595404",
+                &[&gallery_id],
+            )
+            .unwrap();
+        (row.get(0), row.get(1), row.get(2))
+    }
+
+    #[test]
+    fn it_upserts_and_updates_documents() {
+        let gallery_id = "writer-test-upsert";
+        let mut client = match setup(gallery_id) {
+            Some(client) => client,
+            None => return,
+        };
+        upsert_document(&mut client, &document(gallery_id, 1)).unwrap();
+        upsert_document(&mut client, &document(gallery_id, 2)).unwrap();
+        assert_eq!(counts(&mut client, gallery_id), (2, 2, 2));
+        apply_update(
+            &mut client,
+            &DocumentUpdate {
+                gallery_id: gallery_id.to_string(),
+                id: 1595404,
+                comment_count: 3,
+                like_count: 4,
+                view_count: 5,
+                comment_count_delta: 1,
+                like_count_delta: 2,
+                view_count_delta: 3,
+                new_comments: Vec::new(),
+                deleted_comment_ids: Vec::new(),
+                revisited_at: Utc::now(),
+            },
+        )
+        .unwrap();
+        assert_eq!(counts(&mut client, gallery_id), (3, 4, 5));
+    }
+
+    #[test]
+    fn it_marks_deleted_documents() {
+        let gallery_id = "writer-test-deletion";
+        let mut client = match setup(gallery_id) {
+            Some(client) => client,
+            None => return,
+        };
+        upsert_document(&mut client, &document(gallery_id, 1)).unwrap();
+        let deletion = DocumentDeletion {
+            gallery_id: gallery_id.to_string(),
+            id: 1595404,
+            detected_at: Utc::now(),
+        };
+        write_event(&mut client, &DocumentEvent::DocumentDeleted(deletion)).unwrap();
+        let deleted_at: Option<DateTime<Utc>> = client
+            .query_one(
+                "SELECT deleted_at FROM dcinside_document WHERE gallery_id = $1",
+                &[&gallery_id],
+            )
+            .unwrap()
+            .get(0);
+        assert!(deleted_at.is_some());
+    }
+
+    #[test]
+    fn it_writes_comments_of_documents() {
+        let gallery_id = "writer-test-comments";
+        let mut client = match setup(gallery_id) {
+            Some(client) => client,
+            None => return,
+        };
+        let mut doc = document(gallery_id, 1);
+        doc.comments = Some(vec![Comment {
+            id: 1,
+            author: User {
+                ip: None,
+                nickname: "ㅇㅇ".to_string(),
+                id: Some("writer".to_string()),
+                kind: UserKind::Static,
+            },
+            depth: 0,
+            kind: CommentKind::Text,
+            content: Default::default(),
+            mentioned_ids: Vec::new(),
+            contents: "comment".to_string(),
+            parent_id: None,
+            created_at: Some(Utc::now()),
+            deleted: false,
+        }]);
+        write_document(&mut client, &doc).unwrap();
+        let deletion = CommentDeletion {
+            gallery_id: gallery_id.to_string(),
+            document_id: doc.id,
+            id: 1,
+            detected_at: Utc::now(),
+        };
+        write_event(&mut client, &DocumentEvent::CommentDeleted(deletion)).unwrap();
+        let deleted_at: Option<DateTime<Utc>> = client
+            .query_one(
+                "SELECT deleted_at FROM dcinside_comment WHERE gallery_id = $1 AND id = 1",
+                &[&gallery_id],
+            )
+            .unwrap()
+            .get(0);
+        assert!(deleted_at.is_some());
+    }
+
+    #[test]
+    fn it_keeps_deletions_of_unseen_documents() {
+        let gallery_id = "writer-test-gap";
+        let mut client = match setup(gallery_id) {
+            Some(client) => client,
+            None => return,
+        };
+        let deletion = DocumentDeletion {
+            gallery_id: gallery_id.to_string(),
+            id: 1595405,
+            detected_at: Utc::now(),
+        };
+        write_event(&mut client, &DocumentEvent::DocumentDeleted(deletion)).unwrap();
+        let row = client
+            .query_one(
+                "SELECT id FROM dcinside_document_deletion WHERE gallery_id = $1",
+                &[&gallery_id],
+            )
+            .unwrap();
+        assert_eq!(row.get::<_, i32>(0), 1595405);
+    }
+
+    #[test]
+    fn it_clears_ranks_of_galleries_off_the_lists() {
+        let gallery_id = "writer-test-rank";
+        let mut client = match setup(gallery_id) {
+            Some(client) => client,
+            None => return,
+        };
+        let mut gallery = document(gallery_id, 1).gallery;
+        gallery.info = Some(GalleryInfo {
+            rank: Some(3),
+            ..Default::default()
+        });
+        upsert_gallery(&mut client, &gallery).unwrap();
+        gallery.info = None;
+        upsert_gallery(&mut client, &gallery).unwrap();
+        let rank: Option<i32> = client
+            .query_one(
+                "SELECT rank FROM dcinside_gallery WHERE id = $1",
+                &[&gallery_id],
+            )
+            .unwrap()
+            .get(0);
+        assert_eq!(rank, None);
+    }
 }
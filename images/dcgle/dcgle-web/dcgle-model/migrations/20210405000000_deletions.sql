@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE dcinside_document ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE dcinside_comment ADD COLUMN deleted_at TIMESTAMPTZ;
@@ -0,0 +1,11 @@
+-- Add migration script here
+ALTER TABLE dcinside_gallery ADD COLUMN category TEXT;
+ALTER TABLE dcinside_gallery ADD COLUMN manager_id TEXT;
+ALTER TABLE dcinside_gallery ADD COLUMN sub_manager_ids TEXT[];
+ALTER TABLE dcinside_gallery ADD COLUMN member_count INT;
+ALTER TABLE dcinside_gallery ADD COLUMN subjects TEXT[];
+ALTER TABLE dcinside_gallery ADD COLUMN created_at TIMESTAMPTZ;
+ALTER TABLE dcinside_gallery ADD COLUMN rank INT;
+
+CREATE INDEX ON dcinside_gallery (category);
+CREATE INDEX ON dcinside_gallery (manager_id);
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE dcinside_gallery_rank (
+  gallery_id TEXT NOT NULL,
+  endpoint TEXT NOT NULL,
+  rank INT NOT NULL,
+  ranked_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (gallery_id, endpoint, ranked_at)
+);
+
+CREATE INDEX ON dcinside_gallery_rank (endpoint, ranked_at);
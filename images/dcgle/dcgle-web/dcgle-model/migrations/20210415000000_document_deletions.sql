@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE dcinside_document_deletion (
+  gallery_id TEXT NOT NULL,
+  id INT NOT NULL,
+  detected_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (gallery_id, id)
+);
@@ -11,11 +11,26 @@ pub type PoolOptions = sqlx::postgres::PgPoolOptions;
 
 type DateTime = chrono::DateTime<chrono::Utc>;
 
-#[derive(sqlx::FromRow, Debug, Deserialize, Serialize, PartialEq, SimpleObject)]
+#[derive(sqlx::FromRow, Debug, Default, Deserialize, Serialize, PartialEq, SimpleObject)]
 pub struct Gallery {
     pub id: String,
     pub name: String,
     pub kind: String,
+    pub category: Option<String>,
+    pub manager_id: Option<String>,
+    pub sub_manager_ids: Option<Vec<String>>,
+    pub member_count: Option<i32>,
+    /// 말머리
+    pub subjects: Option<Vec<String>>,
+    pub created_at: Option<DateTime>,
+    pub rank: Option<i32>,
+}
+
+#[derive(sqlx::FromRow, Debug, Deserialize, Serialize, PartialEq, SimpleObject)]
+pub struct GalleryCategory {
+    pub category: String,
+    pub gallery_count: i64,
+    pub member_count: i64,
 }
 
 #[derive(sqlx::FromRow, Debug, Deserialize, Serialize, PartialEq, SimpleObject)]
@@ -80,6 +95,34 @@ impl State {
             .await?;
         Ok(res)
     }
+    pub async fn get_galleries_by_category(&self, category: &str) -> Result<Vec<Gallery>, Error> {
+        let res = sqlx::query_as::<_, Gallery>("SELECT * FROM dcinside_gallery WHERE category = $1 ORDER BY member_count DESC NULLS LAST LIMIT 100")
+            .bind(category)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(res)
+    }
+    pub async fn get_galleries_by_manager(&self, manager_id: &str) -> Result<Vec<Gallery>, Error> {
+        let res = sqlx::query_as::<_, Gallery>("SELECT * FROM dcinside_gallery WHERE manager_id = $1 OR $1 = ANY(sub_manager_ids) LIMIT 100")
+            .bind(manager_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(res)
+    }
+    pub async fn get_gallery_categories(&self) -> Result<Vec<GalleryCategory>, Error> {
+        let res = sqlx::query_as::<_, GalleryCategory>(r#"
+            SELECT 
+                category, 
+                COUNT(*) AS gallery_count, 
+                COALESCE(SUM(member_count), 0) AS member_count
+            FROM dcinside_gallery 
+            WHERE category IS NOT NULL 
+            GROUP BY category 
+            ORDER BY gallery_count DESC"#)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(res)
+    }
 
 
 
@@ -219,15 +262,26 @@ impl Query {
         ctx: &Context<'_>,
         #[graphql(desc = "Search term of galelry")] name_part: Option<String>,
         id: Option<String>,
+        category: Option<String>,
+        #[graphql(desc = "Manager or sub manager id")] manager_id: Option<String>,
         ) -> Result<Vec<Gallery>, GraphqlError> {
         let state: &State = ctx.data::<State>()?;
-        Ok(match (id, name_part) {
-            (Some(id), None) => state.get_gallery_by_id(&id).await?,
-            (None, Some(name_part)) => state.get_gallery_by_name_part(&name_part).await?,
+        Ok(match (id, name_part, category, manager_id) {
+            (Some(id), None, None, None) => state.get_gallery_by_id(&id).await?,
+            (None, Some(name_part), None, None) => state.get_gallery_by_name_part(&name_part).await?,
+            (None, None, Some(category), None) => state.get_galleries_by_category(&category).await?,
+            (None, None, None, Some(manager_id)) => state.get_galleries_by_manager(&manager_id).await?,
             //(None, None) => state.get_gallery().await?,
-            _ => Err(Error::BadRequest("galleries", "valid form: { ONE_OF(id, name_part) }"))?,
+            _ => Err(Error::BadRequest("galleries", "valid form: { ONE_OF(id, name_part, category, manager_id) }"))?,
         })
     }
+    async fn gallery_categories(
+        &self,
+        ctx: &Context<'_>,
+        ) -> Result<Vec<GalleryCategory>, GraphqlError> {
+        let state: &State = ctx.data::<State>()?;
+        Ok(state.get_gallery_categories().await?)
+    }
     async fn documents(
         &self,
         ctx: &Context<'_>,
@@ -274,11 +328,16 @@ pub mod tests {
                 id: "gallery_id1".to_string(),
                 name: "gallery_name1".to_string(),
                 kind: "gallery_kind1".to_string(),
+                category: Some("category1".to_string()),
+                manager_id: Some("manager1".to_string()),
+                sub_manager_ids: Some(vec!["sub_manager1".to_string()]),
+                ..Default::default()
             },
             Gallery{ 
                 id: "gallery_id2".to_string(),
                 name: "gallery_name2".to_string(),
                 kind: "gallery_kind2".to_string(),
+                ..Default::default()
             }
         ];
         let docs = vec![
@@ -321,9 +380,9 @@ pub mod tests {
         for g in galleries.iter() {
             sqlx::query!(
                 r#"
-                INSERT INTO dcinside_gallery ( id, name, kind )
-                VALUES ( $1, $2, $3 )"#,
-                g.id, g.name, g.kind
+                INSERT INTO dcinside_gallery ( id, name, kind, category, manager_id, sub_manager_ids )
+                VALUES ( $1, $2, $3, $4, $5, $6 )"#,
+                g.id, g.name, g.kind, g.category, g.manager_id, g.sub_manager_ids.as_deref()
             )
                 .execute(&pool)
                 .await.unwrap();
@@ -373,4 +432,16 @@ pub mod tests {
         let res = state.get_gallery().await.unwrap();
         assert_eq!(res, galleries);
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn get_galleries_by_category_and_manager() {
+        let (state, galleries, _docs) = setup().await;
+        let res = state.get_galleries_by_category("category1").await.unwrap();
+        assert_eq!(res, galleries[..1]);
+        let res = state.get_galleries_by_manager("sub_manager1").await.unwrap();
+        assert_eq!(res, galleries[..1]);
+        let res = state.get_gallery_categories().await.unwrap();
+        assert_eq!(res, vec![GalleryCategory{ category: "category1".to_string(), gallery_count: 1, member_count: 0 }]);
+    }
 }
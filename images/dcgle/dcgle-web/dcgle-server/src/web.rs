@@ -99,11 +99,13 @@ mod tests {
                 id: "gallery_id1".to_string(),
                 name: "gallery_name1".to_string(),
                 kind: "gallery_kind1".to_string(),
+                ..Default::default()
             },
             Gallery{ 
                 id: "gallery_id2".to_string(),
                 name: "gallery_name2".to_string(),
                 kind: "gallery_kind2".to_string(),
+                ..Default::default()
             }
         ];
         let docs = vec![
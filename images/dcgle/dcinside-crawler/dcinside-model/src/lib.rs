@@ -1,5 +1,6 @@
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
+use std::collections::HashMap;
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Copy, Clone)]
 pub enum DocumentKind {
@@ -48,6 +49,22 @@ pub struct Gallery {
     pub id: String,
     pub name: String,
     pub kind: GalleryKind,
+    #[serde(default)]
+    pub info: Option<GalleryInfo>,
+}
+
+/// What a gallery page and the gallery directory tell about a gallery.
+/// Fields are left empty when the source at hand doesn't show them.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct GalleryInfo {
+    pub category: Option<String>,
+    pub manager_id: Option<String>,
+    pub sub_manager_ids: Vec<String>,
+    pub member_count: Option<u32>,
+    /// 말머리 the gallery offers, without `전체`.
+    pub subjects: Vec<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub rank: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -67,7 +84,61 @@ pub struct Document {
     pub created_at: DateTime<Utc>,
 
     pub comments: Option<Vec<Comment>>,
-    pub body: Option<String>,
+    pub body: Option<DocumentBody>,
+}
+
+/// A document body read into what it shows.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct DocumentBody {
+    /// Text without markup. Paragraphs and line breaks become newlines.
+    pub text: String,
+    /// Images in the order they appear.
+    pub images: Vec<BodyImage>,
+    pub dccons: Vec<Dccon>,
+    pub videos: Vec<BodyVideo>,
+    /// Outbound links in the order they appear, without duplicates.
+    pub links: Vec<String>,
+    /// Text of quoted blocks.
+    pub quotes: Vec<String>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// The markup the rest is read from, when kept.
+    pub html: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct BodyImage {
+    /// The resized image the body shows.
+    pub thumbnail_url: String,
+    /// The full size image it opens, if any.
+    pub original_url: Option<String>,
+}
+
+/// A file of the attachment box under a document.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Attachment {
+    pub name: String,
+    pub download_url: String,
+    /// The original url of the body image it is shown as, if any.
+    pub image_url: Option<String>,
+    /// Size and hashes are filled once the file is downloaded.
+    pub size: Option<u64>,
+    /// Hex sha1 of the content, its key in the image store.
+    pub sha1: Option<String>,
+    /// Difference hash of images. Near duplicates are a few bits apart.
+    pub perceptual_hash: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Dccon {
+    pub url: String,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct BodyVideo {
+    pub url: String,
+    pub youtube_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
@@ -120,13 +191,178 @@ impl Default for CommentKind {
     }
 }
 
+/// What a comment shows, read from its raw contents.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub enum CommentContent {
+    Text {
+        /// Contents without markup.
+        text: String,
+        /// Nicknames written as `@nickname`.
+        mentions: Vec<String>,
+    },
+    Con {
+        /// Package of the dccon, when the markup shows it.
+        package_id: Option<String>,
+        /// `no` of the dccon image.
+        image_id: String,
+        url: String,
+        title: Option<String>,
+    },
+    Voice {
+        id: String,
+        url: String,
+    },
+}
+impl CommentContent {
+    pub const fn kind(&self) -> CommentKind {
+        match self {
+            Self::Text { .. } => CommentKind::Text,
+            Self::Con { .. } => CommentKind::Con,
+            Self::Voice { .. } => CommentKind::Voice,
+        }
+    }
+    /// Text of a text comment.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Self::Text { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+}
+impl Default for CommentContent {
+    fn default() -> Self {
+        Self::Text {
+            text: String::new(),
+            mentions: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Comment {
     pub id: usize,
     pub author: User,
     pub depth: usize,
+    /// Contents as served, markup included.
     pub contents: String,
     pub kind: CommentKind,
+    #[serde(default)]
+    pub content: CommentContent,
+    /// The root comment a reply is written under.
     pub parent_id: Option<usize>,
     pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub deleted: bool,
+    /// Earlier comments by the nicknames the comment mentions.
+    #[serde(default)]
+    pub mentioned_ids: Vec<usize>,
+}
+
+/// A root comment and the replies under it.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct CommentThread {
+    pub root_id: usize,
+    /// None when the root isn't among the comments, like a root removed for good.
+    pub root: Option<Comment>,
+    /// Replies in the order they were written.
+    pub replies: Vec<Comment>,
+}
+impl CommentThread {
+    /// Groups comments by their parent. Threads are in the order their first comment comes in.
+    pub fn from_comments(comments: Vec<Comment>) -> Vec<CommentThread> {
+        let mut threads: Vec<CommentThread> = Vec::new();
+        let mut positions = HashMap::new();
+        for comment in comments {
+            let root_id = comment.parent_id.unwrap_or(comment.id);
+            let position = *positions.entry(root_id).or_insert_with(|| {
+                threads.push(CommentThread {
+                    root_id,
+                    root: None,
+                    replies: Vec::new(),
+                });
+                threads.len() - 1
+            });
+            let thread = &mut threads[position];
+            if comment.parent_id.is_some() {
+                thread.replies.push(comment);
+            } else {
+                thread.root = Some(comment);
+            }
+        }
+        for thread in threads.iter_mut() {
+            thread.replies.sort_by_key(|reply| reply.id);
+        }
+        threads
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct DocumentUpdate {
+    pub gallery_id: String,
+    pub id: usize,
+    pub comment_count: u32,
+    pub like_count: u32,
+    pub view_count: u32,
+    pub comment_count_delta: i64,
+    pub like_count_delta: i64,
+    pub view_count_delta: i64,
+    pub new_comments: Vec<Comment>,
+    /// Comments seen before that are deleted now.
+    pub deleted_comment_ids: Vec<usize>,
+    pub revisited_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct DocumentDeletion {
+    pub gallery_id: String,
+    pub id: usize,
+    pub detected_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CommentDeletion {
+    pub gallery_id: String,
+    pub document_id: usize,
+    pub id: usize,
+    pub detected_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum DocumentEvent {
+    Updated(DocumentUpdate),
+    DocumentDeleted(DocumentDeletion),
+    CommentDeleted(CommentDeletion),
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Copy, Clone)]
+pub enum RankingEndpoint {
+    RealtimeMajor,
+    RealtimeMinor,
+    RealtimeMini,
+    WeeklyMajor,
+}
+impl RankingEndpoint {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::RealtimeMajor => "realtime_major",
+            Self::RealtimeMinor => "realtime_minor",
+            Self::RealtimeMini => "realtime_mini",
+            Self::WeeklyMajor => "weekly_major",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct GalleryRank {
+    pub gallery_id: String,
+    pub name: String,
+    pub rank: u32,
+}
+
+/// One read of a ranking endpoint.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RankSnapshot {
+    pub endpoint: RankingEndpoint,
+    pub ranked_at: DateTime<Utc>,
+    pub ranks: Vec<GalleryRank>,
 }
@@ -0,0 +1,135 @@
+use crate::parse::{DocumentIndex, GalleryIndex};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Where a backfill stops. Either bound ends it; with neither it walks down to the
+/// first document of the gallery.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BackfillTarget {
+    #[serde(default)]
+    pub until_id: Option<usize>,
+    #[serde(default)]
+    pub until_date: Option<DateTime<Utc>>,
+}
+impl BackfillTarget {
+    pub fn reached(&self, index: &DocumentIndex) -> bool {
+        matches!(self.until_id, Some(id) if index.id <= id)
+            || matches!(self.until_date, Some(date) if index.created_at < date)
+    }
+}
+
+/// How far a backfill got. New documents push old ones to later pages, so the walk
+/// resumes at `page` and skips everything not below `last_id`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BackfillCheckpoint {
+    /// Listing page to fetch next.
+    pub page: usize,
+    /// Oldest document walked so far.
+    pub last_id: Option<usize>,
+    pub published: usize,
+    pub done: bool,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// A walk over the whole history of a gallery, from a page or a document id down to
+/// a target. Unlike live crawls it is not bound by `MAX_LIST_PAGE`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackfillJob {
+    pub gallery: GalleryIndex,
+    /// Documents from this id down are walked. `None` starts at the newest one.
+    #[serde(default)]
+    pub start_id: Option<usize>,
+    #[serde(default)]
+    pub target: BackfillTarget,
+    pub checkpoint: BackfillCheckpoint,
+    pub created_at: DateTime<Utc>,
+}
+impl BackfillJob {
+    pub fn new(gallery: GalleryIndex) -> Self {
+        BackfillJob {
+            gallery,
+            start_id: None,
+            target: BackfillTarget::default(),
+            checkpoint: BackfillCheckpoint {
+                page: 1,
+                ..Default::default()
+            },
+            created_at: Utc::now(),
+        }
+    }
+    pub fn start_page(mut self, page: usize) -> Self {
+        self.checkpoint.page = page.max(1);
+        self
+    }
+    pub fn start_id(mut self, id: usize) -> Self {
+        self.start_id = Some(id);
+        self
+    }
+    pub fn until_id(mut self, id: usize) -> Self {
+        self.target.until_id = Some(id);
+        self
+    }
+    pub fn until_date(mut self, date: DateTime<Utc>) -> Self {
+        self.target.until_date = Some(date);
+        self
+    }
+    /// Documents of a listing page still to walk: below the checkpoint and above the target.
+    pub fn wants(&self, index: &DocumentIndex) -> bool {
+        let below = match (self.checkpoint.last_id, self.start_id) {
+            (Some(last_id), _) => index.id < last_id,
+            (None, Some(start_id)) => index.id <= start_id,
+            (None, None) => true,
+        };
+        below && !self.target.reached(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dcinside_model::{DocumentKind, GalleryKind, User, UserKind};
+
+    fn index(id: usize, created_at: DateTime<Utc>) -> DocumentIndex {
+        DocumentIndex {
+            gallery_id: "programming".to_string(),
+            id,
+            title: String::new(),
+            subject: None,
+            author: User {
+                ip: None,
+                nickname: String::new(),
+                id: None,
+                kind: UserKind::Unknown,
+            },
+            comment_count: 0,
+            like_count: 0,
+            view_count: 0,
+            kind: DocumentKind::Text,
+            is_recommend: false,
+            created_at,
+        }
+    }
+
+    #[test]
+    fn it_selects_documents_between_checkpoint_and_target() {
+        let now = Utc::now();
+        let gallery = GalleryIndex {
+            id: "programming".to_string(),
+            name: "프로그래밍".to_string(),
+            kind: GalleryKind::Major,
+            rank: None,
+        };
+        let mut job = BackfillJob::new(gallery)
+            .start_id(100)
+            .until_id(10)
+            .until_date(now - chrono::Duration::days(1));
+        assert!(job.wants(&index(100, now)));
+        assert!(!job.wants(&index(101, now)));
+        assert!(!job.wants(&index(10, now)));
+        assert!(!job.wants(&index(50, now - chrono::Duration::days(2))));
+        job.checkpoint.last_id = Some(50);
+        assert!(!job.wants(&index(50, now)));
+        assert!(job.wants(&index(49, now)));
+    }
+}
@@ -2,13 +2,16 @@ use actix_web::{
     error::ResponseError, get, http::StatusCode, post, web, App, HttpResponse, HttpServer,
     Responder,
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use dcinside_crawler::error::*;
 use err_derive::Error;
 use std::convert::TryInto;
 
+use dcinside_crawler::backfill::BackfillJob;
 use dcinside_crawler::crawler::Crawler;
 use dcinside_crawler::model::*;
 use dcinside_crawler::parse::*;
@@ -29,11 +32,13 @@ pub enum LiveDirectoryError {
     Sled(#[source] sled::Error),
     #[error(display = "not found")]
     NotFound,
+    #[error(display = "backfills are not enabled")]
+    BackfillDisabled,
 }
 impl ResponseError for LiveDirectoryError {
     fn status_code(&self) -> StatusCode {
         match *self {
-            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::NotFound | Self::BackfillDisabled => StatusCode::NOT_FOUND,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -63,6 +68,13 @@ struct State {
     min_wait_seconds_per_gallery: usize,
     publish_duration_estimate_weight1: f64,
     publish_duration_estimate_weight2: f64,
+    block_pause_seconds: i64,
+    paused_parts: Arc<Mutex<HashMap<u64, DateTime<Utc>>>>,
+    rank_history: Option<sled::Tree>,
+    rank_retention: chrono::Duration,
+    nats_conn: Option<nats::Connection>,
+    nats_rank_subject: String,
+    backfill_db: Option<sled::Tree>,
 }
 
 impl State {
@@ -78,6 +90,13 @@ impl State {
             min_wait_seconds_per_gallery: 3600 * 3,
             publish_duration_estimate_weight1: 0.0999,
             publish_duration_estimate_weight2: 0.0001,
+            block_pause_seconds: 600,
+            paused_parts: Arc::new(Mutex::new(HashMap::new())),
+            rank_history: None,
+            rank_retention: chrono::Duration::days(7),
+            nats_conn: None,
+            nats_rank_subject: "crawled.dcinside.gallery-ranks".to_string(),
+            backfill_db: None,
         }
     }
     fn docs_per_crawl(mut self, v: usize) -> Self {
@@ -96,6 +115,27 @@ impl State {
         self.publish_duration_estimate_weight2 = v;
         self
     }
+    fn block_pause_seconds(mut self, v: i64) -> Self {
+        self.block_pause_seconds = v;
+        self
+    }
+    /// Keeps every rank read in `tree` for `retention`.
+    fn with_rank_history(mut self, tree: sled::Tree, retention: chrono::Duration) -> Self {
+        self.rank_history = Some(tree);
+        self.rank_retention = retention;
+        self
+    }
+    /// Publishes every read of the ranking endpoints to `subject`.
+    fn with_nats(mut self, conn: nats::Connection, subject: String) -> Self {
+        self.nats_conn = Some(conn);
+        self.nats_rank_subject = subject;
+        self
+    }
+    /// Keeps backfill jobs and their checkpoints in `tree`, one per gallery.
+    fn with_backfills(mut self, tree: sled::Tree) -> Self {
+        self.backfill_db = Some(tree);
+        self
+    }
     fn with_db(db: sled::Tree, gallery_kind: GalleryKind, metrics: Metrics) -> Self {
         State {
             crawler: Crawler::new(),
@@ -106,6 +146,13 @@ impl State {
             min_wait_seconds_per_gallery: 3600 * 3,
             publish_duration_estimate_weight1: 0.0999,
             publish_duration_estimate_weight2: 0.0001,
+            block_pause_seconds: 600,
+            paused_parts: Arc::new(Mutex::new(HashMap::new())),
+            rank_history: None,
+            rank_retention: chrono::Duration::days(7),
+            nats_conn: None,
+            nats_rank_subject: "crawled.dcinside.gallery-ranks".to_string(),
+            backfill_db: None,
         }
     }
     async fn update(&self) -> Result<(), LiveDirectoryError> {
@@ -113,20 +160,19 @@ impl State {
         let hot_galleries = match self.gallery_kind {
             GalleryKind::Major => self.crawler.realtime_hot_galleries().await?,
             GalleryKind::Minor => self.crawler.realtime_hot_minor_galleries().await?,
-            GalleryKind::Mini => panic!("mini gallery kind not supported yet"),
+            GalleryKind::Mini => self.crawler.realtime_hot_mini_galleries().await?,
         };
+        self.record_ranks(
+            match self.gallery_kind {
+                GalleryKind::Major => RankingEndpoint::RealtimeMajor,
+                GalleryKind::Minor => RankingEndpoint::RealtimeMinor,
+                GalleryKind::Mini => RankingEndpoint::RealtimeMini,
+            },
+            &hot_galleries,
+            now,
+        )?;
         for index in hot_galleries {
-            let new_state = GalleryState {
-                index,
-                last_ranked: now,
-                last_crawled_at: None,
-                last_published_at: None,
-                last_crawled_document_id: None,
-                visible: true,
-                last_error: None,
-                publish_duration_in_seconds: Some(0.0),
-                registered_at: Some(Utc::now()),
-            };
+            let new_state = GalleryState::new(index, now);
             self.gallery_db.fetch_and_update(
                 new_state.index.id.clone().as_bytes(),
                 move |old| {
@@ -155,31 +201,147 @@ impl State {
         }
         let weekly_hot_galleries = match self.gallery_kind {
             GalleryKind::Major => self.crawler.weekly_hot_galleries().await?,
-            GalleryKind::Minor => Vec::new(),
-            GalleryKind::Mini => panic!("mini gallery kind not supported yet"),
+            GalleryKind::Minor | GalleryKind::Mini => Vec::new(),
         };
+        if !weekly_hot_galleries.is_empty() {
+            self.record_ranks(RankingEndpoint::WeeklyMajor, &weekly_hot_galleries, now)?;
+        }
         for index in weekly_hot_galleries {
             self.gallery_db
                 .fetch_and_update(index.id.clone().as_bytes(), move |old| {
                     Some(match old {
                         Some(bytes) => bytes.to_vec(),
+                        None => {
+                            let new_state = GalleryState::new(index.clone(), now);
+                            serde_json::to_vec(&new_state).unwrap()
+                        }
+                    })
+                })?;
+        }
+        self.metrics
+            .gallery_total
+            .set(self.gallery_db.len().try_into().unwrap());
+        Ok(())
+    }
+    /// Stores a read of a ranking endpoint into the rank history and publishes it.
+    /// Galleries without a rank take their position in the list.
+    fn record_ranks(
+        &self,
+        endpoint: RankingEndpoint,
+        galleries: &[GalleryIndex],
+        now: DateTime<Utc>,
+    ) -> Result<(), LiveDirectoryError> {
+        let snapshot = RankSnapshot {
+            endpoint,
+            ranked_at: now,
+            ranks: galleries
+                .iter()
+                .enumerate()
+                .map(|(i, g)| GalleryRank {
+                    gallery_id: g.id.clone(),
+                    name: g.name.clone(),
+                    rank: g.rank.unwrap_or(i + 1) as u32,
+                })
+                .collect(),
+        };
+        if let Some(tree) = &self.rank_history {
+            let cutoff = now - self.rank_retention;
+            for rank in snapshot.ranks.iter() {
+                let prefix = rank_key_prefix(&rank.gallery_id, endpoint);
+                let point = RankPoint {
+                    endpoint,
+                    rank: rank.rank,
+                    ranked_at: now,
+                };
+                tree.insert(rank_key(&prefix, now), serde_json::to_vec(&point).unwrap())?;
+                for old in tree.range(prefix.clone()..rank_key(&prefix, cutoff)) {
+                    tree.remove(old?.0)?;
+                }
+            }
+        }
+        if let Some(conn) = &self.nats_conn {
+            let res = bincode::serialize(&snapshot)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| {
+                    conn.publish(&self.nats_rank_subject, &bytes)
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = res {
+                error!("fail to publish ranks of {}: {}", endpoint.name(), e);
+            }
+        }
+        Ok(())
+    }
+    /// Rank reads of a gallery kept in the history, ordered by endpoint and time.
+    fn rank_history(
+        &self,
+        gallery_id: &str,
+        endpoint: Option<&str>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<RankPoint>, LiveDirectoryError> {
+        let tree = match &self.rank_history {
+            Some(tree) => tree,
+            None => return Ok(Vec::new()),
+        };
+        let mut points = Vec::new();
+        for res in tree.scan_prefix(format!("{}\0", gallery_id).as_bytes()) {
+            let (_, bytes) = res?;
+            let point = match serde_json::from_slice::<RankPoint>(&bytes) {
+                Ok(point) => point,
+                Err(e) => {
+                    error!("fail to parse rank history of {}: {}", gallery_id, e);
+                    continue;
+                }
+            };
+            if matches!(endpoint, Some(e) if e != point.endpoint.name())
+                || matches!(since, Some(t) if point.ranked_at < t)
+            {
+                continue;
+            }
+            points.push(point);
+        }
+        Ok(points)
+    }
+    /// Registers every gallery of the directory and refreshes what it says about
+    /// the known ones. Crawl states are left alone.
+    async fn update_catalog(&self) -> Result<(), LiveDirectoryError> {
+        let now = Utc::now();
+        let entries = self.crawler.catalog(self.gallery_kind).await?;
+        let mut registered = 0usize;
+        for entry in entries {
+            let old = self.gallery_db.fetch_and_update(
+                entry.index.id.clone().as_bytes(),
+                move |old| {
+                    Some(match old {
+                        Some(bytes) => match serde_json::from_slice::<GalleryState>(bytes) {
+                            Ok(mut old_state) => {
+                                old_state.index.name = entry.index.name.clone();
+                                old_state.catalog = Some(entry.info.clone());
+                                serde_json::to_vec(&old_state).unwrap()
+                            }
+                            Err(e) => {
+                                error!(
+                                    "fail to parse sled tree data {} with error {}",
+                                    &entry.index.id, e
+                                );
+                                bytes.to_vec()
+                            }
+                        },
                         None => {
                             let new_state = GalleryState {
-                                index: index.clone(),
-                                last_ranked: now,
-                                last_crawled_at: None,
-                                last_published_at: None,
-                                last_crawled_document_id: None,
-                                visible: true,
-                                last_error: None,
-                                publish_duration_in_seconds: Some(0.0),
-                                registered_at: Some(Utc::now()),
+                                catalog: Some(entry.info.clone()),
+                                ..GalleryState::new(entry.index.clone(), now)
                             };
                             serde_json::to_vec(&new_state).unwrap()
                         }
                     })
-                })?;
+                },
+            )?;
+            if old.is_none() {
+                registered += 1;
+            }
         }
+        info!("{} galleries registered from catalog", registered);
         self.metrics
             .gallery_total
             .set(self.gallery_db.len().try_into().unwrap());
@@ -314,23 +476,38 @@ impl State {
     }
     fn error_report(&self, form: GalleryCrawlErrorReportForm) -> Result<(), LiveDirectoryError> {
         let mut found = false;
+        let gallery_kind = match self.gallery_kind {
+            GalleryKind::Major => "major",
+            GalleryKind::Minor => "minor",
+            GalleryKind::Mini => "mini",
+        };
         self.metrics
             .worker_report_error_total
-            .with_label_values(&[
-                match self.gallery_kind {
-                    GalleryKind::Major => "major",
-                    GalleryKind::Minor => "minor",
-                    GalleryKind::Mini => "mini",
-                },
-                form.worker_part.to_string().as_str(),
-            ])
+            .with_label_values(&[gallery_kind, form.worker_part.to_string().as_str()])
             .inc();
+        if let CrawlerErrorReport::ParseFailed { path } = &form.error {
+            self.metrics
+                .worker_report_parse_error_total
+                .with_label_values(&[gallery_kind, path])
+                .inc();
+        }
         if let CrawlerErrorReport::Unknown = form.error {
             warn!(
                 "Unknown error reported from `{}` gallery at worker `{}`",
                 form.id, form.worker_part
             )
         };
+        if form.error.is_block() {
+            let until = Utc::now() + chrono::Duration::seconds(self.block_pause_seconds);
+            warn!(
+                "worker `{}` blocked({:?}) at `{}` gallery. pause until {}",
+                form.worker_part, form.error, form.id, until
+            );
+            self.paused_parts
+                .lock()
+                .unwrap()
+                .insert(form.worker_part, until);
+        }
         self.gallery_db
             .fetch_and_update(form.id.as_bytes(), |old| match old {
                 Some(bytes) => {
@@ -339,6 +516,9 @@ impl State {
                         .map(|mut old_state| {
                             old_state.last_error = Some(form.error.clone());
                             old_state.last_crawled_at = form.last_crawled_at;
+                            if form.last_crawled_document_id > old_state.last_crawled_document_id {
+                                old_state.last_crawled_document_id = form.last_crawled_document_id;
+                            }
                             old_state.publish_duration_in_seconds = Some(
                                 self.estimate_publish_duration(form.last_crawled_at, 0, &old_state),
                             );
@@ -347,6 +527,7 @@ impl State {
                                 CrawlerErrorReport::PageNotFound
                                     | CrawlerErrorReport::MinorGalleryClosed
                                     | CrawlerErrorReport::MinorGalleryPromoted
+                                    | CrawlerErrorReport::MiniGalleryClosed
                                     | CrawlerErrorReport::AdultPage
                             );
                             serde_json::to_vec(&old_state).unwrap()
@@ -362,8 +543,127 @@ impl State {
         }
     }
 
+    fn set_filter(&self, form: GalleryFilterForm) -> Result<(), LiveDirectoryError> {
+        let mut found = false;
+        self.gallery_db
+            .fetch_and_update(form.id.as_bytes(), |old| match old {
+                Some(bytes) => {
+                    found = true;
+                    serde_json::from_slice::<GalleryState>(bytes)
+                        .map(|mut old_state| {
+                            old_state.filter = form.filter.clone();
+                            serde_json::to_vec(&old_state).unwrap()
+                        })
+                        .ok()
+                }
+                None => None,
+            })?;
+        if found {
+            Ok(())
+        } else {
+            Err(LiveDirectoryError::NotFound)
+        }
+    }
+
+    /// Starts a backfill of a registered gallery, replacing the one it had.
+    fn start_backfill(&self, form: BackfillForm) -> Result<BackfillJob, LiveDirectoryError> {
+        let tree = self
+            .backfill_db
+            .as_ref()
+            .ok_or(LiveDirectoryError::BackfillDisabled)?;
+        let state = match self.gallery_db.get(form.id.as_bytes())? {
+            Some(bytes) => serde_json::from_slice::<GalleryState>(&bytes)
+                .map_err(|_| LiveDirectoryError::NotFound)?,
+            None => return Err(LiveDirectoryError::NotFound),
+        };
+        let mut job = BackfillJob::new(state.index).start_page(form.start_page.unwrap_or(1));
+        job.start_id = form.start_id;
+        job.target.until_id = form.until_id;
+        job.target.until_date = form.until_date;
+        tree.insert(form.id.as_bytes(), serde_json::to_vec(&job).unwrap())?;
+        info!("backfill of {} started", form.id);
+        Ok(job)
+    }
+
+    fn report_backfill(&self, form: BackfillReportForm) -> Result<(), LiveDirectoryError> {
+        let tree = self
+            .backfill_db
+            .as_ref()
+            .ok_or(LiveDirectoryError::BackfillDisabled)?;
+        let mut found = false;
+        tree.fetch_and_update(form.id.as_bytes(), |old| match old {
+            Some(bytes) => {
+                found = true;
+                serde_json::from_slice::<BackfillJob>(bytes)
+                    .map(|mut job| {
+                        job.checkpoint = form.checkpoint.clone();
+                        serde_json::to_vec(&job).unwrap()
+                    })
+                    .ok()
+            }
+            None => None,
+        })?;
+        if !found {
+            return Err(LiveDirectoryError::NotFound);
+        }
+        if form.checkpoint.done {
+            info!(
+                "backfill of {} done with {} documents",
+                form.id, form.checkpoint.published
+            );
+        }
+        Ok(())
+    }
+
+    /// Every backfill with its checkpoint. Finished ones are kept until replaced.
+    fn backfills(&self) -> Vec<BackfillJob> {
+        self.backfill_jobs()
+            .into_iter()
+            .map(|(_, job)| job)
+            .collect()
+    }
+
+    /// Unfinished backfills of the galleries `list_part` gives to the worker.
+    fn backfill_part(&self, total: u64, part: u64) -> Vec<BackfillJob> {
+        self.backfill_jobs()
+            .into_iter()
+            .filter(|(id, job)| hash(id) % total == part && !job.checkpoint.done)
+            .map(|(_, job)| job)
+            .collect()
+    }
+
+    fn backfill_jobs(&self) -> Vec<(sled::IVec, BackfillJob)> {
+        let tree = match &self.backfill_db {
+            Some(tree) => tree,
+            None => return Vec::new(),
+        };
+        tree.iter()
+            .filter_map(|res| {
+                if res.is_err() {
+                    error!("fail to iterate over sled");
+                }
+                res.ok()
+            })
+            .filter_map(
+                |(id, bytes)| match serde_json::from_slice::<BackfillJob>(&bytes) {
+                    Ok(job) => Some((id, job)),
+                    Err(e) => {
+                        error!("fail to parse backfill of {:?}: {}", id, e);
+                        None
+                    }
+                },
+            )
+            .collect()
+    }
+
     fn list_part(&self, total: u64, part: u64) -> Vec<GalleryState> {
         let now = Utc::now();
+        if let Some(until) = self.paused_parts.lock().unwrap().get(&part) {
+            if *until > now {
+                info!("worker `{}` is paused until {}", part, until);
+                return Vec::new();
+            }
+        }
         self.gallery_db
             .iter()
             .filter_map(|res| {
@@ -405,6 +705,17 @@ impl State {
     }
 }
 
+fn rank_key_prefix(gallery_id: &str, endpoint: RankingEndpoint) -> Vec<u8> {
+    format!("{}\0{}\0", gallery_id, endpoint.name()).into_bytes()
+}
+
+/// Keys sort by gallery, endpoint and then time.
+fn rank_key(prefix: &[u8], ranked_at: DateTime<Utc>) -> Vec<u8> {
+    let mut key = prefix.to_vec();
+    key.extend_from_slice(&(ranked_at.timestamp_millis().max(0) as u64).to_be_bytes());
+    key
+}
+
 async fn update_forever(
     state: State,
     delay: Duration,
@@ -420,6 +731,18 @@ async fn update_forever(
     }
 }
 
+async fn update_catalog_forever(
+    state: State,
+    delay: Duration,
+) -> Result<std::convert::Infallible, LiveDirectoryError> {
+    info!("start update catalog");
+    loop {
+        state.update_catalog().await?;
+        info!("update catalog done. wait {} seconds..", delay.as_secs());
+        actix::clock::delay_for(delay).await;
+    }
+}
+
 #[get("/health")]
 async fn health() -> impl Responder {
     "ok"
@@ -438,6 +761,24 @@ async fn list_part(
     web::Json(state.list_part(query.total, query.part))
 }
 
+#[derive(Deserialize)]
+pub struct RankHistoryQuery {
+    id: String,
+    endpoint: Option<String>,
+    since: Option<DateTime<Utc>>,
+}
+#[get("/rank-history")]
+async fn rank_history(
+    web::Query(query): web::Query<RankHistoryQuery>,
+    state: web::Data<State>,
+) -> Result<web::Json<Vec<RankPoint>>, LiveDirectoryError> {
+    Ok(web::Json(state.rank_history(
+        &query.id,
+        query.endpoint.as_deref(),
+        query.since,
+    )?))
+}
+
 #[post("/report")]
 async fn report(
     web::Json(form): web::Json<GalleryCrawlReportForm>,
@@ -448,6 +789,45 @@ async fn report(
     Ok(HttpResponse::Ok().finish())
 }
 
+#[post("/filter")]
+async fn gallery_filter(
+    web::Json(form): web::Json<GalleryFilterForm>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, LiveDirectoryError> {
+    state.set_filter(form)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[post("/backfill")]
+async fn start_backfill(
+    web::Json(form): web::Json<BackfillForm>,
+    state: web::Data<State>,
+) -> Result<web::Json<BackfillJob>, LiveDirectoryError> {
+    Ok(web::Json(state.start_backfill(form)?))
+}
+
+#[get("/backfill/list")]
+async fn backfill_part(
+    web::Query(query): web::Query<ListPartQuery>,
+    state: web::Data<State>,
+) -> impl Responder {
+    web::Json(state.backfill_part(query.total, query.part))
+}
+
+#[post("/backfill/report")]
+async fn report_backfill(
+    web::Json(form): web::Json<BackfillReportForm>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, LiveDirectoryError> {
+    state.report_backfill(form)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[get("/backfills")]
+async fn backfills(state: web::Data<State>) -> impl Responder {
+    web::Json(state.backfills())
+}
+
 #[post("/error-report")]
 async fn error_report(
     web::Json(form): web::Json<GalleryCrawlErrorReportForm>,
@@ -462,7 +842,13 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(health)
         .service(list_part)
         .service(report)
-        .service(error_report);
+        .service(error_report)
+        .service(gallery_filter)
+        .service(rank_history)
+        .service(start_backfill)
+        .service(backfill_part)
+        .service(report_backfill)
+        .service(backfills);
 }
 
 #[derive(Clone)]
@@ -470,6 +856,7 @@ struct Metrics {
     gallery_total: IntGauge,
     worker_report_success_total: IntCounterVec,
     worker_report_error_total: IntCounterVec,
+    worker_report_parse_error_total: IntCounterVec,
     crawl_waittime_histogram: Histogram,
     crawled_document_count_histogram: Histogram,
 }
@@ -495,6 +882,14 @@ impl Default for Metrics {
                 &["gallery_kind", "part"],
             )
             .unwrap(),
+            worker_report_parse_error_total: IntCounterVec::new(
+                opts!(
+                    "dccrawler_worker_report_parse_error_total",
+                    "dccrawler_worker_report_parse_error_total"
+                ),
+                &["gallery_kind", "path"],
+            )
+            .unwrap(),
             crawl_waittime_histogram: Histogram::with_opts(HistogramOpts::new(
                 "dccrawler_crawl_waittime_histogram",
                 "dccrawler_crawl_waittime_histogram",
@@ -537,6 +932,24 @@ async fn main() -> std::io::Result<()> {
         .unwrap_or_else(|_| "0.0001".to_string())
         .parse()
         .unwrap();
+    let block_pause_seconds: i64 = std::env::var("BLOCK_PAUSE_SECONDS")
+        .unwrap_or_else(|_| "600".to_string())
+        .parse()
+        .unwrap();
+    let rank_history_days: i64 = std::env::var("RANK_HISTORY_DAYS")
+        .unwrap_or_else(|_| "7".to_string())
+        .parse()
+        .unwrap();
+    let nats_conn = std::env::var("NATS_URL")
+        .ok()
+        .filter(|url| !url.is_empty())
+        .map(|url| nats::connect(&url).expect("nats connect"));
+    let nats_rank_subject = std::env::var("NATS_RANK_SUBJECT")
+        .unwrap_or_else(|_| "crawled.dcinside.gallery-ranks".to_string());
+    let catalog_refresh_seconds: u64 = std::env::var("CATALOG_REFRESH_SECONDS")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .unwrap();
 
     let prometheus = PrometheusMetrics::new(
         "dccrawler",
@@ -575,6 +988,14 @@ async fn main() -> std::io::Result<()> {
             &["gallery_kind", "part"],
         )
         .unwrap(),
+        worker_report_parse_error_total: IntCounterVec::new(
+            opts!(
+                "dccrawler_worker_report_parse_error_total",
+                "dccrawler_worker_report_parse_error_total"
+            ),
+            &["gallery_kind", "path"],
+        )
+        .unwrap(),
         crawl_waittime_histogram: Histogram::with_opts(
             HistogramOpts::new(
                 "dccrawler_crawl_waittime_histogram",
@@ -621,6 +1042,8 @@ async fn main() -> std::io::Result<()> {
         .unwrap();
     reg.register(Box::new(metrics.worker_report_error_total.clone()))
         .unwrap();
+    reg.register(Box::new(metrics.worker_report_parse_error_total.clone()))
+        .unwrap();
     reg.register(Box::new(metrics.worker_report_success_total.clone()))
         .unwrap();
     reg.register(Box::new(metrics.crawl_waittime_histogram.clone()))
@@ -634,30 +1057,62 @@ async fn main() -> std::io::Result<()> {
     } else {
         sled::open(store_path).unwrap()
     };
+    let rank_history_db = db.open_tree("rank_history").unwrap();
+    let backfill_db = db.open_tree("backfills").unwrap();
     let db = db.open_tree("galleries").unwrap();
 
     let _metrics = metrics.clone();
     let db2 = db.clone();
+    let rank_history_db2 = rank_history_db.clone();
     State::upgrade_db(db2.clone()).unwrap();
     actix_rt::spawn(async move {
         loop {
-            let state = State::with_db(db2.clone(), gallery_kind, _metrics.clone())
+            let mut state = State::with_db(db2.clone(), gallery_kind, _metrics.clone())
                 .docs_per_crawl(docs_per_crawl)
                 .min_wait_seconds(min_wait_seconds)
                 .pub_dur_estimate_weight1(pub_dur_estimate_weight1)
-                .pub_dur_estimate_weight2(pub_dur_estimate_weight2);
+                .pub_dur_estimate_weight2(pub_dur_estimate_weight2)
+                .with_rank_history(
+                    rank_history_db2.clone(),
+                    chrono::Duration::days(rank_history_days),
+                );
+            if let Some(conn) = &nats_conn {
+                state = state.with_nats(conn.clone(), nats_rank_subject.clone());
+            }
             let res = update_forever(state, Duration::from_secs(60)).await;
             if let Err(e) = res {
                 error!("updator restart due to: {}", e.to_string());
             }
         }
     });
+    if catalog_refresh_seconds > 0 {
+        let db3 = db.clone();
+        let _metrics = metrics.clone();
+        actix_rt::spawn(async move {
+            loop {
+                let state = State::with_db(db3.clone(), gallery_kind, _metrics.clone());
+                let res =
+                    update_catalog_forever(state, Duration::from_secs(catalog_refresh_seconds))
+                        .await;
+                if let Err(e) = res {
+                    error!("catalog updator restart due to: {}", e.to_string());
+                    actix::clock::delay_for(Duration::from_secs(60)).await;
+                }
+            }
+        });
+    }
     HttpServer::new(move || {
         let state = State::with_db(db.clone(), gallery_kind, metrics.clone())
             .docs_per_crawl(docs_per_crawl)
             .min_wait_seconds(min_wait_seconds)
             .pub_dur_estimate_weight1(pub_dur_estimate_weight1)
-            .pub_dur_estimate_weight2(pub_dur_estimate_weight2);
+            .pub_dur_estimate_weight2(pub_dur_estimate_weight2)
+            .block_pause_seconds(block_pause_seconds)
+            .with_rank_history(
+                rank_history_db.clone(),
+                chrono::Duration::days(rank_history_days),
+            )
+            .with_backfills(backfill_db.clone());
         App::new()
             .wrap(prometheus.clone())
             .app_data(web::Data::new(state))
@@ -673,6 +1128,8 @@ async fn main() -> std::io::Result<()> {
 mod tests {
     use super::*;
     use actix_web::{http, test, App};
+    use dcinside_crawler::backfill::BackfillCheckpoint;
+    use dcinside_crawler::listing::ListingFilter;
 
     #[actix_rt::test]
     async fn state_update_minor_list_part() {
@@ -758,6 +1215,7 @@ mod tests {
                 id: res1[0].index.id.clone(),
                 last_crawled_at: Some(now),
                 error: CrawlerErrorReport::MinorGalleryClosed,
+                last_crawled_document_id: None,
             })
             .unwrap();
         let res2 = state.list_part(2, 1);
@@ -772,6 +1230,206 @@ mod tests {
             1
         );
     }
+    #[actix_rt::test]
+    async fn state_set_filter() {
+        let state = State::new(GalleryKind::Major, Metrics::default());
+        let gallery = GalleryState::new(
+            GalleryIndex {
+                id: String::from("programming"),
+                name: String::from("프로그래밍"),
+                kind: GalleryKind::Major,
+                rank: None,
+            },
+            Utc::now(),
+        );
+        state
+            .gallery_db
+            .insert("programming", serde_json::to_vec(&gallery).unwrap())
+            .unwrap();
+        let filter = ListingFilter::recommend().search_head(10);
+        state
+            .set_filter(GalleryFilterForm {
+                id: "programming".to_string(),
+                filter: filter.clone(),
+            })
+            .unwrap();
+        assert_eq!(state.list_part(1, 0)[0].filter, filter);
+        assert!(state
+            .set_filter(GalleryFilterForm {
+                id: "notfound".to_string(),
+                filter,
+            })
+            .is_err());
+    }
+    #[actix_rt::test]
+    async fn state_backfill() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let state = State::new(GalleryKind::Major, Metrics::default())
+            .with_backfills(db.open_tree("backfills").unwrap());
+        let gallery = GalleryState::new(
+            GalleryIndex {
+                id: String::from("programming"),
+                name: String::from("프로그래밍"),
+                kind: GalleryKind::Major,
+                rank: None,
+            },
+            Utc::now(),
+        );
+        state
+            .gallery_db
+            .insert("programming", serde_json::to_vec(&gallery).unwrap())
+            .unwrap();
+        let form = |id: &str| BackfillForm {
+            id: id.to_string(),
+            start_page: Some(1000),
+            start_id: None,
+            until_id: Some(10),
+            until_date: None,
+        };
+        assert!(state.start_backfill(form("notfound")).is_err());
+        let job = state.start_backfill(form("programming")).unwrap();
+        assert_eq!(job.gallery, gallery.index);
+        assert_eq!(job.checkpoint.page, 1000);
+        assert_eq!(state.backfill_part(1, 0), vec![job.clone()]);
+
+        let checkpoint = BackfillCheckpoint {
+            page: 1001,
+            last_id: Some(500),
+            published: 100,
+            done: false,
+            updated_at: Some(Utc::now()),
+        };
+        state
+            .report_backfill(BackfillReportForm {
+                worker_part: 0,
+                id: "programming".to_string(),
+                checkpoint: checkpoint.clone(),
+            })
+            .unwrap();
+        assert_eq!(state.backfill_part(1, 0)[0].checkpoint, checkpoint);
+        state
+            .report_backfill(BackfillReportForm {
+                worker_part: 0,
+                id: "programming".to_string(),
+                checkpoint: BackfillCheckpoint {
+                    done: true,
+                    ..checkpoint
+                },
+            })
+            .unwrap();
+        assert!(state.backfill_part(1, 0).is_empty());
+        assert!(state.backfills()[0].checkpoint.done);
+    }
+    #[actix_rt::test]
+    async fn state_rank_history() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let state = State::new(GalleryKind::Major, Metrics::default())
+            .with_rank_history(db.open_tree("ranks").unwrap(), chrono::Duration::days(1));
+        let gallery = |id: &str, rank| GalleryIndex {
+            id: id.to_string(),
+            name: id.to_string(),
+            kind: GalleryKind::Major,
+            rank,
+        };
+        let now = Utc::now();
+        let galleries = vec![gallery("programming", Some(1)), gallery("game", Some(2))];
+        state
+            .record_ranks(
+                RankingEndpoint::RealtimeMajor,
+                &galleries,
+                now - chrono::Duration::days(2),
+            )
+            .unwrap();
+        state
+            .record_ranks(
+                RankingEndpoint::RealtimeMajor,
+                &galleries,
+                now - chrono::Duration::hours(1),
+            )
+            .unwrap();
+        let galleries = vec![gallery("game", None), gallery("programming", None)];
+        state
+            .record_ranks(RankingEndpoint::RealtimeMajor, &galleries, now)
+            .unwrap();
+        state
+            .record_ranks(RankingEndpoint::WeeklyMajor, &galleries, now)
+            .unwrap();
+
+        let res = state.rank_history("programming", None, None).unwrap();
+        // the read two days ago is past the retention
+        assert_eq!(
+            res.iter().map(|p| p.rank).collect::<Vec<_>>(),
+            vec![1, 2, 2]
+        );
+        let res = state
+            .rank_history("programming", Some("realtime_major"), Some(now))
+            .unwrap();
+        assert_eq!(
+            res,
+            vec![RankPoint {
+                endpoint: RankingEndpoint::RealtimeMajor,
+                rank: 2,
+                ranked_at: now,
+            }]
+        );
+        assert!(state.rank_history("prog", None, None).unwrap().is_empty());
+    }
+    #[actix_rt::test]
+    async fn state_error_report_counts_parse_failures() {
+        let state = State::new(GalleryKind::Major, Metrics::default());
+        let res = state.error_report(GalleryCrawlErrorReportForm {
+            worker_part: 0u64,
+            id: String::from("programming"),
+            last_crawled_at: Some(Utc::now()),
+            error: CrawlerErrorReport::ParseFailed {
+                path: String::from(".us-post .gall_num"),
+            },
+            last_crawled_document_id: None,
+        });
+        assert!(matches!(res, Err(LiveDirectoryError::NotFound)));
+        assert_eq!(
+            state
+                .metrics
+                .worker_report_parse_error_total
+                .with_label_values(&["major", ".us-post .gall_num"])
+                .get(),
+            1
+        );
+    }
+    #[actix_rt::test]
+    async fn state_error_report_pauses_blocked_part() {
+        // only the pause holds the gallery back
+        let state = State::new(GalleryKind::Major, Metrics::default()).min_wait_seconds(0);
+        let gallery = GalleryState::new(
+            GalleryIndex {
+                id: String::from("programming"),
+                name: String::from("프로그래밍"),
+                kind: GalleryKind::Major,
+                rank: None,
+            },
+            Utc::now(),
+        );
+        state
+            .gallery_db
+            .insert("programming", serde_json::to_vec(&gallery).unwrap())
+            .unwrap();
+        assert_eq!(state.list_part(1, 0).len(), 1);
+        state
+            .error_report(GalleryCrawlErrorReportForm {
+                worker_part: 0u64,
+                id: String::from("programming"),
+                last_crawled_at: Some(Utc::now()),
+                error: CrawlerErrorReport::Captcha,
+                last_crawled_document_id: Some(1595404),
+            })
+            .unwrap();
+        assert!(state.list_part(1, 0).is_empty());
+        state.paused_parts.lock().unwrap().clear();
+        let res = state.list_part(1, 0);
+        assert_eq!(res.len(), 1);
+        // what was published before the block is not walked again
+        assert_eq!(res[0].last_crawled_document_id, Some(1595404));
+    }
 
     #[actix_rt::test]
     async fn test_health() {
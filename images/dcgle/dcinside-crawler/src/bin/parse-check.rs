@@ -0,0 +1,119 @@
+use dcinside_crawler::drift::{check_page, DriftCorpus, PageKind};
+
+use std::collections::BTreeMap;
+
+const FIXTURES: [(PageKind, &str, &str); 9] = [
+    (
+        PageKind::List,
+        "gallery.html",
+        include_str!("../../assets/gallery.html"),
+    ),
+    (
+        PageKind::List,
+        "minor_gallery.html",
+        include_str!("../../assets/minor_gallery.html"),
+    ),
+    (
+        PageKind::List,
+        "gallery-problemtic.html",
+        include_str!("../../assets/gallery-problemtic.html"),
+    ),
+    (
+        PageKind::Body,
+        "body.html",
+        include_str!("../../assets/body.html"),
+    ),
+    (
+        PageKind::Comments,
+        "comments.json",
+        include_str!("../../assets/comments.json"),
+    ),
+    (
+        PageKind::Comments,
+        "minor_comments.json",
+        include_str!("../../assets/minor_comments.json"),
+    ),
+    (
+        PageKind::MobileList,
+        "mobile_list.html",
+        include_str!("../../assets/mobile_list.html"),
+    ),
+    (
+        PageKind::MobileBody,
+        "mobile_view.html",
+        include_str!("../../assets/mobile_view.html"),
+    ),
+    (
+        PageKind::MobileComments,
+        "mobile_comments.html",
+        include_str!("../../assets/mobile_comments.html"),
+    ),
+];
+
+#[derive(Default)]
+struct Row {
+    pages: usize,
+    passed: usize,
+    rows: usize,
+    failures: BTreeMap<&'static str, usize>,
+}
+
+/// Runs the parsers over the test fixtures and the pages sampled into the drift corpus,
+/// and prints how each group of pages parses now. Sampled pages are grouped by the
+/// selector they failed on when sampled.
+fn main() -> std::io::Result<()> {
+    let corpus_path = std::env::args()
+        .nth(1)
+        .or_else(|| std::env::var("DRIFT_CORPUS_PATH").ok());
+
+    let mut matrix: BTreeMap<(PageKind, String), Row> = BTreeMap::new();
+    let mut add = |kind: PageKind, group: String, body: &str| {
+        let check = check_page(kind, body);
+        let row = matrix.entry((kind, group)).or_default();
+        row.pages += 1;
+        row.rows += check.rows;
+        if check.passed() {
+            row.passed += 1;
+        }
+        for key in check.failures {
+            *row.failures.entry(key).or_default() += 1;
+        }
+    };
+    for (kind, name, body) in FIXTURES.iter() {
+        add(*kind, format!("fixture {}", name), body);
+    }
+    if let Some(path) = corpus_path {
+        for (kind, key, path) in DriftCorpus::new(path).samples()? {
+            let body = std::fs::read(&path)?;
+            add(kind, key, &String::from_utf8_lossy(&body));
+        }
+    }
+
+    println!(
+        "{:<16} {:<40} {:>6} {:>6} {:>6}  failing",
+        "kind", "group", "pages", "passed", "rows"
+    );
+    let mut failed = false;
+    for ((kind, group), row) in matrix.iter() {
+        failed |= row.passed < row.pages;
+        let failures = row
+            .failures
+            .iter()
+            .map(|(key, count)| format!("{}({})", key, count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "{:<16} {:<40} {:>6} {:>6} {:>6}  {}",
+            kind.name(),
+            group,
+            row.pages,
+            row.passed,
+            row.rows,
+            failures
+        );
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use dcinside_crawler::crawler::Crawler;
+use dcinside_crawler::listing::SearchMode;
+use dcinside_crawler::parse::{gallerykind_from_str, GalleryIndex};
+
+use futures::stream::StreamExt;
+use log::{error, info};
+
+/// Crawls every document of a gallery matching a search once, and publishes them
+/// on the subject workers publish to.
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    pretty_env_logger::init();
+
+    let nats_url = std::env::var("NATS_URL").expect("NATS_URL");
+    let nats_subject =
+        std::env::var("NATS_SUBJECT").unwrap_or_else(|_| "crawled.dcinside.documents".to_string());
+    let gallery = GalleryIndex {
+        id: std::env::var("GALLERY_ID").expect("GALLERY_ID"),
+        name: std::env::var("GALLERY_NAME").expect("GALLERY_NAME"),
+        kind: gallerykind_from_str(
+            std::env::var("GALLERY_KIND").unwrap_or_else(|_| "major".to_string()),
+        ),
+        rank: None,
+    };
+    let query = std::env::var("SEARCH_QUERY").expect("SEARCH_QUERY");
+    let mode: SearchMode = std::env::var("SEARCH_MODE")
+        .unwrap_or_else(|_| "title_content".to_string())
+        .parse()
+        .expect("SEARCH_MODE");
+    let delay: u64 = std::env::var("DELAY")
+        .unwrap_or_else(|_| "100".to_string())
+        .parse()
+        .expect("DELAY");
+    let max_documents: usize = std::env::var("MAX_DOCUMENTS")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .expect("MAX_DOCUMENTS");
+
+    let nats_conn = nats::connect(&nats_url)?;
+    let mut searcher = Crawler::new().delay(delay);
+    let mut fetcher = searcher.clone();
+    let mut indexes = searcher.search(&gallery, &query, mode);
+
+    info!("start search backfill of {} for `{}`", gallery.id, query);
+    let (mut published, mut failed) = (0usize, 0usize);
+    while let Some(res) = indexes.next().await {
+        let doc = match res {
+            Ok(index) => fetcher.document(&gallery, index).await,
+            Err(err) => Err(err),
+        };
+        let doc = match doc {
+            Ok(doc) => doc,
+            Err(err) if err.is_block() => {
+                error!("search backfill of {} blocked: {}", gallery.id, err);
+                break;
+            }
+            Err(err) => {
+                error!("search backfill of {}: {}", gallery.id, err);
+                failed += 1;
+                continue;
+            }
+        };
+        match bincode::serialize(&doc) {
+            Ok(bytes) => nats_conn.publish(&nats_subject, &bytes)?,
+            Err(err) => {
+                error!("bincode of {}.{}: {}", gallery.id, doc.id, err);
+                failed += 1;
+                continue;
+            }
+        }
+        published += 1;
+        if published % 100 == 0 {
+            info!("published {} documents of {}", published, gallery.id);
+        }
+        if max_documents > 0 && published >= max_documents {
+            break;
+        }
+    }
+    nats_conn.flush()?;
+    info!(
+        "search backfill of {} done: {} published, {} failed",
+        gallery.id, published, failed
+    );
+    Ok(())
+}
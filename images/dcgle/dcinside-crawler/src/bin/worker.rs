@@ -1,4 +1,6 @@
 use actix_web::{get, http::StatusCode, web, App, HttpServer, Responder};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 use std::time::Duration;
 
 use dcinside_crawler::error::*;
@@ -6,8 +8,17 @@ use err_derive::Error;
 
 use std::convert::TryInto;
 
-use dcinside_crawler::crawler::Crawler;
+use dcinside_crawler::backfill::BackfillJob;
+use dcinside_crawler::body::BodyFetchPolicy;
+use dcinside_crawler::crawler::{Backend, Crawler, MAX_LIST_PAGE};
+use dcinside_crawler::deletion::IdGaps;
+use dcinside_crawler::drift::DriftCorpus;
+use dcinside_crawler::egress::{EgressConfig, EgressHealth, EgressPool, Proxy};
+use dcinside_crawler::images::{ImageFetchPolicy, ImageStore};
 use dcinside_crawler::model::*;
+use dcinside_crawler::parse::{CatalogInfo, GalleryIndex};
+use dcinside_crawler::retry::{backoff_from_str, RetryMetrics, RetryPolicy};
+use dcinside_crawler::revisit::RevisitQueue;
 use dcinside_model::*;
 
 use serde::Serialize;
@@ -15,9 +26,10 @@ use serde::Serialize;
 use actix_web_prom::PrometheusMetrics;
 use prometheus::IntGauge;
 
+use futures::stream::{self, StreamExt};
 use log::{error, info};
 
-use actix_web::client::{PayloadError, SendRequestError};
+use actix_web::client::{Client, PayloadError, SendRequestError};
 
 #[derive(Error, Debug)]
 pub enum WorkerError {
@@ -48,13 +60,21 @@ pub struct ListPartQuery {
 #[derive(Clone)]
 struct State {
     crawler: Crawler,
+    client: Client,
     nats_conn: nats::Connection,
     nats_subject: String,
+    nats_update_subject: String,
+    revisits: RefCell<RevisitQueue>,
+    deletion_max_gap: usize,
     live_directory_url: String,
     data_broker_url: String,
     part: u64,
     total: u64,
     start_page: usize,
+    concurrency: usize,
+    backfill_crawler: Option<Crawler>,
+    backfill_pages: usize,
+    backfill_delay: Duration,
 }
 
 #[derive(Default)]
@@ -65,6 +85,24 @@ struct ResultMetric {
     gallery_error: usize,
     document_error: usize,
     comment_error: usize,
+    revisit_success: usize,
+    revisit_error: usize,
+    document_deleted: usize,
+    comment_deleted: usize,
+}
+impl std::ops::AddAssign for ResultMetric {
+    fn add_assign(&mut self, other: Self) {
+        self.gallery_success += other.gallery_success;
+        self.document_success += other.document_success;
+        self.comment_success += other.comment_success;
+        self.gallery_error += other.gallery_error;
+        self.document_error += other.document_error;
+        self.comment_error += other.comment_error;
+        self.revisit_success += other.revisit_success;
+        self.revisit_error += other.revisit_error;
+        self.document_deleted += other.document_deleted;
+        self.comment_deleted += other.comment_deleted;
+    }
 }
 impl State {
     fn new(
@@ -77,22 +115,103 @@ impl State {
     ) -> Result<Self, WorkerError> {
         Ok(State {
             crawler: Crawler::new(),
+            client: Client::default(),
             live_directory_url: live_directory_url.to_string(),
             nats_subject,
+            nats_update_subject: "crawled.dcinside.document-updates".to_string(),
+            revisits: RefCell::new(RevisitQueue::default().sample_rate(0.0)),
+            deletion_max_gap: 0,
             nats_conn: nats::connect(nats_url).map_err(WorkerError::NatsConnect)?,
             data_broker_url: data_broker_url.to_string(),
             total,
             part,
             start_page: 2,
+            concurrency: 1,
+            backfill_crawler: None,
+            backfill_pages: 0,
+            backfill_delay: Duration::from_millis(1000),
         })
     }
     fn with_crawler_delay(mut self, v: u64) -> Self {
         self.crawler = self.crawler.delay(v);
         self
     }
+    /// Galleries crawled at once. Each has its own crawler, so its own gallery session.
+    fn with_concurrency(mut self, v: usize) -> Self {
+        self.concurrency = v.max(1);
+        self
+    }
+    /// Requests per second over all galleries. Zero is unlimited.
+    fn with_max_rps(mut self, v: f64) -> Self {
+        if v > 0.0 {
+            self.crawler = self.crawler.throttle(v);
+        }
+        self
+    }
+    /// Walks up to `pages` listing pages of every backfill each round, with a crawler of
+    /// its own. Call it before `with_max_rps` to keep the budgets apart.
+    fn with_backfill(mut self, pages: usize, delay: u64, max_rps: f64) -> Self {
+        self.backfill_pages = pages;
+        self.backfill_delay = Duration::from_millis(delay);
+        self.backfill_crawler = if pages > 0 {
+            let crawler = self.crawler.clone().delay(delay);
+            Some(if max_rps > 0.0 {
+                crawler.throttle(max_rps)
+            } else {
+                crawler
+            })
+        } else {
+            None
+        };
+        self
+    }
+    fn with_body_fetch(mut self, v: BodyFetchPolicy) -> Self {
+        self.crawler = self.crawler.body_fetch(v);
+        self
+    }
+    fn with_image_fetch(mut self, policy: ImageFetchPolicy, store_path: &str) -> Self {
+        if policy.max_per_document > 0 {
+            self.crawler = self
+                .crawler
+                .image_fetch(policy, ImageStore::new(store_path));
+        }
+        self
+    }
+    /// `fallback` of zero keeps the pc backend on the pc site however it is blocked.
+    fn with_backend(mut self, backend: Backend, fallback: Duration) -> Self {
+        self.crawler = self.crawler.backend(backend);
+        if fallback > Duration::from_secs(0) {
+            self.crawler = self.crawler.mobile_fallback(fallback);
+        }
+        self
+    }
+    fn with_drift_corpus(mut self, corpus: Option<DriftCorpus>) -> Self {
+        if let Some(corpus) = corpus {
+            self.crawler = self.crawler.drift_corpus(corpus);
+        }
+        self
+    }
+    fn with_retry(mut self, policy: RetryPolicy, metrics: RetryMetrics) -> Self {
+        self.crawler = self.crawler.retry_policy(policy).retry_metrics(metrics);
+        self
+    }
+    fn with_egress_pool(mut self, pool: Option<Rc<EgressPool>>) -> Self {
+        if let Some(pool) = pool {
+            self.crawler = self.crawler.transport(pool);
+        }
+        self
+    }
+    fn with_revisits(mut self, queue: RevisitQueue, nats_update_subject: String) -> Self {
+        self.revisits = RefCell::new(queue);
+        self.nats_update_subject = nats_update_subject;
+        self
+    }
+    fn with_deletion_max_gap(mut self, v: usize) -> Self {
+        self.deletion_max_gap = v;
+        self
+    }
     async fn fetch_gallery_list(&self) -> Result<Vec<GalleryState>, WorkerError> {
         let bytes = self
-            .crawler
             .client
             .get(format!("{}/list", self.live_directory_url))
             .query(&ListPartQuery {
@@ -107,9 +226,36 @@ impl State {
             .await?;
         Ok(serde_json::from_slice(&bytes)?)
     }
+    async fn fetch_backfill_list(&self) -> Result<Vec<BackfillJob>, WorkerError> {
+        let bytes = self
+            .client
+            .get(format!("{}/backfill/list", self.live_directory_url))
+            .query(&ListPartQuery {
+                total: self.total,
+                part: self.part,
+            })
+            .unwrap()
+            .send()
+            .await?
+            .body()
+            .limit(1024 * 1024 * 8)
+            .await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+    async fn report_backfill(&self, form: BackfillReportForm) -> Result<(), WorkerError> {
+        let res = self
+            .client
+            .post(format!("{}/backfill/report", self.live_directory_url))
+            .send_json(&form)
+            .await?;
+        if res.status() == StatusCode::OK {
+            Ok(())
+        } else {
+            Err(WorkerError::Response(res.status()))
+        }
+    }
     async fn error_report(&self, form: GalleryCrawlErrorReportForm) -> Result<(), WorkerError> {
         let res = self
-            .crawler
             .client
             .post(format!("{}/error-report", self.live_directory_url))
             .send_json(&form)
@@ -122,7 +268,6 @@ impl State {
     }
     async fn report_success(&self, form: GalleryCrawlReportForm) -> Result<(), WorkerError> {
         let res = self
-            .crawler
             .client
             .post(format!("{}/report", self.live_directory_url))
             .send_json(&form)
@@ -135,7 +280,6 @@ impl State {
     }
     async fn send_data(&self, data: &Document) -> Result<(), WorkerError> {
         let res = self
-            .crawler
             .client
             .post(&self.data_broker_url)
             .send_json(data)
@@ -152,125 +296,354 @@ impl State {
             Err(WorkerError::Response(res.status()))
         }
     }
-    async fn run(&mut self) -> Result<ResultMetric, WorkerError> {
-        let mut gallery_states = self.fetch_gallery_list().await?;
-        let mut metric = ResultMetric::default();
-        let len = gallery_states.len();
-        gallery_states.sort_by(|a, b| match (a.last_crawled_at, b.last_crawled_at) {
-            (Some(a), Some(b)) => a.cmp(&b),
-            (None, Some(_)) => std::cmp::Ordering::Less,
-            (Some(_), None) => std::cmp::Ordering::Greater,
-            (None, None) => std::cmp::Ordering::Equal,
+    fn send_event(&self, event: &DocumentEvent) -> Result<(), WorkerError> {
+        self.nats_conn
+            .publish(&self.nats_update_subject, &bincode::serialize(event)?)
+            .map_err(WorkerError::NatsPublish)
+    }
+    fn send_document_deleted(&self, metric: &mut ResultMetric, gallery_id: &str, id: usize) {
+        metric.document_deleted += 1;
+        let event = DocumentEvent::DocumentDeleted(DocumentDeletion {
+            gallery_id: gallery_id.to_string(),
+            id,
+            detected_at: chrono::Utc::now(),
         });
-        for (i, gallery_state) in gallery_states.into_iter().enumerate() {
+        if let Err(e) = self.send_event(&event) {
+            error!("error while send deletion: {}", e.to_string());
+        }
+    }
+    async fn probe_gaps(
+        &self,
+        crawler: &mut Crawler,
+        gallery: &GalleryIndex,
+        ids: Vec<usize>,
+        metric: &mut ResultMetric,
+    ) {
+        if !ids.is_empty() {
+            info!("probe {} missing documents of {}", ids.len(), gallery.id);
+        }
+        for id in ids {
+            match crawler.probe_document(gallery, id).await {
+                Ok(true) => self.send_document_deleted(metric, &gallery.id, id),
+                Ok(false) => {}
+                Err(err) => {
+                    error!("probe of {}.{}: {}", &gallery.id, id, err.to_string());
+                    if err.is_block() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    async fn revisit_due(&mut self, metric: &mut ResultMetric) {
+        let due = self.revisits.get_mut().pop_due(chrono::Utc::now());
+        if due.is_empty() {
+            return;
+        }
+        info!(
+            "revisit {} documents ({} waiting)",
+            due.len(),
+            self.revisits.get_mut().len()
+        );
+        let mut crawler = self.crawler.clone();
+        for revisit in due {
+            match crawler.revisit(&revisit.gallery, &revisit.snapshot).await {
+                Ok(update) => {
+                    metric.revisit_success += 1;
+                    self.revisits
+                        .get_mut()
+                        .reschedule(revisit, &update, chrono::Utc::now());
+                    for &id in update.deleted_comment_ids.iter() {
+                        metric.comment_deleted += 1;
+                        let event = DocumentEvent::CommentDeleted(CommentDeletion {
+                            gallery_id: update.gallery_id.clone(),
+                            document_id: update.id,
+                            id,
+                            detected_at: update.revisited_at,
+                        });
+                        if let Err(e) = self.send_event(&event) {
+                            error!("error while send deletion: {}", e.to_string());
+                        }
+                    }
+                    if let Err(e) = self.send_event(&DocumentEvent::Updated(update)) {
+                        error!("error while send update: {}", e.to_string());
+                    }
+                }
+                Err(err) if err.is_deleted() => {
+                    metric.revisit_success += 1;
+                    self.send_document_deleted(metric, &revisit.gallery.id, revisit.snapshot.id);
+                }
+                Err(err) => {
+                    error!(
+                        "revisit of {}.{}: {}",
+                        &revisit.gallery.id,
+                        revisit.snapshot.id,
+                        err.to_string()
+                    );
+                    metric.revisit_error += 1;
+                    if err.is_block() {
+                        error!("blocked({}). skip the rest revisits", err);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    /// Walks the backfills of this part from their checkpoints, a few pages each.
+    async fn backfill(&self, metric: &mut ResultMetric) {
+        let mut crawler = match &self.backfill_crawler {
+            Some(crawler) => crawler.clone(),
+            None => return,
+        };
+        let jobs = match self.fetch_backfill_list().await {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                error!("error while fetch backfills: {}", e.to_string());
+                return;
+            }
+        };
+        for mut job in jobs {
             info!(
-                "{}/{} start | {}(last crawled at {:?})",
-                i, len, gallery_state.index.id, gallery_state.last_crawled_at
+                "backfill {} from page {} ({} published)",
+                job.gallery.id, job.checkpoint.page, job.checkpoint.published
             );
-            let now = chrono::Utc::now();
-            let res = match gallery_state.last_crawled_document_id {
-                Some(last_crawled_document_id) if last_crawled_document_id > 0 => {
-                    self.crawler
-                        .documents_after(
-                            &gallery_state.index,
-                            last_crawled_document_id,
-                            self.start_page,
-                        )
-                        .await
+            for _ in 0..self.backfill_pages {
+                let (documents, checkpoint) = match crawler.backfill_page(&job).await {
+                    Ok(res) => res,
+                    Err(err) => {
+                        error!(
+                            "backfill of {} fail at page {}: {}",
+                            &job.gallery.id,
+                            job.checkpoint.page,
+                            err.to_string()
+                        );
+                        metric.gallery_error += 1;
+                        if err.is_block() {
+                            error!("blocked({}). skip the rest backfills", err);
+                            return;
+                        }
+                        break;
+                    }
+                };
+                for doc in documents {
+                    match doc {
+                        Ok(doc) => {
+                            metric.document_success += 1;
+                            if let Err(e) = self.send_data(&doc).await {
+                                error!("error while send data: {}", e.to_string());
+                            }
+                        }
+                        Err(err) => {
+                            error!(
+                                "backfill document of {}: {}",
+                                &job.gallery.id,
+                                err.to_string()
+                            );
+                            metric.document_error += 1;
+                        }
+                    }
                 }
-                _ => {
-                    self.crawler
-                        .documents(&gallery_state.index, self.start_page)
-                        .await
+                job.checkpoint = checkpoint;
+                if let Err(e) = self
+                    .report_backfill(BackfillReportForm {
+                        worker_part: self.part,
+                        id: job.gallery.id.clone(),
+                        checkpoint: job.checkpoint.clone(),
+                    })
+                    .await
+                {
+                    error!("error while report backfill: {}", e.to_string());
                 }
-            };
-            match &res {
-                Ok(res) => {
-                    info!("crawled documents: {}", res.len());
-                    metric.gallery_success += 1;
-                    let mut last_document_id =
-                        gallery_state.last_crawled_document_id.unwrap_or(0usize);
-                    for r in res {
-                        match r {
-                            Ok(doc) => {
-                                metric.document_success += 1;
-                                metric.comment_success += 1;
-                                if last_document_id < doc.id {
-                                    last_document_id = doc.id;
-                                }
-                                if let Err(e) = self.send_data(doc).await {
-                                    error!("error while send data: {}", e.to_string());
-                                }
-                            }
-                            Err(CrawlerError::DocumentParseError(err)) => {
-                                error!(
-                                    "document parse error of {}: {}",
-                                    &gallery_state.index.id,
-                                    err.to_string()
-                                );
-                                metric.document_error += 1;
-                            }
-                            Err(CrawlerError::CommentParseError(err)) => {
-                                error!(
-                                    "coments parse error of {}: {}",
-                                    &gallery_state.index.id,
-                                    err.to_string()
-                                );
-                                metric.comment_error += 1;
-                            }
-                            Err(err) => {
-                                error!(
-                                    "document crawl of {}: {}",
-                                    &gallery_state.index.id,
-                                    err.to_string()
-                                );
-                                metric.document_error += 1;
-                            }
-                        };
+                if job.checkpoint.done {
+                    info!("backfill of {} done", job.gallery.id);
+                    break;
+                }
+                actix::clock::delay_for(self.backfill_delay).await;
+            }
+        }
+    }
+    async fn crawl_gallery(
+        &self,
+        i: usize,
+        len: usize,
+        gallery_state: GalleryState,
+        blocked: &Cell<bool>,
+    ) -> ResultMetric {
+        let mut metric = ResultMetric::default();
+        if blocked.get() {
+            return metric;
+        }
+        info!(
+            "{}/{} start | {}(last crawled at {:?})",
+            i, len, gallery_state.index.id, gallery_state.last_crawled_at
+        );
+        let now = chrono::Utc::now();
+        let (last_crawled_document_id, pages) = match gallery_state.last_crawled_document_id {
+            Some(last_crawled_document_id) if last_crawled_document_id > 0 => {
+                (last_crawled_document_id, self.start_page..MAX_LIST_PAGE)
+            }
+            _ => (0, self.start_page..self.start_page + 1),
+        };
+        let mut crawler = self.crawler.clone();
+        let mut documents = crawler.filtered_document_stream(
+            &gallery_state.index,
+            gallery_state.filter.clone(),
+            last_crawled_document_id,
+            pages,
+        );
+        let mut list_error = None;
+        let mut gaps = IdGaps::new(self.deletion_max_gap);
+        while let Some(res) = documents.next().await {
+            // rows rather than documents, so a document failing to fetch is no suspect.
+            // ids of partial lists have gaps by design
+            for id in documents.take_listed_ids() {
+                if !gallery_state.filter.is_partial() {
+                    gaps.observe(id);
+                }
+            }
+            match res {
+                Ok(mut doc) => {
+                    if let Some(catalog) = &gallery_state.catalog {
+                        merge_catalog(&mut doc.gallery, catalog);
+                    }
+                    metric.document_success += 1;
+                    metric.comment_success += 1;
+                    if let Err(e) = self.send_data(&doc).await {
+                        error!("error while send data: {}", e.to_string());
                     }
-                    if let Err(e) = self
-                        .report_success(GalleryCrawlReportForm {
-                            id: gallery_state.index.id.clone(),
-                            worker_part: self.part,
-                            last_crawled_at: Some(now),
-                            last_crawled_document_id: if last_document_id > 0 {
-                                Some(last_document_id)
-                            } else {
-                                None
-                            },
-                            crawled_document_count: res.len(),
-                        })
-                        .await
-                    {
-                        error!("error while report: {}", e.to_string());
-                    };
+                    self.revisits
+                        .borrow_mut()
+                        .push(&gallery_state.index, &doc, chrono::Utc::now());
+                }
+                Err(err) if documents.progress().aborted => {
+                    list_error = Some(err);
+                }
+                Err(CrawlerError::DocumentParseError(err)) => {
+                    error!(
+                        "document parse error of {}: {}",
+                        &gallery_state.index.id,
+                        err.to_string()
+                    );
+                    metric.document_error += 1;
+                }
+                Err(CrawlerError::CommentParseError(err)) => {
+                    error!(
+                        "coments parse error of {}: {}",
+                        &gallery_state.index.id,
+                        err.to_string()
+                    );
+                    metric.comment_error += 1;
                 }
                 Err(err) => {
                     error!(
-                        "get index of {} fail: {}",
+                        "document crawl of {}: {}",
                         &gallery_state.index.id,
                         err.to_string()
                     );
-                    metric.gallery_error += 1;
-                    info!("report error");
-                    if let Err(e) = self
-                        .error_report(GalleryCrawlErrorReportForm {
-                            worker_part: self.part,
-                            id: gallery_state.index.id.clone(),
-                            error: err.into(),
-                            last_crawled_at: Some(now),
-                        })
-                        .await
-                    {
-                        error!("error while error report: {}", e.to_string());
-                    };
+                    metric.document_error += 1;
                 }
             };
         }
+        let progress = documents.progress();
+        drop(documents);
+        match list_error {
+            None => {
+                info!(
+                    "crawled documents: {} (until page {})",
+                    progress.crawled_document_count, progress.page
+                );
+                metric.gallery_success += 1;
+                self.probe_gaps(&mut crawler, &gallery_state.index, gaps.take(), &mut metric)
+                    .await;
+                let last_document_id = progress
+                    .newest_document_id
+                    .unwrap_or(0)
+                    .max(last_crawled_document_id);
+                if let Err(e) = self
+                    .report_success(GalleryCrawlReportForm {
+                        id: gallery_state.index.id.clone(),
+                        worker_part: self.part,
+                        last_crawled_at: Some(now),
+                        last_crawled_document_id: if last_document_id > 0 {
+                            Some(last_document_id)
+                        } else {
+                            None
+                        },
+                        crawled_document_count: progress.crawled_document_count,
+                    })
+                    .await
+                {
+                    error!("error while report: {}", e.to_string());
+                };
+            }
+            Some(err) => {
+                error!(
+                    "get index of {} fail at page {}: {}",
+                    &gallery_state.index.id,
+                    progress.page,
+                    err.to_string()
+                );
+                metric.gallery_error += 1;
+                info!("report error");
+                if let Err(e) = self
+                    .error_report(GalleryCrawlErrorReportForm {
+                        worker_part: self.part,
+                        id: gallery_state.index.id.clone(),
+                        error: (&err).into(),
+                        last_crawled_at: Some(now),
+                        last_crawled_document_id: progress
+                            .newest_document_id
+                            .filter(|&id| id > last_crawled_document_id),
+                    })
+                    .await
+                {
+                    error!("error while error report: {}", e.to_string());
+                };
+                if err.is_block() {
+                    error!("blocked({}). skip the rest galleries", err);
+                    blocked.set(true);
+                }
+            }
+        };
+        metric
+    }
+    async fn run(&mut self) -> Result<ResultMetric, WorkerError> {
+        let mut gallery_states = self.fetch_gallery_list().await?;
+        let len = gallery_states.len();
+        gallery_states.sort_by(|a, b| match (a.last_crawled_at, b.last_crawled_at) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+        let blocked = Cell::new(false);
+        let mut metric = stream::iter(gallery_states.into_iter().enumerate())
+            .map(|(i, gallery_state)| self.crawl_gallery(i, len, gallery_state, &blocked))
+            .buffer_unordered(self.concurrency)
+            .fold(ResultMetric::default(), |mut acc, metric| async move {
+                acc += metric;
+                acc
+            })
+            .await;
+        self.revisit_due(&mut metric).await;
+        if !blocked.get() {
+            self.backfill(&mut metric).await;
+        }
         Ok(metric)
     }
 }
 
+/// Fills what the listing page did not tell with what the gallery directory did.
+fn merge_catalog(gallery: &mut Gallery, catalog: &CatalogInfo) {
+    let info = gallery.info.get_or_insert_with(Default::default);
+    info.category = info.category.take().or_else(|| catalog.category.clone());
+    info.manager_id = info
+        .manager_id
+        .take()
+        .or_else(|| catalog.manager_id.clone());
+    info.created_at = info.created_at.or(catalog.created_at);
+}
+
 #[derive(Clone)]
 struct ResultMetricGauges {
     gallery_success: IntGauge,
@@ -279,6 +652,10 @@ struct ResultMetricGauges {
     gallery_error: IntGauge,
     document_error: IntGauge,
     comment_error: IntGauge,
+    revisit_success: IntGauge,
+    revisit_error: IntGauge,
+    document_deleted: IntGauge,
+    comment_deleted: IntGauge,
 }
 async fn crawl_forever(
     mut state: State,
@@ -305,6 +682,18 @@ async fn crawl_forever(
         gauges
             .comment_error
             .set(metric.comment_error.try_into().unwrap());
+        gauges
+            .revisit_success
+            .set(metric.revisit_success.try_into().unwrap());
+        gauges
+            .revisit_error
+            .set(metric.revisit_error.try_into().unwrap());
+        gauges
+            .document_deleted
+            .set(metric.document_deleted.try_into().unwrap());
+        gauges
+            .comment_deleted
+            .set(metric.comment_deleted.try_into().unwrap());
         info!("crawl done. wait {} milli seconds..", delay.as_millis());
         actix::clock::delay_for(delay).await;
     }
@@ -315,8 +704,13 @@ async fn health() -> impl Responder {
     "ok"
 }
 
+#[get("/egress")]
+async fn egress(egress_health: web::Data<EgressHealth>) -> impl Responder {
+    web::Json(egress_health.snapshot())
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
-    cfg.service(health);
+    cfg.service(health).service(egress);
 }
 
 #[actix_web::main]
@@ -330,6 +724,8 @@ async fn main() -> std::io::Result<()> {
     let nats_url = std::env::var("NATS_URL").expect("NATS_URL");
     let nats_subject =
         std::env::var("NATS_SUBJECT").unwrap_or_else(|_| "crawled.dcinside.documents".to_string());
+    let nats_update_subject = std::env::var("NATS_UPDATE_SUBJECT")
+        .unwrap_or_else(|_| "crawled.dcinside.document-updates".to_string());
 
     let part: u64 = std::env::var("PART").expect("PART").parse().expect("PART");
     let total: u64 = std::env::var("TOTAL")
@@ -345,6 +741,196 @@ async fn main() -> std::io::Result<()> {
         .parse()
         .expect("SLEEP_DURATION");
 
+    let body_fetch_policy = BodyFetchPolicy {
+        per_gallery_budget: std::env::var("BODY_FETCH_GALLERY_BUDGET")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .expect("BODY_FETCH_GALLERY_BUDGET"),
+        per_host_budget: std::env::var("BODY_FETCH_HOST_BUDGET")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .expect("BODY_FETCH_HOST_BUDGET"),
+        window: Duration::from_secs(
+            std::env::var("BODY_FETCH_WINDOW_SECONDS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .expect("BODY_FETCH_WINDOW_SECONDS"),
+        ),
+        delay: Duration::from_millis(
+            std::env::var("BODY_FETCH_DELAY")
+                .unwrap_or_else(|_| "1000".to_string())
+                .parse()
+                .expect("BODY_FETCH_DELAY"),
+        ),
+        jitter: Duration::from_millis(
+            std::env::var("BODY_FETCH_JITTER")
+                .unwrap_or_else(|_| "1000".to_string())
+                .parse()
+                .expect("BODY_FETCH_JITTER"),
+        ),
+        recommended: std::env::var("BODY_FETCH_RECOMMENDED")
+            .map(|v| v == "true")
+            .unwrap_or(false),
+        min_comment_count: std::env::var("BODY_FETCH_MIN_COMMENTS")
+            .ok()
+            .map(|v| v.parse().expect("BODY_FETCH_MIN_COMMENTS")),
+        keep_html: std::env::var("BODY_FETCH_KEEP_HTML")
+            .map(|v| v == "true")
+            .unwrap_or(false),
+    };
+
+    let image_fetch_policy = ImageFetchPolicy {
+        max_per_document: std::env::var("IMAGE_FETCH_MAX_PER_DOCUMENT")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .expect("IMAGE_FETCH_MAX_PER_DOCUMENT"),
+        max_bytes: std::env::var("IMAGE_FETCH_MAX_BYTES")
+            .unwrap_or_else(|_| (1024 * 1024 * 8).to_string())
+            .parse()
+            .expect("IMAGE_FETCH_MAX_BYTES"),
+        images_only: std::env::var("IMAGE_FETCH_IMAGES_ONLY")
+            .map(|v| v != "false")
+            .unwrap_or(true),
+    };
+    let image_store_path =
+        std::env::var("IMAGE_STORE_PATH").unwrap_or_else(|_| "/data/images".to_string());
+
+    let retry_policy = RetryPolicy {
+        backoff: backoff_from_str(
+            std::env::var("RETRY_BACKOFF").unwrap_or_else(|_| "linear".to_string()),
+        ),
+        base_delay: Duration::from_millis(
+            std::env::var("RETRY_BASE_DELAY")
+                .unwrap_or_else(|_| "1000".to_string())
+                .parse()
+                .expect("RETRY_BASE_DELAY"),
+        ),
+        max_delay: Duration::from_millis(
+            std::env::var("RETRY_MAX_DELAY")
+                .unwrap_or_else(|_| "10000".to_string())
+                .parse()
+                .expect("RETRY_MAX_DELAY"),
+        ),
+        max_attempts: std::env::var("RETRY_MAX_ATTEMPTS")
+            .unwrap_or_else(|_| "11".to_string())
+            .parse()
+            .expect("RETRY_MAX_ATTEMPTS"),
+        respect_retry_after: std::env::var("RETRY_RESPECT_RETRY_AFTER")
+            .map(|v| v != "false")
+            .unwrap_or(true),
+    };
+
+    let revisit_schedule: Vec<chrono::Duration> = std::env::var("REVISIT_SCHEDULE")
+        .unwrap_or_else(|_| "3600,21600,86400".to_string())
+        .split(',')
+        .map(|v| chrono::Duration::seconds(v.trim().parse().expect("REVISIT_SCHEDULE")))
+        .collect();
+    let revisits = RevisitQueue::new(revisit_schedule)
+        .sample_rate(
+            std::env::var("REVISIT_SAMPLE_RATE")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .expect("REVISIT_SAMPLE_RATE"),
+        )
+        .capacity(
+            std::env::var("REVISIT_CAPACITY")
+                .unwrap_or_else(|_| "10000".to_string())
+                .parse()
+                .expect("REVISIT_CAPACITY"),
+        );
+
+    let concurrency: usize = std::env::var("CONCURRENCY")
+        .unwrap_or_else(|_| "1".to_string())
+        .parse()
+        .expect("CONCURRENCY");
+    let max_rps: f64 = std::env::var("MAX_RPS")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .expect("MAX_RPS");
+    let deletion_max_gap: usize = std::env::var("DELETION_MAX_GAP")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .expect("DELETION_MAX_GAP");
+    let backfill_pages: usize = std::env::var("BACKFILL_PAGES")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .expect("BACKFILL_PAGES");
+    let backfill_delay: u64 = std::env::var("BACKFILL_DELAY")
+        .unwrap_or_else(|_| "1000".to_string())
+        .parse()
+        .expect("BACKFILL_DELAY");
+    let backfill_max_rps: f64 = std::env::var("BACKFILL_MAX_RPS")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .expect("BACKFILL_MAX_RPS");
+
+    let backend: Backend = std::env::var("CRAWLER_BACKEND")
+        .unwrap_or_else(|_| "pc".to_string())
+        .parse()
+        .expect("CRAWLER_BACKEND");
+    let mobile_fallback = Duration::from_secs(
+        std::env::var("MOBILE_FALLBACK_SECONDS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .expect("MOBILE_FALLBACK_SECONDS"),
+    );
+
+    let drift_corpus = std::env::var("DRIFT_CORPUS_PATH").ok().map(|path| {
+        DriftCorpus::new(path)
+            .sample_rate(
+                std::env::var("DRIFT_SAMPLE_RATE")
+                    .unwrap_or_else(|_| "0.1".to_string())
+                    .parse()
+                    .expect("DRIFT_SAMPLE_RATE"),
+            )
+            .max_per_key(
+                std::env::var("DRIFT_MAX_PER_SELECTOR")
+                    .unwrap_or_else(|_| "20".to_string())
+                    .parse()
+                    .expect("DRIFT_MAX_PER_SELECTOR"),
+            )
+    });
+
+    let egress_user_agents: Vec<String> = std::env::var("EGRESS_USER_AGENTS")
+        .map(|v| {
+            v.split('|')
+                .map(|ua| ua.trim().to_string())
+                .filter(|ua| !ua.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    let egress_cooldown = Duration::from_secs(
+        std::env::var("EGRESS_COOLDOWN_SECONDS")
+            .unwrap_or_else(|_| "600".to_string())
+            .parse()
+            .expect("EGRESS_COOLDOWN_SECONDS"),
+    );
+    let egress_configs: Vec<EgressConfig> = std::env::var("EGRESS_PROXIES")
+        .map(|v| {
+            v.split(',')
+                .map(|p| p.trim())
+                .filter(|p| !p.is_empty())
+                .map(|p| match p {
+                    "direct" => EgressConfig::direct(),
+                    p => EgressConfig::proxy(p.parse::<Proxy>().expect("EGRESS_PROXIES")),
+                })
+                .map(|c| {
+                    c.user_agents(egress_user_agents.clone())
+                        .cooldown(egress_cooldown)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let egress_pool = if egress_configs.is_empty() {
+        None
+    } else {
+        Some(Rc::new(EgressPool::new(egress_configs)))
+    };
+    let egress_health = egress_pool
+        .as_ref()
+        .map(|pool| pool.health())
+        .unwrap_or_default();
+
     let prometheus = PrometheusMetrics::new("api", Some("/metrics"), None);
     let metrics = ResultMetricGauges {
         gallery_success: IntGauge::new("dccrawler_gallery_success", "gallery_success").unwrap(),
@@ -353,6 +939,10 @@ async fn main() -> std::io::Result<()> {
         document_error: IntGauge::new("dccrawler_document_error", "document_error").unwrap(),
         comment_success: IntGauge::new("dccrawler_comment_success", "comment_success").unwrap(),
         comment_error: IntGauge::new("dccrawler_comment_error", "comment_error").unwrap(),
+        revisit_success: IntGauge::new("dccrawler_revisit_success", "revisit_success").unwrap(),
+        revisit_error: IntGauge::new("dccrawler_revisit_error", "revisit_error").unwrap(),
+        document_deleted: IntGauge::new("dccrawler_document_deleted", "document_deleted").unwrap(),
+        comment_deleted: IntGauge::new("dccrawler_comment_deleted", "comment_deleted").unwrap(),
     };
 
     let reg = prometheus.clone().registry;
@@ -368,6 +958,17 @@ async fn main() -> std::io::Result<()> {
         .unwrap();
     reg.register(Box::new(metrics.comment_error.clone()))
         .unwrap();
+    reg.register(Box::new(metrics.revisit_success.clone()))
+        .unwrap();
+    reg.register(Box::new(metrics.revisit_error.clone()))
+        .unwrap();
+    reg.register(Box::new(metrics.document_deleted.clone()))
+        .unwrap();
+    reg.register(Box::new(metrics.comment_deleted.clone()))
+        .unwrap();
+    let retry_metrics = RetryMetrics::default();
+    reg.register(Box::new(retry_metrics.retry_total.clone()))
+        .unwrap();
 
     actix_rt::spawn(async move {
         loop {
@@ -380,7 +981,18 @@ async fn main() -> std::io::Result<()> {
                 part,
             )
             .unwrap()
-            .with_crawler_delay(delay);
+            .with_crawler_delay(delay)
+            .with_body_fetch(body_fetch_policy.clone())
+            .with_image_fetch(image_fetch_policy.clone(), &image_store_path)
+            .with_retry(retry_policy.clone(), retry_metrics.clone())
+            .with_egress_pool(egress_pool.clone())
+            .with_backend(backend, mobile_fallback)
+            .with_drift_corpus(drift_corpus.clone())
+            .with_backfill(backfill_pages, backfill_delay, backfill_max_rps)
+            .with_max_rps(max_rps)
+            .with_concurrency(concurrency)
+            .with_revisits(revisits.clone(), nats_update_subject.clone())
+            .with_deletion_max_gap(deletion_max_gap);
             let res = crawl_forever(
                 state,
                 Duration::from_millis(sleep_duration),
@@ -392,11 +1004,16 @@ async fn main() -> std::io::Result<()> {
             }
         }
     });
-    HttpServer::new(move || App::new().wrap(prometheus.clone()).configure(config))
-        .bind(format!("0.0.0.0:{}", port))?
-        .workers(1)
-        .run()
-        .await
+    HttpServer::new(move || {
+        App::new()
+            .wrap(prometheus.clone())
+            .app_data(web::Data::new(egress_health.clone()))
+            .configure(config)
+    })
+    .bind(format!("0.0.0.0:{}", port))?
+    .workers(1)
+    .run()
+    .await
 }
 
 #[cfg(test)]
@@ -412,6 +1029,7 @@ mod tests {
                 id: s(),
                 name: s(),
                 kind: GalleryKind::Major,
+                info: None,
             },
             gallery_id: s(),
             id: 1,
@@ -440,15 +1058,44 @@ mod tests {
                 },
                 depth: 0,
                 kind: CommentKind::Text,
+                content: Default::default(),
+                mentioned_ids: Vec::new(),
                 contents: s(),
                 parent_id: None,
                 created_at: Some(chrono::Utc::now()),
+                deleted: false,
             }]),
             body: None,
         };
         let _bytes = bincode::serialize(&doc).unwrap();
     }
 
+    #[test]
+    fn merges_catalog() {
+        let created_at = chrono::Utc::now();
+        let catalog = CatalogInfo {
+            category: Some(s()),
+            manager_id: Some(String::from("catalog")),
+            created_at: Some(created_at),
+            ..Default::default()
+        };
+        let mut gallery = Gallery {
+            id: s(),
+            name: s(),
+            kind: GalleryKind::Minor,
+            info: None,
+        };
+        merge_catalog(&mut gallery, &catalog);
+        let info = gallery.info.as_ref().unwrap();
+        assert_eq!(info.category, catalog.category);
+        assert_eq!(info.manager_id.as_deref(), Some("catalog"));
+        assert_eq!(info.created_at, Some(created_at));
+        // the listing page is newer than the directory
+        gallery.info.as_mut().unwrap().manager_id = Some(String::from("listing"));
+        merge_catalog(&mut gallery, &catalog);
+        assert_eq!(gallery.info.unwrap().manager_id.as_deref(), Some("listing"));
+    }
+
     /*
     #[actix_rt::test]
     async fn state_update_list_part() {
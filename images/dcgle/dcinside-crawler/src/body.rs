@@ -0,0 +1,158 @@
+use crate::parse::DocumentIndex;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Which documents get their body fetched, and how fast.
+/// Budgets count body requests per `window`; a budget of 0 disables body fetching.
+#[derive(Debug, Clone)]
+pub struct BodyFetchPolicy {
+    pub per_gallery_budget: usize,
+    pub per_host_budget: usize,
+    pub window: Duration,
+    pub delay: Duration,
+    pub jitter: Duration,
+    pub recommended: bool,
+    pub min_comment_count: Option<u32>,
+    /// Keeps the markup of fetched bodies next to what is read from it.
+    pub keep_html: bool,
+}
+impl Default for BodyFetchPolicy {
+    fn default() -> Self {
+        BodyFetchPolicy {
+            per_gallery_budget: 0,
+            per_host_budget: 0,
+            window: Duration::from_secs(3600),
+            delay: Duration::from_millis(1000),
+            jitter: Duration::from_millis(1000),
+            recommended: false,
+            min_comment_count: None,
+            keep_html: false,
+        }
+    }
+}
+impl BodyFetchPolicy {
+    /// With no subset filter every document is wanted, otherwise the ones matching any filter.
+    pub fn wants(&self, index: &DocumentIndex) -> bool {
+        if !self.recommended && self.min_comment_count.is_none() {
+            return true;
+        }
+        (self.recommended && index.is_recommend)
+            || self
+                .min_comment_count
+                .map(|min| index.comment_count >= min)
+                .unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BodyFetchQueue {
+    policy: BodyFetchPolicy,
+    window_started_at: Option<Instant>,
+    host_spent: usize,
+    gallery_spent: HashMap<String, usize>,
+}
+impl BodyFetchQueue {
+    pub fn new(policy: BodyFetchPolicy) -> Self {
+        BodyFetchQueue {
+            policy,
+            window_started_at: None,
+            host_spent: 0,
+            gallery_spent: HashMap::new(),
+        }
+    }
+    pub fn policy(&self) -> &BodyFetchPolicy {
+        &self.policy
+    }
+    /// Takes one request from both the gallery and the host budget.
+    /// Returns false without spending anything if either is exhausted.
+    pub fn acquire(&mut self, gallery_id: &str, now: Instant) -> bool {
+        match self.window_started_at {
+            Some(t) if now.duration_since(t) < self.policy.window => (),
+            _ => {
+                self.window_started_at = Some(now);
+                self.host_spent = 0;
+                self.gallery_spent.clear();
+            }
+        }
+        let gallery_spent = self.gallery_spent.get(gallery_id).copied().unwrap_or(0);
+        if self.host_spent >= self.policy.per_host_budget
+            || gallery_spent >= self.policy.per_gallery_budget
+        {
+            return false;
+        }
+        self.host_spent += 1;
+        self.gallery_spent
+            .insert(gallery_id.to_string(), gallery_spent + 1);
+        true
+    }
+    pub fn next_delay(&self) -> Duration {
+        let jitter_millis = self.policy.jitter.as_millis() as f64 * rand::random::<f64>();
+        self.policy.delay + Duration::from_millis(jitter_millis as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use dcinside_model::{DocumentKind, User, UserKind};
+
+    fn index(comment_count: u32, is_recommend: bool) -> DocumentIndex {
+        DocumentIndex {
+            gallery_id: "gallery_id".to_string(),
+            id: 1,
+            title: "title".to_string(),
+            subject: None,
+            author: User {
+                id: None,
+                ip: Some("1.1".to_string()),
+                nickname: "ㅇㅇ".to_string(),
+                kind: UserKind::Dynamic,
+            },
+            comment_count,
+            like_count: 0,
+            view_count: 0,
+            kind: DocumentKind::Text,
+            is_recommend,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn it_selects_subset() {
+        let all = BodyFetchPolicy::default();
+        assert!(all.wants(&index(0, false)));
+        let subset = BodyFetchPolicy {
+            recommended: true,
+            min_comment_count: Some(10),
+            ..BodyFetchPolicy::default()
+        };
+        assert!(!subset.wants(&index(0, false)));
+        assert!(subset.wants(&index(0, true)));
+        assert!(subset.wants(&index(10, false)));
+    }
+
+    #[test]
+    fn it_spends_budgets() {
+        let mut queue = BodyFetchQueue::new(BodyFetchPolicy {
+            per_gallery_budget: 2,
+            per_host_budget: 3,
+            window: Duration::from_secs(60),
+            ..BodyFetchPolicy::default()
+        });
+        let now = Instant::now();
+        assert!(queue.acquire("a", now));
+        assert!(queue.acquire("a", now));
+        assert!(!queue.acquire("a", now));
+        assert!(queue.acquire("b", now));
+        assert!(!queue.acquire("c", now));
+        assert!(queue.acquire("a", now + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn it_disables_with_zero_budget() {
+        let mut queue = BodyFetchQueue::new(BodyFetchPolicy::default());
+        assert!(!queue.acquire("a", Instant::now()));
+    }
+}
@@ -1,19 +1,29 @@
+use crate::backfill::{BackfillCheckpoint, BackfillJob};
+use crate::body::{BodyFetchPolicy, BodyFetchQueue};
+use crate::drift::{DriftCorpus, PageKind, EMPTY_LIST};
 use crate::error::*;
+use crate::images::{is_image_name, perceptual_hash, ImageFetchPolicy, ImageStore};
+use crate::listing::{ListingFilter, SearchMode};
+use crate::mobile::*;
 use crate::parse::*;
+use crate::retry::{RetryMetrics, RetryPolicy};
+use crate::revisit::DocumentSnapshot;
+use crate::session::GallerySession;
+use crate::stream::{CrawlProgress, DocumentStream};
+use crate::transport::{ActixTransport, Request, Response, Throttled, Transport};
 use dcinside_model::*;
 
-use actix_web::{
-    client::{Client, ClientBuilder},
-    http::StatusCode,
-};
-
 use serde::{Deserialize, Serialize};
 
 use chrono::Utc;
-use std::time::Duration;
-
-use select::document::Document as HTMLDocument;
-use select::predicate::Attr;
+use futures::stream::{self, LocalBoxStream, StreamExt};
+use log::warn;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 #[allow(non_snake_case)]
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
@@ -29,101 +39,254 @@ struct CommentsPostForm<'a> {
     _GALLTYPE_: &'a str,
 }
 
-macro_rules! back_off {
-    ($delay:expr, $max_delay:expr, $($escape_rule:pat)|+, $($func:tt)+) => {
-        {
-        let mut i = 0;
-        let f = $($func)+;
-        loop {
-            let res = f().await;
-            if res.is_ok() || i * $delay >= $max_delay {
-                break res;
-            }
-            match &res {
-                Err(t) => match t {
-                    $($escape_rule)|+ => break res,
-                    _ => (),
-                },
-                _ => (),
-            };
-            println!("{} {}", "backoff..", i);
-            i += 1;
-            actix::clock::delay_for(Duration::from_millis($delay*i)).await;
-        }
-        }
-    };
-    ($delay:expr, $max_delay:expr, $($pattern:tt)+) => {
-        {
-        let mut i = 0;
-        let f = $($pattern)+;
-        loop {
-            let res = f().await;
-            if res.is_ok() || i * $delay >= $max_delay {
-                break res;
-            }
-            println!("{} {}", "backoff..", i);
-            i += 1;
-            actix::clock::delay_for(Duration::from_millis($delay*i)).await;
-        }
+fn jsonp_contents(text: &str) -> &str {
+    let trimed = text.trim();
+    match (trimed.find('('), trimed.rfind(')')) {
+        (Some(start), Some(end)) if start < end => &trimed[start + 1..end],
+        _ => trimed,
+    }
+}
+
+fn board_path(kind: GalleryKind) -> &'static str {
+    match kind {
+        GalleryKind::Major => "/board",
+        GalleryKind::Minor => "/mgallery/board",
+        GalleryKind::Mini => "/mini/board",
+    }
+}
+
+fn galltype(kind: GalleryKind) -> &'static str {
+    match kind {
+        GalleryKind::Major => "G",
+        GalleryKind::Minor => "M",
+        GalleryKind::Mini => "MI",
+    }
+}
+
+fn catalog_path(kind: GalleryKind) -> &'static str {
+    match kind {
+        GalleryKind::Major => "/category",
+        GalleryKind::Minor => "/m/category",
+        GalleryKind::Mini => "/n/category",
+    }
+}
+
+/// Listing pages beyond this are never walked.
+pub const MAX_LIST_PAGE: usize = 1000;
+
+/// Which site listings, bodies and comments are read from. Both give the same models.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Backend {
+    /// gall.dcinside.com
+    Pc,
+    /// m.dcinside.com. Lighter pages without `e_s_n_o`, but listing dates are
+    /// only to the day and pages hold 20 documents.
+    Mobile,
+}
+impl std::str::FromStr for Backend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pc" => Ok(Backend::Pc),
+            "mobile" => Ok(Backend::Mobile),
+            _ => Err(format!("unsupported backend `{}`", s)),
         }
     }
 }
 
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct MobileCommentsPostForm<'a> {
+    id: &'a str,
+    no: usize,
+    cpage: usize,
+    managerskill: &'a str,
+    del_scope: usize,
+    csort: &'a str,
+}
+
 #[derive(Clone)]
 pub struct Crawler {
-    pub client: Client,
+    transport: Rc<dyn Transport>,
     host: String,
-    e_s_n_o: Option<String>,
+    json_host: String,
+    mobile_host: String,
+    backend: Backend,
+    mobile_fallback: Option<Duration>,
+    /// Shared by clones, so galleries crawled after a block skip the PC site too.
+    mobile_until: Rc<Cell<Option<Instant>>>,
+    sessions: HashMap<String, GallerySession>,
+    session_ttl: Duration,
+    /// Shared by clones, so a crawler cloned for each gallery still sees what others listed.
+    gallery_infos: Rc<RefCell<HashMap<String, GalleryInfo>>>,
     delay: Duration,
+    retry_policy: RetryPolicy,
+    retry_metrics: Option<RetryMetrics>,
+    body_queue: Option<Rc<RefCell<BodyFetchQueue>>>,
+    image_fetch: Option<(ImageFetchPolicy, ImageStore)>,
+    drift: Option<DriftCorpus>,
 }
 impl<'a> Crawler {
     pub fn new() -> Self {
-        let client = ClientBuilder::new()
-            .header(
-                "User-Agent",
-                "Mozilla/5.0 (X11; Linux x86_64; rv:84.0) Gecko/20100101 Firefox/84.0",
-            )
-            .finish();
         Crawler {
-            client,
+            transport: Rc::new(ActixTransport::new()),
             host: String::from("https://gall.dcinside.com"),
-            e_s_n_o: None,
+            json_host: String::from("https://json2.dcinside.com"),
+            mobile_host: String::from("https://m.dcinside.com"),
+            backend: Backend::Pc,
+            mobile_fallback: None,
+            mobile_until: Rc::new(Cell::new(None)),
+            sessions: HashMap::new(),
+            session_ttl: Duration::from_secs(30 * 60),
+            gallery_infos: Rc::new(RefCell::new(HashMap::new())),
             delay: Duration::from_millis(100),
+            retry_policy: RetryPolicy::default(),
+            retry_metrics: None,
+            body_queue: None,
+            image_fetch: None,
+            drift: None,
         }
     }
     pub fn delay(mut self, millis: u64) -> Self {
         self.delay = Duration::from_millis(millis);
         self
     }
+    /// How long an `e_s_n_o` token is used before it is fetched again.
+    pub fn session_ttl(mut self, ttl: Duration) -> Self {
+        self.session_ttl = ttl;
+        self
+    }
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+    pub fn retry_metrics(mut self, metrics: RetryMetrics) -> Self {
+        self.retry_metrics = Some(metrics);
+        self
+    }
+    /// Enables the body fetching stage. Clones of this crawler share the budgets.
+    pub fn body_fetch(mut self, policy: BodyFetchPolicy) -> Self {
+        self.body_queue = Some(Rc::new(RefCell::new(BodyFetchQueue::new(policy))));
+        self
+    }
+    /// Downloads attachments of fetched bodies into `store`.
+    pub fn image_fetch(mut self, policy: ImageFetchPolicy, store: ImageStore) -> Self {
+        self.image_fetch = Some((policy, store));
+        self
+    }
+    /// Keeps samples of pages failing to parse in `corpus`.
+    pub fn drift_corpus(mut self, corpus: DriftCorpus) -> Self {
+        self.drift = Some(corpus);
+        self
+    }
+    pub fn transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Rc::new(transport);
+        self
+    }
+    /// Caps requests per second over this crawler and all its clones. A cap that is not
+    /// positive leaves it unthrottled.
+    pub fn throttle(mut self, requests_per_second: f64) -> Self {
+        if requests_per_second > 0.0 {
+            self.transport = Rc::new(Throttled::new(self.transport, requests_per_second));
+        }
+        self
+    }
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = host.to_string();
+        self
+    }
+    pub fn json_host(mut self, json_host: &str) -> Self {
+        self.json_host = json_host.to_string();
+        self
+    }
+    pub fn mobile_host(mut self, mobile_host: &str) -> Self {
+        self.mobile_host = mobile_host.to_string();
+        self
+    }
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+    /// Moves to the mobile site for `cooldown` whenever the PC site is found blocked.
+    pub fn mobile_fallback(mut self, cooldown: Duration) -> Self {
+        self.mobile_fallback = Some(cooldown);
+        self
+    }
+    fn uses_mobile(&self) -> bool {
+        self.backend == Backend::Mobile
+            || matches!(self.mobile_until.get(), Some(until) if until > Instant::now())
+    }
+    /// Whether to try the mobile site after `err` of the PC site.
+    fn falls_back_to_mobile(&mut self, err: &CrawlerError) -> bool {
+        match self.mobile_fallback {
+            Some(cooldown) if err.is_block() => {
+                warn!(
+                    "pc site is blocked({}). use the mobile site for {:?}",
+                    err, cooldown
+                );
+                self.mobile_until.set(Some(Instant::now() + cooldown));
+                true
+            }
+            _ => false,
+        }
+    }
+    fn sample_drift(&self, kind: PageKind, key: Option<&str>, body: &str) {
+        if let Some(drift) = &self.drift {
+            drift.sample(kind, key, body);
+        }
+    }
+    /// Samples a listing page by its first failing row, or as an empty list if it has
+    /// no row but `expects_rows`. Searches and partial lists may well be empty.
+    fn sample_rows(
+        &self,
+        kind: PageKind,
+        body: &str,
+        expects_rows: bool,
+        res: Result<Vec<Result<DocumentIndex, DocumentParseError>>, DocumentParseError>,
+    ) -> Result<Vec<Result<DocumentIndex, DocumentParseError>>, DocumentParseError> {
+        let key = match &res {
+            Ok(rows) if rows.is_empty() && expects_rows => Some(EMPTY_LIST),
+            Ok(rows) => rows
+                .iter()
+                .find_map(|row| row.as_ref().err())
+                .and_then(|err| err.drift_key()),
+            Err(err) => err.drift_key(),
+        };
+        self.sample_drift(kind, key, body);
+        res
+    }
+    async fn retry<T, F, Fut>(&self, operation: &'static str, f: F) -> Result<T, CrawlerError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, CrawlerError>>,
+    {
+        self.retry_policy
+            .run(operation, self.retry_metrics.as_ref(), f)
+            .await
+    }
     pub async fn weekly_hot_galleries(&self) -> Result<Vec<GalleryIndex>, CrawlerError> {
         let jsonp_callback_func = format!(
             "jQuery32109002533932178827_{}",
             Utc::now().timestamp_millis()
         );
         let path = format!(
-            "https://json2.dcinside.com/json0/gallmain/gallery_hot.php?jsoncallback={}&_={}",
+            "{}/json0/gallmain/gallery_hot.php?jsoncallback={}&_={}",
+            self.json_host,
             jsonp_callback_func,
             Utc::now().timestamp_millis()
         );
-        Ok(back_off!(1000, 1000 * 10, || async {
-            let bytes = self
-                .client
-                .get(path.as_str())
-                .header("Referer", "https://gall.dcinside.com/")
-                .send()
-                .await?
-                .body()
-                .limit(1024 * 1024 * 8)
+        self.retry("weekly_hot_galleries", || async {
+            let res = self
+                .transport
+                .send(Request::get(path.as_str()).header("Referer", format!("{}/", self.host)))
                 .await?;
-            let text = std::str::from_utf8(&bytes)?;
-            let trimed = text.trim();
-            let jsonp_contents = &trimed[jsonp_callback_func.len() + 1..trimed.len() - 1];
-            let mut galleries: Vec<GalleryIndex> = serde_json::from_str(&jsonp_contents)?;
+            let mut galleries: Vec<GalleryIndex> =
+                serde_json::from_str(jsonp_contents(res.text()?))?;
             for g in galleries.iter_mut() {
                 g.kind = GalleryKind::Major;
             }
-            Ok::<_, CrawlerError>(galleries)
-        })?)
+            Ok(galleries)
+        })
+        .await
     }
     pub async fn realtime_hot_galleries(&self) -> Result<Vec<GalleryIndex>, CrawlerError> {
         let jsonp_callback_func = format!(
@@ -131,29 +294,24 @@ impl<'a> Crawler {
             Utc::now().timestamp_millis()
         );
         let path = format!(
-            "https://json2.dcinside.com/json1/ranking_gallery.php?jsoncallback={}&_={}",
+            "{}/json1/ranking_gallery.php?jsoncallback={}&_={}",
+            self.json_host,
             jsonp_callback_func,
             Utc::now().timestamp_millis()
         );
-        Ok(back_off!(1000, 1000 * 10, || async {
-            let bytes = self
-                .client
-                .get(path.as_str())
-                .header("Referer", "https://gall.dcinside.com/")
-                .send()
-                .await?
-                .body()
-                .limit(1024 * 1024 * 8)
+        self.retry("realtime_hot_galleries", || async {
+            let res = self
+                .transport
+                .send(Request::get(path.as_str()).header("Referer", format!("{}/", self.host)))
                 .await?;
-            let text = std::str::from_utf8(&bytes)?;
-            let trimed = text.trim();
-            let jsonp_contents = &trimed[jsonp_callback_func.len() + 1..trimed.len() - 1];
-            let mut galleries: Vec<GalleryIndex> = serde_json::from_str(&jsonp_contents)?;
+            let mut galleries: Vec<GalleryIndex> =
+                serde_json::from_str(jsonp_contents(res.text()?))?;
             for g in galleries.iter_mut() {
                 g.kind = GalleryKind::Major;
             }
-            Ok::<_, CrawlerError>(galleries)
-        })?)
+            Ok(galleries)
+        })
+        .await
     }
     pub async fn realtime_hot_minor_galleries(&self) -> Result<Vec<GalleryIndex>, CrawlerError> {
         let jsonp_callback_func = format!(
@@ -161,29 +319,114 @@ impl<'a> Crawler {
             Utc::now().timestamp_millis()
         );
         let path = format!(
-            "https://json2.dcinside.com/json1/mgallmain/mgallery_ranking.php?jsoncallback={}&_={}",
+            "{}/json1/mgallmain/mgallery_ranking.php?jsoncallback={}&_={}",
+            self.json_host,
             jsonp_callback_func,
             Utc::now().timestamp_millis()
         );
-        Ok(back_off!(1000, 1000 * 10, || async {
-            let bytes = self
-                .client
-                .get(path.as_str())
-                .header("Referer", "https://gall.dcinside.com/m")
-                .send()
-                .await?
-                .body()
-                .limit(1024 * 1024 * 8)
+        self.retry("realtime_hot_minor_galleries", || async {
+            let res = self
+                .transport
+                .send(Request::get(path.as_str()).header("Referer", format!("{}/m", self.host)))
                 .await?;
-            let text = std::str::from_utf8(&bytes)?;
-            let trimed = text.trim();
-            let jsonp_contents = &trimed[jsonp_callback_func.len() + 1..trimed.len() - 1];
-            let mut galleries: Vec<GalleryIndex> = serde_json::from_str(&jsonp_contents)?;
+            let mut galleries: Vec<GalleryIndex> =
+                serde_json::from_str(jsonp_contents(res.text()?))?;
             for g in galleries.iter_mut() {
                 g.kind = GalleryKind::Minor;
             }
-            Ok::<_, CrawlerError>(galleries)
-        })?)
+            Ok(galleries)
+        })
+        .await
+    }
+    pub async fn realtime_hot_mini_galleries(&self) -> Result<Vec<GalleryIndex>, CrawlerError> {
+        let jsonp_callback_func = format!(
+            "jQuery32103937496412891207_{}",
+            Utc::now().timestamp_millis()
+        );
+        let path = format!(
+            "{}/json1/migallmain/migallery_ranking.php?jsoncallback={}&_={}",
+            self.json_host,
+            jsonp_callback_func,
+            Utc::now().timestamp_millis()
+        );
+        self.retry("realtime_hot_mini_galleries", || async {
+            let res = self
+                .transport
+                .send(Request::get(path.as_str()).header("Referer", format!("{}/n", self.host)))
+                .await?;
+            let mut galleries: Vec<GalleryIndex> =
+                serde_json::from_str(jsonp_contents(res.text()?))?;
+            for g in galleries.iter_mut() {
+                g.kind = GalleryKind::Mini;
+            }
+            Ok(galleries)
+        })
+        .await
+    }
+    /// The categories of the gallery directory of `kind`.
+    pub async fn catalog_categories(
+        &self,
+        kind: GalleryKind,
+    ) -> Result<Vec<CatalogCategory>, CrawlerError> {
+        let path = format!("{}{}", self.host, catalog_path(kind));
+        self.retry("catalog_categories", || async {
+            let res = self
+                .transport
+                .send(Request::get(path.as_str()).header("Referer", format!("{}/", self.host)))
+                .await?
+                .error_for_status()?;
+            Ok(parse_catalog_categories(res.text()?)?)
+        })
+        .await
+    }
+    pub async fn catalog_entries(
+        &self,
+        kind: GalleryKind,
+        category: &CatalogCategory,
+        page: usize,
+    ) -> Result<Vec<CatalogEntry>, CrawlerError> {
+        let path = format!(
+            "{}{}?cate={}&page={}",
+            self.host,
+            catalog_path(kind),
+            category.id,
+            page
+        );
+        self.retry("catalog_entries", || async {
+            let res = self
+                .transport
+                .send(
+                    Request::get(path.as_str())
+                        .header("Referer", format!("{}{}", self.host, catalog_path(kind))),
+                )
+                .await?
+                .error_for_status()?;
+            Ok(parse_catalog_entries(
+                res.text()?,
+                kind,
+                Some(category.name.as_str()),
+            )?)
+        })
+        .await
+    }
+    /// Every gallery of the directory of `kind`, walking all pages of all categories.
+    /// A gallery listed under several categories keeps the first one.
+    pub async fn catalog(&self, kind: GalleryKind) -> Result<Vec<CatalogEntry>, CrawlerError> {
+        let mut entries: Vec<CatalogEntry> = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        for category in self.catalog_categories(kind).await? {
+            for page in 1..MAX_LIST_PAGE {
+                actix::clock::delay_for(self.delay).await;
+                let next = self.catalog_entries(kind, &category, page).await?;
+                let len = entries.len();
+                entries.extend(next.into_iter().filter(|e| seen.insert(e.index.id.clone())));
+                // past the last page the site repeats it
+                if entries.len() == len {
+                    break;
+                }
+            }
+        }
+        Ok(entries)
     }
     pub async fn document_indexes_after(
         &mut self,
@@ -192,7 +435,7 @@ impl<'a> Crawler {
         start_page: usize,
     ) -> Result<Vec<Result<DocumentIndex, DocumentParseError>>, CrawlerError> {
         let mut docs = Vec::new();
-        for i in start_page..1000 {
+        for i in start_page..MAX_LIST_PAGE {
             let next_docs = self.document_indexes(gallery, i).await?;
             if next_docs.is_empty() {
                 break;
@@ -219,10 +462,30 @@ impl<'a> Crawler {
         &mut self,
         gallery: &GalleryIndex,
         doc_id: usize,
+    ) -> Result<Vec<Comment>, CrawlerError> {
+        let pc = if self.uses_mobile() {
+            None
+        } else {
+            Some(self.pc_comments(gallery, doc_id).await)
+        };
+        let mut comms = match pc {
+            Some(Err(err)) if self.falls_back_to_mobile(&err) => {
+                self.mobile_comments(gallery, doc_id).await?
+            }
+            Some(res) => res?,
+            None => self.mobile_comments(gallery, doc_id).await?,
+        };
+        attach_mentions(&mut comms);
+        Ok(comms)
+    }
+    async fn pc_comments(
+        &mut self,
+        gallery: &GalleryIndex,
+        doc_id: usize,
     ) -> Result<Vec<Comment>, CrawlerError> {
         let mut comms = Vec::new();
         for i in 1..1000 {
-            let (next_comms, max_page) = self._comments(&gallery, doc_id, i, None).await?;
+            let (next_comms, max_page) = self._comments(&gallery, doc_id, i).await?;
             if next_comms.is_empty() {
                 break;
             }
@@ -235,6 +498,183 @@ impl<'a> Crawler {
         }
         Ok(comms.into_iter().rev().collect())
     }
+    /// Comments of the mobile site, which lists them oldest first.
+    async fn mobile_comments(
+        &self,
+        gallery: &GalleryIndex,
+        doc_id: usize,
+    ) -> Result<Vec<Comment>, CrawlerError> {
+        let path = format!("{}/ajax/response-comment", self.mobile_host);
+        let referer = format!(
+            "{}{}/{}/{}",
+            self.mobile_host,
+            mobile_board_path(gallery.kind),
+            gallery.id,
+            doc_id
+        );
+        let mut comms: Vec<Comment> = Vec::new();
+        for page in 1..1000 {
+            let form = MobileCommentsPostForm {
+                id: &gallery.id,
+                no: doc_id,
+                cpage: page,
+                managerskill: "",
+                del_scope: 1,
+                csort: "",
+            };
+            let last_root_comment_id = comms.iter().rev().find(|c| c.depth == 0).map(|c| c.id);
+            let (next_comms, max_page) = self
+                .retry("mobile_comments", || async {
+                    let req = Request::post_form(path.as_str(), &form)?
+                        .header("User-Agent", MOBILE_USER_AGENT)
+                        .header("Referer", referer.as_str())
+                        .header("X-Requested-With", "XMLHttpRequest");
+                    let res = self.transport.send(req).await?.error_for_status()?;
+                    let text = res.text()?;
+                    let parsed = parse_mobile_comments(text, last_root_comment_id, Utc::now());
+                    if let Err(err) = &parsed {
+                        self.sample_drift(PageKind::MobileComments, err.drift_key(), text);
+                    }
+                    Ok(parsed?)
+                })
+                .await?;
+            if next_comms.is_empty() {
+                break;
+            }
+            comms.extend(next_comms);
+            if max_page <= page {
+                break;
+            }
+            actix::clock::delay_for(self.delay).await;
+        }
+        Ok(comms)
+    }
+    /// The document of `index` with its comments, and its body if the body fetch policy picks it.
+    pub async fn document(
+        &mut self,
+        gallery: &GalleryIndex,
+        index: DocumentIndex,
+    ) -> Result<Document, CrawlerError> {
+        let id = index.id;
+        let comments = if index.comment_count > 0 {
+            Some(self.comments(&gallery, id).await?)
+        } else {
+            None
+        };
+        let body = if self.reserve_body_fetch(gallery, &index).await {
+            match self.document_body(&gallery, id).await {
+                Ok(mut body) => {
+                    if !self
+                        .body_queue
+                        .as_ref()
+                        .map_or(false, |queue| queue.borrow().policy().keep_html)
+                    {
+                        body.html = None;
+                    }
+                    self.download_attachments(gallery, id, &mut body.attachments)
+                        .await;
+                    Some(body)
+                }
+                Err(err) => {
+                    warn!("fail to fetch body of {}.{}: {}", gallery.id, id, err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let mut doc = document_from_indexes(gallery.clone(), index, comments, body);
+        doc.gallery.info = self.gallery_info(gallery);
+        Ok(doc)
+    }
+    /// What the last listing page of `gallery` told, with its rank.
+    pub fn gallery_info(&self, gallery: &GalleryIndex) -> Option<GalleryInfo> {
+        let mut info = match self.gallery_infos.borrow().get(&gallery.id) {
+            Some(info) => info.clone(),
+            None if gallery.rank.is_some() => GalleryInfo::default(),
+            None => return None,
+        };
+        info.rank = gallery.rank.map(|rank| rank as u32);
+        Some(info)
+    }
+    /// Downloads the wanted attachments into the image store, filling their size and hashes.
+    /// A failed download leaves its attachment as listed.
+    async fn download_attachments(
+        &self,
+        gallery: &GalleryIndex,
+        id: usize,
+        attachments: &mut [Attachment],
+    ) {
+        let (policy, store) = match &self.image_fetch {
+            Some(image_fetch) => image_fetch,
+            None => return,
+        };
+        let referer = format!(
+            "{}{}/view/?id={}&no={}",
+            self.host,
+            board_path(gallery.kind),
+            gallery.id,
+            id
+        );
+        for attachment in attachments
+            .iter_mut()
+            .filter(|a| policy.wants(&a.name))
+            .take(policy.max_per_document)
+        {
+            actix::clock::delay_for(self.delay).await;
+            let req = Request::get(attachment.download_url.as_str())
+                .header("Referer", referer.as_str())
+                .body_limit(policy.max_bytes);
+            let bytes = match self
+                .transport
+                .send(req)
+                .await
+                .and_then(|res| res.error_for_status())
+            {
+                Ok(res) => res.body,
+                Err(CrawlerError::BodyTooLarge(len)) => {
+                    attachment.size = len;
+                    continue;
+                }
+                Err(err) => {
+                    warn!(
+                        "fail to download {} of {}.{}: {}",
+                        attachment.name, gallery.id, id, err
+                    );
+                    continue;
+                }
+            };
+            attachment.size = Some(bytes.len() as u64);
+            match store.put(&bytes) {
+                Ok(sha1) => attachment.sha1 = Some(sha1),
+                Err(err) => {
+                    warn!(
+                        "fail to store {} of {}.{}: {}",
+                        attachment.name, gallery.id, id, err
+                    );
+                    continue;
+                }
+            }
+            if is_image_name(&attachment.name) {
+                attachment.perceptual_hash = perceptual_hash(&bytes);
+            }
+        }
+    }
+    /// Waits out the body fetch delay if `index` is selected and still fits in the budget.
+    async fn reserve_body_fetch(&mut self, gallery: &GalleryIndex, index: &DocumentIndex) -> bool {
+        let delay = match &self.body_queue {
+            Some(queue) => {
+                let mut queue = queue.borrow_mut();
+                if !queue.policy().wants(index) || !queue.acquire(&gallery.id, Instant::now()) {
+                    return false;
+                }
+                queue.next_delay()
+            }
+            None => return false,
+        };
+        actix::clock::delay_for(delay).await;
+        true
+    }
     pub async fn documents(
         &mut self,
         gallery: &GalleryIndex,
@@ -242,42 +682,8 @@ impl<'a> Crawler {
     ) -> Result<Vec<Result<Document, CrawlerError>>, CrawlerError> {
         let mut documents = Vec::new();
         for res in self.document_indexes(&gallery, page).await? {
-            let doc: Result<Document, CrawlerError> = match res {
-                Ok(index) => {
-                    let id = index.id;
-                    let comments = if index.comment_count > 0 {
-                        Some(self.comments(&gallery, id).await)
-                    } else {
-                        None
-                    };
-                    // HACKIT: block by dcinside
-                    let body: Option<Result<String, CrawlerError>> = None; //Some(self.document_body(&gallery, id).await);
-                    match (comments, body) {
-                        (Some(Ok(comms)), Some(Ok(body))) => Ok(document_from_indexes(
-                            gallery.clone(),
-                            index,
-                            Some(comms),
-                            Some(body),
-                        )),
-                        (None, Some(Ok(body))) => Ok(document_from_indexes(
-                            gallery.clone(),
-                            index,
-                            None,
-                            Some(body),
-                        )),
-                        (Some(Ok(comms)), None) => Ok(document_from_indexes(
-                            gallery.clone(),
-                            index,
-                            Some(comms),
-                            None,
-                        )),
-                        (None, None) => {
-                            Ok(document_from_indexes(gallery.clone(), index, None, None))
-                        }
-                        (Some(Err(err)), _) => Err(err),
-                        (_, Some(Err(err))) => Err(err),
-                    }
-                }
+            let doc = match res {
+                Ok(index) => self.document(gallery, index).await,
                 Err(err) => Err(err.into()),
             };
             documents.push(doc);
@@ -288,200 +694,523 @@ impl<'a> Crawler {
         &mut self,
         gallery: &GalleryIndex,
         id: usize,
-    ) -> Result<String, CrawlerError> {
+    ) -> Result<DocumentBody, CrawlerError> {
+        if !self.uses_mobile() {
+            let drift = self.drift.clone();
+            match self
+                .document_view("document_body", gallery, id, |body| {
+                    let parsed = parse_document_body(body, &gallery.id, id);
+                    if let (Err(err), Some(drift)) = (&parsed, &drift) {
+                        drift.sample(PageKind::Body, err.drift_key(), body);
+                    }
+                    parsed
+                })
+                .await
+            {
+                Err(err) if self.falls_back_to_mobile(&err) => {}
+                res => return res,
+            }
+        }
         let path = format!(
-            "{}/board/view/?id={}&no={}&page=1",
-            self.host, gallery.id, id
+            "{}{}/{}/{}",
+            self.mobile_host,
+            mobile_board_path(gallery.kind),
+            gallery.id,
+            id
         );
-        let referer = format!("{}/board/lists?id={}", self.host, gallery.id);
-        Ok(back_off!(1000, 1000 * 10, || async {
-            let bytes = self
-                .client
-                .get(path.as_str())
-                .header("Referer", referer.as_str())
-                .send()
-                .await?
-                .body()
-                .limit(1024 * 1024 * 8)
-                .await?;
-            let text = std::str::from_utf8(&bytes)?;
-            Ok::<_, CrawlerError>(parse_document_body(text, &gallery.id, id)?)
-        })?)
+        let referer = format!(
+            "{}{}/{}",
+            self.mobile_host,
+            mobile_board_path(gallery.kind),
+            gallery.id
+        );
+        self.retry("mobile_document_body", || async {
+            let req = Request::get(path.as_str())
+                .header("User-Agent", MOBILE_USER_AGENT)
+                .header("Referer", referer.as_str());
+            let res = self.transport.send(req).await?.error_for_status()?;
+            let text = res.text()?;
+            let parsed = parse_mobile_document_body(text);
+            if let Err(err) = &parsed {
+                self.sample_drift(PageKind::MobileBody, err.drift_key(), text);
+            }
+            Ok(parsed?)
+        })
+        .await
+    }
+    /// Fetches counts and comments changed since `snapshot`. A document that is
+    /// gone fails with `DocumentParseError::Deleted`.
+    pub async fn revisit(
+        &mut self,
+        gallery: &GalleryIndex,
+        snapshot: &DocumentSnapshot,
+    ) -> Result<DocumentUpdate, CrawlerError> {
+        let counts = match self
+            .document_view("revisit", gallery, snapshot.id, parse_document_counts)
+            .await
+        {
+            Err(CrawlerError::PageNotFound) => Err(DocumentParseError::Deleted.into()),
+            res => res,
+        }?;
+        actix::clock::delay_for(self.delay).await;
+        let comments = if counts.comment_count > 0 || snapshot.comment_count > 0 {
+            self.comments(gallery, snapshot.id).await?
+        } else {
+            Vec::new()
+        };
+        Ok(snapshot.diff(&gallery.id, counts, comments, Utc::now()))
     }
+    /// Whether the document `id`, missing from a listing, is deleted.
+    pub async fn probe_document(
+        &mut self,
+        gallery: &GalleryIndex,
+        id: usize,
+    ) -> Result<bool, CrawlerError> {
+        let res = self
+            .document_view("probe_document", gallery, id, |body| {
+                Ok::<_, DocumentParseError>(is_deleted_document_page(body))
+            })
+            .await;
+        actix::clock::delay_for(self.delay).await;
+        match res {
+            Err(CrawlerError::PageNotFound) => Ok(true),
+            res => res,
+        }
+    }
+    async fn document_view<T, E, F>(
+        &mut self,
+        operation: &'static str,
+        gallery: &GalleryIndex,
+        id: usize,
+        parse: F,
+    ) -> Result<T, CrawlerError>
+    where
+        F: Fn(&str) -> Result<T, E>,
+        CrawlerError: From<E>,
+    {
+        let path = format!(
+            "{}{}/view/?id={}&no={}&page=1",
+            self.host,
+            board_path(gallery.kind),
+            gallery.id,
+            id
+        );
+        let referer = format!(
+            "{}{}/lists?id={}",
+            self.host,
+            board_path(gallery.kind),
+            gallery.id
+        );
+        let (this, path, referer, parse) = (&*self, &path, &referer, &parse);
+        let (parsed, e_s_n_o, res) = this
+            .retry(operation, || async move {
+                let req = this.with_session(
+                    &gallery.id,
+                    Request::get(path.as_str()).header("Referer", referer.as_str()),
+                );
+                let res = this.transport.send(req).await?.error_for_status()?;
+                let text = res.text()?;
+                Ok((parse(text)?, parse_e_s_n_o(text).ok(), res))
+            })
+            .await?;
+        self.update_session(gallery, e_s_n_o, &res);
+        Ok(parsed)
+    }
+    /// Documents newer than `last_document_id`, with the error of a listing page that
+    /// stopped the walk. Documents fetched before it are kept.
     pub async fn documents_after(
         &mut self,
         gallery: &GalleryIndex,
         last_document_id: usize,
         start_page: usize,
-    ) -> Result<Vec<Result<Document, CrawlerError>>, CrawlerError> {
+    ) -> (Vec<Result<Document, CrawlerError>>, Option<CrawlerError>) {
+        let mut stream = self.document_stream(gallery, last_document_id, start_page..MAX_LIST_PAGE);
         let mut documents = Vec::new();
-        for res in self
-            .document_indexes_after(&gallery, last_document_id, start_page)
-            .await?
-        {
-            let doc: Result<Document, CrawlerError> = match res {
+        while let Some(doc) = stream.next().await {
+            match doc {
+                Err(err) if stream.progress().aborted => return (documents, Some(err)),
+                doc => documents.push(doc),
+            }
+        }
+        (documents, None)
+    }
+    /// Streams documents newer than `last_document_id` from the listing pages in `pages`,
+    /// stopping at the first page that reaches `last_document_id`.
+    pub fn document_stream(
+        &'a mut self,
+        gallery: &'a GalleryIndex,
+        last_document_id: usize,
+        pages: Range<usize>,
+    ) -> DocumentStream<'a> {
+        self.filtered_document_stream(gallery, ListingFilter::default(), last_document_id, pages)
+    }
+    /// Like `document_stream`, over the list `filter` selects.
+    pub fn filtered_document_stream(
+        &'a mut self,
+        gallery: &'a GalleryIndex,
+        filter: ListingFilter,
+        last_document_id: usize,
+        pages: Range<usize>,
+    ) -> DocumentStream<'a> {
+        let progress = Rc::new(Cell::new(CrawlProgress::default()));
+        let listed_ids = Rc::new(RefCell::new(Vec::new()));
+        let state = DocumentStreamState {
+            crawler: self,
+            gallery,
+            filter,
+            last_document_id,
+            pages,
+            pending: VecDeque::new(),
+            fetched_page: false,
+            done: false,
+            progress: progress.clone(),
+            listed_ids: listed_ids.clone(),
+        };
+        let inner = stream::unfold(state, |mut state| async move {
+            let doc = state.next().await?;
+            Some((doc, state))
+        })
+        .boxed_local();
+        DocumentStream::new(inner, progress, listed_ids)
+    }
+    /// Walks the listing page at the checkpoint of `job`, returning its wanted documents
+    /// and the checkpoint to resume from.
+    pub async fn backfill_page(
+        &mut self,
+        job: &BackfillJob,
+    ) -> Result<(Vec<Result<Document, CrawlerError>>, BackfillCheckpoint), CrawlerError> {
+        let page = job.checkpoint.page;
+        let mut indexes = self.document_indexes(&job.gallery, page).await?;
+        // a page of unparsable rows is no end of the gallery. keep the checkpoint on it
+        if indexes.iter().all(|d| d.is_err()) && !indexes.is_empty() {
+            return Err(indexes.remove(0).unwrap_err().into());
+        }
+        let mut next = job.checkpoint.clone();
+        next.page = page + 1;
+        next.updated_at = Some(Utc::now());
+        // pinned notices come first, so the last index is the oldest document
+        next.done = match indexes.iter().rev().find_map(|d| d.as_ref().ok()) {
+            Some(oldest) => job.target.reached(oldest),
+            None => true,
+        };
+        let mut documents = Vec::new();
+        for res in indexes {
+            let doc = match res {
+                Ok(index) if !job.wants(&index) => continue,
                 Ok(index) => {
                     let id = index.id;
-                    let comments = if index.comment_count > 0 {
-                        Some(self.comments(&gallery, id).await)
-                    } else {
-                        None
-                    };
-                    // HACKIT: block by dcinside
-                    let body: Option<Result<String, CrawlerError>> = None; //Some(self.document_body(&gallery, id).await);
-                    match (comments, body) {
-                        (Some(Ok(comms)), Some(Ok(body))) => Ok(document_from_indexes(
-                            gallery.clone(),
-                            index,
-                            Some(comms),
-                            Some(body),
-                        )),
-                        (None, Some(Ok(body))) => Ok(document_from_indexes(
-                            gallery.clone(),
-                            index,
-                            None,
-                            Some(body),
-                        )),
-                        (Some(Ok(comms)), None) => Ok(document_from_indexes(
-                            gallery.clone(),
-                            index,
-                            Some(comms),
-                            None,
-                        )),
-                        (None, None) => {
-                            Ok(document_from_indexes(gallery.clone(), index, None, None))
-                        }
-                        (Some(Err(err)), _) => Err(err),
-                        (_, Some(Err(err))) => Err(err),
-                    }
+                    next.last_id = Some(next.last_id.map_or(id, |last_id| last_id.min(id)));
+                    self.document(&job.gallery, index).await
                 }
                 Err(err) => Err(err.into()),
             };
+            if doc.is_ok() {
+                next.published += 1;
+            }
             documents.push(doc);
         }
-        Ok(documents)
+        Ok((documents, next))
+    }
+    /// One result page of an in-gallery search. `search_pos` of `None` searches the newest chunk.
+    pub async fn search_page(
+        &mut self,
+        gallery: &GalleryIndex,
+        query: &str,
+        mode: SearchMode,
+        search_pos: Option<i64>,
+        page: usize,
+    ) -> Result<(Vec<Result<DocumentIndex, DocumentParseError>>, SearchPaging), CrawlerError> {
+        let mut path = format!(
+            "{}{}/lists/?id={}&s_type={}&{}&page={}",
+            self.host,
+            board_path(gallery.kind),
+            gallery.id,
+            mode.s_type(),
+            serde_urlencoded::to_string(&[("s_keyword", query)])?,
+            page
+        );
+        if let Some(pos) = search_pos {
+            path.push_str(&format!("&search_pos={}", pos));
+        }
+        let (e_s_n_o, parsed, paging, res) = self
+            .retry("search", || async {
+                let req = self.with_session(
+                    &gallery.id,
+                    Request::get(path.as_str()).header(
+                        "Referer",
+                        format!(
+                            "{}{}/lists/?id={}",
+                            self.host,
+                            board_path(gallery.kind),
+                            gallery.id
+                        ),
+                    ),
+                );
+                let res = self.transport.send(req).await?.error_for_status()?;
+                let text = res.text()?;
+                let parsed = self.sample_rows(
+                    PageKind::List,
+                    text,
+                    false,
+                    parse_document_indexes(text, &gallery.id, Utc::now()),
+                )?;
+                let paging = parse_search_paging(text, page);
+                Ok((parse_e_s_n_o(text).ok(), parsed, paging, res))
+            })
+            .await?;
+        self.update_session(gallery, e_s_n_o, &res);
+        Ok((parsed, paging))
+    }
+    /// Indexes of the documents matching `query`, newest first, walking every search chunk
+    /// down to the first document of the gallery.
+    pub fn search(
+        &'a mut self,
+        gallery: &'a GalleryIndex,
+        query: &'a str,
+        mode: SearchMode,
+    ) -> LocalBoxStream<'a, Result<DocumentIndex, CrawlerError>> {
+        let state = SearchState {
+            crawler: self,
+            gallery,
+            query,
+            mode,
+            search_pos: None,
+            page: 1,
+            pending: VecDeque::new(),
+            fetched_page: false,
+            done: false,
+        };
+        stream::unfold(state, |mut state| async move {
+            let index = state.next().await?;
+            Some((index, state))
+        })
+        .boxed_local()
+    }
+    fn with_session(&self, gallery_id: &str, req: Request) -> Request {
+        match self.sessions.get(gallery_id) {
+            Some(session) => session.apply(req),
+            None => req,
+        }
+    }
+    fn update_session(&mut self, gallery: &GalleryIndex, e_s_n_o: Option<String>, res: &Response) {
+        let ttl = self.session_ttl;
+        let session = self
+            .sessions
+            .entry(gallery.id.clone())
+            .or_insert_with(|| GallerySession::new(&gallery.id).ttl(ttl));
+        session.store_cookies(res);
+        if let Some(e_s_n_o) = e_s_n_o {
+            session.set_token(e_s_n_o, Instant::now());
+        }
+    }
+    /// A live `e_s_n_o` of the gallery, taken from the view page of `doc_id` if
+    /// no page of the gallery was fetched lately.
+    async fn session_token(
+        &mut self,
+        gallery: &GalleryIndex,
+        doc_id: usize,
+    ) -> Result<String, CrawlerError> {
+        let token = |crawler: &Self| {
+            crawler
+                .sessions
+                .get(&gallery.id)
+                .and_then(|session| session.token(Instant::now()))
+                .map(str::to_string)
+        };
+        if let Some(token) = token(self) {
+            return Ok(token);
+        }
+        self.document_view("session", gallery, doc_id, |body| {
+            parse_e_s_n_o(body).map(|_| ())
+        })
+        .await?;
+        token(self).ok_or(CrawlerError::DocumentParseError(
+            DocumentParseError::Select {
+                path: ".e_s_n_o",
+                context: SelectContext::default().document_id(doc_id),
+            },
+        ))
     }
     async fn _comments(
         &mut self,
         gallery: &GalleryIndex,
         doc_id: usize,
         page: usize,
-        last_root_comment_id: Option<usize>,
     ) -> Result<(Vec<Comment>, usize), CrawlerError> {
-        let path = format!("{}/board/comment", self.host);
-        if self.e_s_n_o.is_none() {
-            self.document_indexes(&gallery, 1).await?;
+        let mut refreshed = false;
+        loop {
+            let e_s_n_o = self.session_token(gallery, doc_id).await?;
+            match self
+                ._comments_with_token(gallery, doc_id, page, &e_s_n_o)
+                .await
+            {
+                Err(CrawlerError::CommentParseError(CommentParseError::InvalidToken))
+                    if !refreshed =>
+                {
+                    warn!("e_s_n_o of {} is refused. refresh it", gallery.id);
+                    if let Some(session) = self.sessions.get_mut(&gallery.id) {
+                        session.invalidate();
+                    }
+                    refreshed = true;
+                }
+                Err(err) => return Err(err),
+                Ok((res, http_res)) => {
+                    self.update_session(gallery, None, &http_res);
+                    return Ok(res);
+                }
+            }
         }
+    }
+    async fn _comments_with_token(
+        &self,
+        gallery: &GalleryIndex,
+        doc_id: usize,
+        page: usize,
+        e_s_n_o: &str,
+    ) -> Result<((Vec<Comment>, usize), Response), CrawlerError> {
+        let path = format!("{}/board/comment", self.host);
         let form = CommentsPostForm {
             id: &gallery.id,
             no: doc_id,
             cmt_id: &gallery.id,
             cmt_no: doc_id,
-            e_s_n_o: self.e_s_n_o.as_deref(),
+            e_s_n_o: Some(e_s_n_o),
             comment_page: page,
             sort: if page == 1 { "" } else { "D" },
             prevCnt: 0,
-            _GALLTYPE_: match gallery.kind {
-                GalleryKind::Major => "G",
-                GalleryKind::Minor => "M",
-                _ => panic!("other than major, minor gallery is not supported yet"),
-            },
+            _GALLTYPE_: galltype(gallery.kind),
         };
-        Ok(back_off!(1000, 1000 * 10, || async {
-            let bytes = self
-                .client
-                .post(path.as_str())
+        self.retry("comments", || async {
+            let req = Request::post_form(path.as_str(), &form)?
                 .header("Accept", "application/json, text/javascript, */*; q=0.01")
                 .header("Accept-Encoding", "gzip, deflate, br")
-                .header(
-                    "Content-Type",
-                    "application/x-www-form-urlencoded; charset=UTF-8",
-                )
-                .header("Origin", "https://gall.dcinside.com")
-                .header("Host", "gall.dcinside.com")
+                .header("Origin", self.host.as_str())
                 .header(
                     "Referer",
                     format!(
-                        "https://gall.dcinside.com/board/view/?id={}&no={}&_rk=tDl&page=1",
-                        gallery.id, doc_id
+                        "{}{}/view/?id={}&no={}&_rk=tDl&page=1",
+                        self.host,
+                        board_path(gallery.kind),
+                        gallery.id,
+                        doc_id
                     ),
                 )
                 .header("X-Requested-With", "XMLHttpRequest")
                 .header("Cache-Control", "no-cache")
-                .header("Pragma", "no-cache")
-                .send_form(&form)
+                .header("Pragma", "no-cache");
+            let res = self
+                .transport
+                .send(self.with_session(&gallery.id, req))
                 .await?
-                .body()
-                .limit(1024 * 1024 * 8)
-                .await?;
-            let text = std::str::from_utf8(&bytes)?;
-            Ok::<_, CrawlerError>(parse_comments(
+                .error_for_status()?;
+            let text = res.text()?;
+            let parsed = parse_comments(text, &gallery.id, doc_id, Utc::now());
+            if let Err(err) = &parsed {
+                self.sample_drift(PageKind::Comments, err.drift_key(), text);
+            }
+            let parsed = parsed?;
+            Ok((parsed, res))
+        })
+        .await
+    }
+    pub async fn document_indexes(
+        &mut self,
+        gallery: &GalleryIndex,
+        page: usize,
+    ) -> Result<Vec<Result<DocumentIndex, DocumentParseError>>, CrawlerError> {
+        self.filtered_document_indexes(gallery, &ListingFilter::default(), page)
+            .await
+    }
+    /// A listing page of the backend in use. Pages of the mobile site are smaller, so a
+    /// walk that falls back midway lists some documents again.
+    pub async fn filtered_document_indexes(
+        &mut self,
+        gallery: &GalleryIndex,
+        filter: &ListingFilter,
+        page: usize,
+    ) -> Result<Vec<Result<DocumentIndex, DocumentParseError>>, CrawlerError> {
+        if !self.uses_mobile() {
+            match self.pc_document_indexes(gallery, filter, page).await {
+                Err(err) if self.falls_back_to_mobile(&err) => {}
+                res => return res,
+            }
+        }
+        let mut query = mobile_listing_query(filter);
+        if !query.is_empty() {
+            query.push('&');
+        }
+        let path = format!(
+            "{}{}/{}?{}page={}",
+            self.mobile_host,
+            mobile_board_path(gallery.kind),
+            gallery.id,
+            query,
+            page
+        );
+        self.retry("mobile_document_indexes", || async {
+            let req = Request::get(path.as_str())
+                .header("User-Agent", MOBILE_USER_AGENT)
+                .header("Referer", format!("{}/", self.mobile_host));
+            let res = self.transport.send(req).await?.error_for_status()?;
+            let text = res.text()?;
+            Ok(self.sample_rows(
+                PageKind::MobileList,
                 text,
-                &gallery.id,
-                doc_id,
-                last_root_comment_id,
+                !filter.is_partial(),
+                parse_mobile_document_indexes(text, &gallery.id, Utc::now()),
             )?)
-        })?)
+        })
+        .await
     }
-    pub async fn document_indexes(
+    async fn pc_document_indexes(
         &mut self,
         gallery: &GalleryIndex,
+        filter: &ListingFilter,
         page: usize,
     ) -> Result<Vec<Result<DocumentIndex, DocumentParseError>>, CrawlerError> {
-        let path = match gallery.kind {
-            GalleryKind::Major => format!(
-                "{}/board/lists?id={}&list_num=100&page={}",
-                self.host, gallery.id, page
-            ),
-            GalleryKind::Minor => format!(
-                "{}/mgallery/board/lists?id={}&list_num=100&page={}",
-                self.host, gallery.id, page
-            ),
-            _ => panic!("mini gallery kind not supported yet"),
-        };
-        let (e_s_n_o, res) = back_off!(
-            1000,
-            1000 * 10,
-            CrawlerError::PageNotFound
-                | CrawlerError::DocumentParseError(DocumentParseError::AdultPage)
-                | CrawlerError::DocumentParseError(DocumentParseError::MinorGalleryClosed)
-                | CrawlerError::DocumentParseError(DocumentParseError::MinorGalleryPromoted)
-                | CrawlerError::DocumentParseError(
-                    DocumentParseError::MinorGalleryAccessNotAllowed
-                ),
-            || async {
-                let mut res = self
-                    .client
-                    .get(path.as_str())
-                    .header("Referer", "https://gall.dcinside.com/")
-                    .send()
-                    .await?;
-                if res.status() == StatusCode::NOT_FOUND {
-                    return Err(CrawlerError::PageNotFound);
-                }
-                let bytes = res.body().limit(1024 * 1024 * 8).await?;
-                let text = std::str::from_utf8(&bytes)?;
-                let parsed = parse_document_indexes(text, &gallery.id)?;
-                let e_s_n_o = Some(
-                    HTMLDocument::from(text)
-                        .select(Attr("id", "e_s_n_o"))
-                        .next()
-                        .ok_or(DocumentParseError::Select {
-                            path: ".e_s_n_o",
-                            html: text.to_string(),
-                        })?
-                        .attr("value")
-                        .ok_or(DocumentParseError::Select {
-                            path: ".e_s_n_o@value",
-                            html: text.to_string(),
-                        })?
-                        .to_string(),
+        let path = format!(
+            "{}{}/lists?id={}&{}&page={}",
+            self.host,
+            board_path(gallery.kind),
+            gallery.id,
+            filter.query(),
+            page
+        );
+        let (e_s_n_o, parsed, info, res) = self
+            .retry("document_indexes", || async {
+                let req = self.with_session(
+                    &gallery.id,
+                    Request::get(path.as_str()).header("Referer", format!("{}/", self.host)),
                 );
-                Ok::<_, CrawlerError>((e_s_n_o, parsed))
-            }
-        )?;
-        self.e_s_n_o = e_s_n_o;
-        Ok(res)
+                let res = self.transport.send(req).await?.error_for_status()?;
+                let text = res.text()?;
+                let parsed = self.sample_rows(
+                    PageKind::List,
+                    text,
+                    !filter.is_partial(),
+                    parse_document_indexes(text, &gallery.id, Utc::now()),
+                )?;
+                // an empty first page without the list itself is a stub served to a blocked
+                // egress. Empty galleries, notices only and row drift keep the skeleton
+                if let (1, true, false, Some(egress)) =
+                    (page, parsed.is_empty(), filter.is_partial(), &res.egress)
+                {
+                    if !text.contains("id=\"e_s_n_o\"") || !text.contains("class=\"gall_list\"") {
+                        self.transport.bench(egress);
+                        return Err(CrawlerError::EgressBlocked(egress.clone()));
+                    }
+                }
+                let e_s_n_o = parse_e_s_n_o(text)?;
+                // every listing page shows the information box, and walks may start past the first
+                let info = parse_gallery_info(text);
+                Ok((e_s_n_o, parsed, info, res))
+            })
+            .await?;
+        self.update_session(gallery, Some(e_s_n_o), &res);
+        self.gallery_infos
+            .borrow_mut()
+            .insert(gallery.id.clone(), info);
+        Ok(parsed)
     }
 }
 impl Default for Crawler {
@@ -490,12 +1219,246 @@ impl Default for Crawler {
     }
 }
 
+struct SearchState<'a> {
+    crawler: &'a mut Crawler,
+    gallery: &'a GalleryIndex,
+    query: &'a str,
+    mode: SearchMode,
+    search_pos: Option<i64>,
+    page: usize,
+    pending: VecDeque<Result<DocumentIndex, DocumentParseError>>,
+    fetched_page: bool,
+    done: bool,
+}
+impl<'a> SearchState<'a> {
+    async fn next(&mut self) -> Option<Result<DocumentIndex, CrawlerError>> {
+        loop {
+            if let Some(res) = self.pending.pop_front() {
+                return Some(res.map_err(CrawlerError::from));
+            }
+            if self.done {
+                return None;
+            }
+            if self.fetched_page {
+                actix::clock::delay_for(self.crawler.delay).await;
+            }
+            self.fetched_page = true;
+            let (indexes, paging) = match self
+                .crawler
+                .search_page(
+                    self.gallery,
+                    self.query,
+                    self.mode,
+                    self.search_pos,
+                    self.page,
+                )
+                .await
+            {
+                Ok(res) => res,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            };
+            self.pending.extend(indexes);
+            match paging {
+                SearchPaging {
+                    has_next_page: true,
+                    ..
+                } => self.page += 1,
+                SearchPaging {
+                    next_search_pos: Some(pos),
+                    ..
+                } if Some(pos) != self.search_pos => {
+                    self.search_pos = Some(pos);
+                    self.page = 1;
+                }
+                _ => self.done = true,
+            }
+        }
+    }
+}
+
+struct DocumentStreamState<'a> {
+    crawler: &'a mut Crawler,
+    gallery: &'a GalleryIndex,
+    filter: ListingFilter,
+    last_document_id: usize,
+    pages: Range<usize>,
+    pending: VecDeque<Result<DocumentIndex, DocumentParseError>>,
+    fetched_page: bool,
+    done: bool,
+    progress: Rc<Cell<CrawlProgress>>,
+    listed_ids: Rc<RefCell<Vec<usize>>>,
+}
+impl<'a> DocumentStreamState<'a> {
+    async fn next(&mut self) -> Option<Result<Document, CrawlerError>> {
+        loop {
+            if let Some(res) = self.pending.pop_front() {
+                let doc = match res {
+                    Ok(index) => self.crawler.document(self.gallery, index).await,
+                    Err(err) => Err(err.into()),
+                };
+                if let Ok(doc) = &doc {
+                    let mut progress = self.progress.get();
+                    progress.last_document_id = Some(doc.id);
+                    progress.newest_document_id = progress.newest_document_id.max(Some(doc.id));
+                    progress.crawled_document_count += 1;
+                    self.progress.set(progress);
+                }
+                return Some(doc);
+            }
+            if self.done {
+                return None;
+            }
+            let page = self.pages.next()?;
+            if self.fetched_page {
+                actix::clock::delay_for(self.crawler.delay).await;
+            }
+            self.fetched_page = true;
+            let mut progress = self.progress.get();
+            progress.page = page;
+            let indexes = match self
+                .crawler
+                .filtered_document_indexes(self.gallery, &self.filter, page)
+                .await
+            {
+                Ok(indexes) => indexes,
+                Err(err) => {
+                    self.done = true;
+                    progress.aborted = true;
+                    self.progress.set(progress);
+                    return Some(Err(err));
+                }
+            };
+            self.progress.set(progress);
+            match indexes.iter().rev().find_map(|d| d.as_ref().ok()) {
+                None => return None,
+                Some(oldest) if oldest.id <= self.last_document_id => self.done = true,
+                _ => (),
+            }
+            let last_document_id = self.last_document_id;
+            self.pending.extend(indexes.into_iter().filter(|t| match t {
+                Ok(d) => d.id > last_document_id,
+                Err(_e) => true,
+            }));
+            self.listed_ids.borrow_mut().extend(
+                self.pending
+                    .iter()
+                    .filter_map(|t| t.as_ref().ok().map(|d| d.id)),
+            );
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::body::BodyFetchPolicy;
+    use crate::transport::{FixtureTransport, Response};
+    use actix_web::http::StatusCode;
+
+    fn fixtures() -> Rc<FixtureTransport> {
+        Rc::new(
+            FixtureTransport::new()
+                .route(
+                    "/json0/gallmain/gallery_hot.php",
+                    Response::ok(include_str!("../assets/weekly_hot_galleries.jsonp")),
+                )
+                .route(
+                    "/json1/ranking_gallery.php",
+                    Response::ok(include_str!("../assets/realtime_hot_galleries.jsonp")),
+                )
+                .route(
+                    "/json1/mgallmain/mgallery_ranking.php",
+                    Response::ok(include_str!("../assets/realtime_hot_minor_galleries.jsonp")),
+                )
+                .route(
+                    "/json1/migallmain/migallery_ranking.php",
+                    Response::ok(include_str!("../assets/realtime_hot_mini_galleries.jsonp")),
+                )
+                .route(
+                    "search_pos=-9950000",
+                    Response::ok("<html><body></body></html>"),
+                )
+                .route(
+                    "s_keyword=",
+                    Response::ok(include_str!("../assets/search.html")),
+                )
+                .route(
+                    "/m/category?cate=4&page=1",
+                    Response::ok(include_str!("../assets/catalog.html")),
+                )
+                .route(
+                    "/m/category?cate=",
+                    Response::ok("<table class=\"gall_catalog\"></table>"),
+                )
+                .route(
+                    "/m/category",
+                    Response::ok(include_str!("../assets/catalog_categories.html")),
+                )
+                .route(
+                    "m.dcinside.com/ajax/response-comment",
+                    Response::ok(include_str!("../assets/mobile_comments.html")),
+                )
+                .route(
+                    "m.dcinside.com/board/programming/",
+                    Response::ok(include_str!("../assets/mobile_view.html")),
+                )
+                .route(
+                    "m.dcinside.com/board/programming?",
+                    Response::ok(include_str!("../assets/mobile_list.html")),
+                )
+                .route(
+                    "/mini/board/lists",
+                    Response::ok(include_str!("../assets/gallery.html")),
+                )
+                .route(
+                    "/mgallery/board/lists",
+                    Response::ok(include_str!("../assets/minor_gallery.html")),
+                )
+                .route(
+                    "/board/lists?id=notfound",
+                    Response::ok("").with_status(StatusCode::NOT_FOUND),
+                )
+                .route(
+                    "/board/lists",
+                    Response::ok(include_str!("../assets/gallery.html")),
+                )
+                .route(
+                    "/board/view/?id=programming&no=404&",
+                    Response::ok("").with_status(StatusCode::NOT_FOUND),
+                )
+                .route(
+                    "/board/view/?id=programming&no=405&",
+                    Response::ok(include_str!("../assets/deleted.html")),
+                )
+                .route(
+                    "/board/view",
+                    Response::ok(include_str!("../assets/body.html")),
+                )
+                .route("/download.php", Response::ok("gif89a"))
+                .route(
+                    "/board/comment",
+                    Response::ok(include_str!("../assets/comments.json")),
+                ),
+        )
+    }
+    fn crawler(transport: &Rc<FixtureTransport>) -> Crawler {
+        Crawler::new().transport(transport.clone()).delay(0)
+    }
+    fn programming() -> GalleryIndex {
+        GalleryIndex {
+            id: String::from("programming"),
+            name: String::from("프로그래밍"),
+            kind: GalleryKind::Major,
+            rank: None,
+        }
+    }
+
     #[actix_rt::test]
     async fn weekly_hot_galleries() {
-        let crawler = Crawler::new();
+        let crawler = crawler(&fixtures());
         let res = crawler.weekly_hot_galleries().await.unwrap();
         assert!(!res.is_empty());
         assert!(!res[0].id.is_empty());
@@ -504,16 +1467,17 @@ mod tests {
     }
     #[actix_rt::test]
     async fn realtime_hot_galleries() {
-        let crawler = Crawler::new();
+        let crawler = crawler(&fixtures());
         let res = crawler.realtime_hot_galleries().await.unwrap();
         assert!(!res.is_empty());
         assert!(!res[0].id.is_empty());
         assert!(!res[0].name.is_empty());
         assert_eq!(res[0].kind, GalleryKind::Major);
+        assert_eq!(res[0].rank, Some(1));
     }
     #[actix_rt::test]
     async fn realtime_hot_minor_galleries() {
-        let crawler = Crawler::new();
+        let crawler = crawler(&fixtures());
         let res = crawler.realtime_hot_minor_galleries().await.unwrap();
         assert!(!res.is_empty());
         assert!(!res[0].id.is_empty());
@@ -521,17 +1485,23 @@ mod tests {
         assert_eq!(res[0].kind, GalleryKind::Minor);
     }
     #[actix_rt::test]
+    async fn catalog() {
+        let transport = fixtures();
+        let crawler = crawler(&transport);
+        let res = crawler.catalog(GalleryKind::Minor).await.unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].index.id, "github");
+        assert_eq!(res[0].info.category.as_deref(), Some("교육/금융/IT"));
+        // the first three categories are empty, the last one has a single page
+        assert_eq!(transport.requests().len(), 1 + 3 + 2);
+    }
+    #[actix_rt::test]
     async fn document_indexes() {
-        let mut crawler = Crawler::new();
-        let gallery = GalleryIndex {
-            id: String::from("programming"),
-            name: String::from("프로그래밍"),
-            kind: GalleryKind::Major,
-            rank: None,
-        };
-        let res = crawler.document_indexes(&gallery, 1).await.unwrap();
+        let transport = fixtures();
+        let mut crawler = crawler(&transport);
+        let res = crawler.document_indexes(&programming(), 1).await.unwrap();
         assert!(!res.is_empty());
-        assert!(res.len() >= 90);
+        assert!(res.len() >= 20);
         assert!(res.iter().any(|d| match d {
             Ok(d) => d.comment_count > 0,
             Err(_) => false,
@@ -540,19 +1510,59 @@ mod tests {
             Ok(d) => DocumentKind::Picture == d.kind,
             Err(_) => false,
         }));
+        let requests = transport.requests();
+        assert_eq!(
+            requests[0].url,
+            "https://gall.dcinside.com/board/lists?id=programming&list_num=100&page=1"
+        );
+    }
+    #[actix_rt::test]
+    async fn filtered_document_indexes() {
+        let transport = fixtures();
+        let mut crawler = crawler(&transport);
+        let filter = ListingFilter::recommend().search_head(10).list_num(50);
+        let res = crawler
+            .filtered_document_indexes(&programming(), &filter, 2)
+            .await
+            .unwrap();
+        assert!(!res.is_empty());
+        assert_eq!(
+            transport.requests()[0].url,
+            "https://gall.dcinside.com/board/lists?id=programming&list_num=50&exception_mode=recommend&search_head=10&page=2"
+        );
+    }
+    #[actix_rt::test]
+    async fn search() {
+        let transport = fixtures();
+        let mut crawler = crawler(&transport);
+        let gallery = programming();
+        let res: Vec<_> = crawler
+            .search(&gallery, "러스트 async", SearchMode::TitleContent)
+            .collect()
+            .await;
+        assert!(res.len() >= 20);
+        assert!(res.iter().all(|d| d.is_ok()));
+        let urls: Vec<_> = transport.requests().into_iter().map(|r| r.url).collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://gall.dcinside.com/board/lists/?id=programming&s_type=search_subject_memo&s_keyword=%EB%9F%AC%EC%8A%A4%ED%8A%B8+async&page=1",
+                "https://gall.dcinside.com/board/lists/?id=programming&s_type=search_subject_memo&s_keyword=%EB%9F%AC%EC%8A%A4%ED%8A%B8+async&page=1&search_pos=-9950000",
+            ]
+        );
     }
     #[actix_rt::test]
     async fn minor_document_indexes() {
-        let mut crawler = Crawler::new();
+        let mut crawler = crawler(&fixtures());
         let gallery = GalleryIndex {
             id: String::from("tenbagger"),
             name: String::from("해외주식"),
             kind: GalleryKind::Minor,
-            rank: None,
+            rank: Some(3),
         };
         let res = crawler.document_indexes(&gallery, 1).await.unwrap();
         assert!(!res.is_empty());
-        assert!(res.len() >= 90);
+        assert!(res.len() >= 20);
         assert!(res.iter().any(|d| match d {
             Ok(d) => d.comment_count > 0,
             Err(_) => false,
@@ -561,18 +1571,70 @@ mod tests {
             Ok(d) => DocumentKind::Picture == d.kind,
             Err(_) => false,
         }));
+        let info = crawler.gallery_info(&gallery).unwrap();
+        assert_eq!(info.manager_id.as_deref(), Some("kimtpwjd"));
+        assert_eq!(info.rank, Some(3));
     }
     #[actix_rt::test]
-    async fn comments() {
-        let mut crawler = Crawler::new();
+    async fn gallery_info_shared_by_clones() {
+        let crawler = crawler(&fixtures());
         let gallery = GalleryIndex {
-            id: String::from("programming"),
-            name: String::from("프로그래밍"),
-            kind: GalleryKind::Major,
+            id: String::from("tenbagger"),
+            name: String::from("해외주식"),
+            kind: GalleryKind::Minor,
             rank: None,
         };
-        let res = crawler.comments(&gallery, 1595404).await.unwrap();
+        // workers clone the crawler for each gallery and start past the first page
+        let mut clone = crawler.clone();
+        clone.document_indexes(&gallery, 2).await.unwrap();
+        let info = crawler.gallery_info(&gallery).unwrap();
+        assert_eq!(info.manager_id.as_deref(), Some("kimtpwjd"));
+    }
+    #[actix_rt::test]
+    async fn realtime_hot_mini_galleries() {
+        let crawler = crawler(&fixtures());
+        let res = crawler.realtime_hot_mini_galleries().await.unwrap();
         assert!(!res.is_empty());
+        assert!(!res[0].id.is_empty());
+        assert_eq!(res[0].kind, GalleryKind::Mini);
+    }
+    #[actix_rt::test]
+    async fn mini_comments() {
+        let transport = fixtures();
+        let mut crawler = crawler(&transport);
+        let gallery = GalleryIndex {
+            id: String::from("lilpa"),
+            name: String::from("릴파"),
+            kind: GalleryKind::Mini,
+            rank: None,
+        };
+        let res = crawler.comments(&gallery, 1).await.unwrap();
+        assert!(!res.is_empty());
+        let requests = transport.requests();
+        assert!(requests[0]
+            .url
+            .starts_with("https://gall.dcinside.com/mini/board/view/?id=lilpa&no=1"));
+        let comment_request = requests
+            .into_iter()
+            .find(|r| r.url.ends_with("/board/comment"))
+            .unwrap();
+        assert!(comment_request.form.unwrap().contains("_GALLTYPE_=MI"));
+    }
+    #[actix_rt::test]
+    async fn document_indexes_not_found() {
+        let mut crawler = crawler(&fixtures());
+        let gallery = GalleryIndex {
+            id: String::from("notfound"),
+            ..programming()
+        };
+        let res = crawler.document_indexes(&gallery, 1).await;
+        assert!(matches!(res, Err(CrawlerError::PageNotFound)));
+    }
+    #[actix_rt::test]
+    async fn comments() {
+        let transport = fixtures();
+        let mut crawler = crawler(&transport);
+        let res = crawler.comments(&programming(), 1595404).await.unwrap();
         assert!(!res.is_empty());
         for c in res {
             match c.author.kind {
@@ -581,61 +1643,494 @@ mod tests {
                 UserKind::Unknown => assert_eq!(c.author.nickname, "댓글돌이".to_string()),
             }
         }
-        /*assert!(res.iter().any(|c| d.comment_count > 0));
-        assert!(res.iter().any(|c| if DocumentKind::Picture == d.kind {
-            true
-        } else {
-            false
-        }));*/
+        let requests = transport.requests();
+        assert!(!requests.iter().any(|r| r.url.contains("/lists")));
+        let comment_request = requests
+            .into_iter()
+            .find(|r| r.url.ends_with("/board/comment"))
+            .unwrap();
+        // taken from the view page of the document
+        assert!(comment_request
+            .form
+            .unwrap()
+            .contains("e_s_n_o=3eabc219ebdd65f53e"));
     }
-    /*
     #[actix_rt::test]
-    async fn minor_comments() {
-        let mut crawler = Crawler::new();
-        let gallery = GalleryIndex {
-            id: String::from("tenbagger"),
-            name: String::from("해외주식"),
-            kind: GalleryKind::Minor,
-            rank: None,
-        };
-        let res = crawler.comments(&gallery, 1962073).await.unwrap();
+    async fn comments_reuse_listing_session() {
+        let transport = Rc::new(
+            FixtureTransport::new()
+                .route(
+                    "/board/lists",
+                    Response::ok(include_str!("../assets/gallery.html"))
+                        .header("Set-Cookie", "PHPSESSID=1234; path=/"),
+                )
+                .route(
+                    "/board/comment",
+                    Response::ok(include_str!("../assets/comments.json")),
+                ),
+        );
+        let mut crawler = crawler(&transport);
+        crawler.document_indexes(&programming(), 1).await.unwrap();
+        crawler.comments(&programming(), 1595404).await.unwrap();
+        let requests = transport.requests();
+        assert!(!requests.iter().any(|r| r.url.contains("/view")));
+        let comment_request = requests
+            .into_iter()
+            .find(|r| r.url.ends_with("/board/comment"))
+            .unwrap();
+        assert!(comment_request
+            .headers
+            .contains(&("Cookie".to_string(), "PHPSESSID=1234".to_string())));
+        assert!(comment_request
+            .form
+            .unwrap()
+            .contains("e_s_n_o=3eabc219ebdd65f437"));
+    }
+    /// Refuses the first comment request as if its token expired.
+    struct RefuseOnce {
+        inner: Rc<FixtureTransport>,
+        refused: Cell<bool>,
+    }
+    impl Transport for RefuseOnce {
+        fn send(
+            &self,
+            req: Request,
+        ) -> futures::future::LocalBoxFuture<'_, Result<Response, CrawlerError>> {
+            if req.url.ends_with("/board/comment") && !self.refused.replace(true) {
+                return Box::pin(futures::future::ready(Ok(Response::ok(""))));
+            }
+            self.inner.send(req)
+        }
+    }
+    #[actix_rt::test]
+    async fn comments_refresh_refused_token() {
+        let transport = fixtures();
+        let mut crawler = crawler(&transport).transport(RefuseOnce {
+            inner: transport.clone(),
+            refused: Cell::new(false),
+        });
+        crawler.document_indexes(&programming(), 1).await.unwrap();
+        let res = crawler.comments(&programming(), 1595404).await.unwrap();
         assert!(!res.is_empty());
-        assert!(res.len() >= 1);
-        assert!(!res.iter().any(|c| match &c.author {
-            User::Static { id, .. } => id.is_empty(),
-            User::Dynamic { ip, .. } => ip.is_empty(),
-            _ => false,
-        }));
-        /*assert!(res.iter().any(|c| d.comment_count > 0));
-        assert!(res.iter().any(|c| if DocumentKind::Picture == d.kind {
-            true
-        } else {
-            false
-        }));*/
+        let requests = transport.requests();
+        assert_eq!(
+            requests.iter().filter(|r| r.url.contains("/view")).count(),
+            1
+        );
+        assert!(requests
+            .iter()
+            .filter(|r| r.url.ends_with("/board/comment"))
+            .all(|r| r
+                .form
+                .as_ref()
+                .unwrap()
+                .contains("e_s_n_o=3eabc219ebdd65f53e")));
     }
-    */
-    /*
     #[actix_rt::test]
-    async fn documents() {
-        let mut crawler = Crawler::new();
-        let gallery = GalleryIndex {
-            id: String::from("lovegame"),
-            name: String::from("이승기"),
-            kind: GalleryKind::Major,
-            rank: None,
-        };
-        let res = crawler.documents(&gallery, 2).await.unwrap();
+    async fn mobile_backend() {
+        let transport = fixtures();
+        let mut crawler =
+            crawler(&transport)
+                .backend(Backend::Mobile)
+                .body_fetch(BodyFetchPolicy {
+                    per_gallery_budget: 10,
+                    per_host_budget: 10,
+                    delay: Duration::from_millis(0),
+                    jitter: Duration::from_millis(0),
+                    ..Default::default()
+                });
+        let res = crawler.documents(&programming(), 1).await.unwrap();
+        assert_eq!(res.len(), 3);
+        let doc = res[0].as_ref().unwrap();
+        assert_eq!(doc.id, 1595404);
+        assert_eq!(doc.comments.as_ref().unwrap()[1].parent_id, Some(13369033));
+        assert!(doc.body.as_ref().unwrap().text.starts_with("tokio 0.2"));
+        let requests = transport.requests();
+        assert!(requests
+            .iter()
+            .all(|r| r.url.starts_with("https://m.dcinside.com/")));
+        assert!(requests.iter().all(|r| r
+            .headers
+            .contains(&("User-Agent".to_string(), MOBILE_USER_AGENT.to_string()))));
+        assert!(requests[0].url.ends_with("/board/programming?page=1"));
+    }
+    #[actix_rt::test]
+    async fn mobile_fallback() {
+        let transport = Rc::new(
+            FixtureTransport::new()
+                .route(
+                    "m.dcinside.com/board/programming?",
+                    Response::ok(include_str!("../assets/mobile_list.html")),
+                )
+                .route(
+                    "/board/lists",
+                    Response::ok(include_str!("../assets/blocked_captcha.html")),
+                ),
+        );
+        let mut crawler = crawler(&transport);
+        assert!(crawler.document_indexes(&programming(), 1).await.is_err());
+
+        let mut crawler = crawler.mobile_fallback(Duration::from_secs(60));
+        // as the worker clones one for each gallery
+        let mut other = crawler.clone();
+        let res = crawler
+            .filtered_document_indexes(&programming(), &ListingFilter::recommend(), 1)
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 3);
+        other.document_indexes(&programming(), 2).await.unwrap();
+        let urls: Vec<_> = transport.requests().into_iter().map(|r| r.url).collect();
+        assert_eq!(
+            urls.iter().filter(|u| u.contains("/board/lists")).count(),
+            2
+        );
+        assert!(urls[2].ends_with("/board/programming?recommend=1&page=1"));
+        assert!(urls[3].ends_with("/board/programming?page=2"));
+    }
+    #[actix_rt::test]
+    async fn documents_after() {
+        let mut crawler = crawler(&fixtures());
+        let indexes: Vec<_> = parse_document_indexes(
+            include_str!("../assets/gallery.html"),
+            "programming",
+            Utc::now(),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|d| d.unwrap())
+        .collect();
+        let last_document_id = indexes[indexes.len() / 2].id;
+        let (res, err) = crawler
+            .documents_after(&programming(), last_document_id, 1)
+            .await;
+        assert!(err.is_none());
         assert!(!res.is_empty());
-        assert!(res.len() >= 1);
+        assert_eq!(
+            res.len(),
+            indexes.iter().filter(|d| d.id > last_document_id).count()
+        );
         for d in res {
-            assert!(d.is_ok());
+            let d = d.unwrap();
+            assert!(d.id > last_document_id);
+            assert_eq!(d.comments.is_some(), d.comment_count > 0);
         }
-        /*assert!(res.iter().any(|c| d.comment_count > 0));
-        assert!(res.iter().any(|c| if DocumentKind::Picture == d.kind {
-            true
-        } else {
-            false
-        }));*/
     }
-    */
+    #[actix_rt::test]
+    async fn empty_first_page_benches_only_without_list() {
+        let notices_only = include_str!("../assets/gallery.html").replace("us-post", "us-notice");
+        let transport = Rc::new(
+            FixtureTransport::new()
+                .route(
+                    "/board/lists?id=notices",
+                    Response::ok(notices_only).egress("direct"),
+                )
+                .route(
+                    "/board/lists",
+                    Response::ok("<html><body></body></html>").egress("direct"),
+                ),
+        );
+        let mut crawler = crawler(&transport).retry_policy(RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        });
+        let mut gallery = programming();
+        gallery.id = String::from("notices");
+        assert!(crawler
+            .document_indexes(&gallery, 1)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            crawler.document_indexes(&programming(), 1).await,
+            Err(CrawlerError::EgressBlocked(_))
+        ));
+    }
+    #[actix_rt::test]
+    async fn documents_after_keeps_documents_on_abort() {
+        let transport = Rc::new(
+            FixtureTransport::new()
+                .route(
+                    "&page=2",
+                    Response::ok("").with_status(StatusCode::NOT_FOUND),
+                )
+                .route(
+                    "/board/lists",
+                    Response::ok(include_str!("../assets/gallery.html")),
+                )
+                .route(
+                    "/board/view",
+                    Response::ok(include_str!("../assets/body.html")),
+                )
+                .route(
+                    "/board/comment",
+                    Response::ok(include_str!("../assets/comments.json")),
+                ),
+        );
+        let mut crawler = crawler(&transport);
+        let (res, err) = crawler.documents_after(&programming(), 1, 1).await;
+        assert!(matches!(err, Some(CrawlerError::PageNotFound)));
+        assert!(!res.is_empty());
+        assert!(res.iter().all(|d| d.is_ok()));
+    }
+    #[actix_rt::test]
+    async fn document_stream() {
+        let transport = fixtures();
+        let mut crawler = crawler(&transport);
+        let gallery = programming();
+        let mut stream = crawler.document_stream(&gallery, 0, 1..MAX_LIST_PAGE);
+        assert_eq!(stream.progress(), CrawlProgress::default());
+        let first = stream.next().await.unwrap().unwrap();
+        let second = stream.next().await.unwrap().unwrap();
+        assert!(first.id > second.id);
+        assert_eq!(
+            stream.progress(),
+            CrawlProgress {
+                page: 1,
+                last_document_id: Some(second.id),
+                newest_document_id: Some(first.id),
+                crawled_document_count: 2,
+                aborted: false,
+            }
+        );
+        let listed = stream.take_listed_ids();
+        assert_eq!(&listed[..2], &[first.id, second.id]);
+        assert!(stream.take_listed_ids().is_empty());
+        drop(stream);
+        let lists = transport
+            .requests()
+            .iter()
+            .filter(|r| r.url.contains("/board/lists"))
+            .count();
+        assert_eq!(lists, 1);
+    }
+    #[actix_rt::test]
+    async fn backfill_page() {
+        let mut backfill = crawler(&fixtures());
+        let mut job = BackfillJob::new(programming()).start_page(3);
+        let (docs, next) = backfill.backfill_page(&job).await.unwrap();
+        let ids: Vec<_> = docs.iter().map(|d| d.as_ref().unwrap().id).collect();
+        assert!(!ids.is_empty());
+        assert_eq!(next.page, 4);
+        assert_eq!(next.last_id, ids.iter().min().cloned());
+        assert_eq!(next.published, ids.len());
+        assert!(!next.done);
+
+        // the fixture serves the same page again, which is all above the checkpoint
+        job.checkpoint = next.clone();
+        let (docs, again) = backfill.backfill_page(&job).await.unwrap();
+        assert!(docs.is_empty());
+        assert_eq!(again.last_id, next.last_id);
+        assert_eq!(again.page, 5);
+
+        let job = BackfillJob::new(programming()).until_id(ids[1]);
+        let (docs, next) = backfill.backfill_page(&job).await.unwrap();
+        assert_eq!(docs.len(), 1);
+        assert!(next.done);
+
+        // rows that fail to parse are an error rather than the end of the gallery
+        let changed = include_str!("../assets/gallery.html")
+            .replace("class=\"gall_count\"", "class=\"gall_views\"");
+        let transport =
+            Rc::new(FixtureTransport::new().route("/board/lists", Response::ok(changed)));
+        let job = BackfillJob::new(programming()).start_page(3);
+        assert!(crawler(&transport).backfill_page(&job).await.is_err());
+
+        // a page without rows is
+        let changed = include_str!("../assets/gallery.html").replace("us-post", "us-row");
+        let transport =
+            Rc::new(FixtureTransport::new().route("/board/lists", Response::ok(changed)));
+        let (docs, next) = crawler(&transport).backfill_page(&job).await.unwrap();
+        assert!(docs.is_empty());
+        assert!(next.done);
+    }
+    #[actix_rt::test]
+    async fn document_stream_aborts() {
+        let mut crawler = crawler(&fixtures());
+        let mut gallery = programming();
+        gallery.id = String::from("notfound");
+        let mut stream = crawler.document_stream(&gallery, 0, 1..MAX_LIST_PAGE);
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(CrawlerError::PageNotFound))
+        ));
+        assert!(stream.progress().aborted);
+        assert!(stream.next().await.is_none());
+    }
+    #[actix_rt::test]
+    async fn documents_with_bodies() {
+        let transport = fixtures();
+        let mut crawler = crawler(&transport).body_fetch(BodyFetchPolicy {
+            per_gallery_budget: 2,
+            per_host_budget: 10,
+            delay: Duration::from_millis(0),
+            jitter: Duration::from_millis(0),
+            min_comment_count: Some(1),
+            ..BodyFetchPolicy::default()
+        });
+        let res = crawler.documents(&programming(), 1).await.unwrap();
+        let with_body: Vec<_> = res
+            .into_iter()
+            .map(|d| d.unwrap())
+            .filter(|d| d.body.is_some())
+            .collect();
+        assert_eq!(with_body.len(), 2);
+        assert!(with_body.iter().all(|d| d.comment_count >= 1));
+        let body = with_body[0].body.as_ref().unwrap();
+        assert!(body.text.contains("백신"));
+        assert!(body.html.is_none());
+        assert_eq!(
+            transport
+                .requests()
+                .iter()
+                .filter(|r| r.url.contains("/board/view/"))
+                .count(),
+            2
+        );
+    }
+    #[actix_rt::test]
+    async fn documents_with_attachments() {
+        let transport = fixtures();
+        let root = std::env::temp_dir().join(format!("image-store-{}", rand::random::<u64>()));
+        let store = ImageStore::new(&root);
+        let mut large = crawler(&transport)
+            .body_fetch(BodyFetchPolicy {
+                per_gallery_budget: 1,
+                per_host_budget: 10,
+                delay: Duration::from_millis(0),
+                jitter: Duration::from_millis(0),
+                ..BodyFetchPolicy::default()
+            })
+            .image_fetch(
+                ImageFetchPolicy {
+                    max_per_document: 1,
+                    ..ImageFetchPolicy::default()
+                },
+                store.clone(),
+            );
+        let res = large.documents(&programming(), 1).await.unwrap();
+        let body = res.into_iter().find_map(|d| d.unwrap().body).unwrap();
+        let downloaded = &body.attachments[0];
+        assert_eq!(downloaded.size, Some(6));
+        assert!(store.contains(downloaded.sha1.as_ref().unwrap()));
+        assert_eq!(body.attachments[1].sha1, None);
+        let downloads: Vec<_> = transport
+            .requests()
+            .into_iter()
+            .filter(|r| r.url.contains("/download.php"))
+            .collect();
+        assert_eq!(downloads.len(), 1);
+        assert!(downloads[0]
+            .headers
+            .iter()
+            .any(|(k, v)| k == "Referer" && v.contains("/board/view/?id=programming")));
+        assert_eq!(
+            downloads[0].body_limit,
+            Some(ImageFetchPolicy::default().max_bytes)
+        );
+
+        // a larger file is refused by the transport, but its size is still noted
+        let transport = fixtures();
+        let store = ImageStore::new(root.join("small"));
+        let mut small = crawler(&transport)
+            .body_fetch(BodyFetchPolicy {
+                per_gallery_budget: 1,
+                per_host_budget: 10,
+                delay: Duration::from_millis(0),
+                jitter: Duration::from_millis(0),
+                ..BodyFetchPolicy::default()
+            })
+            .image_fetch(
+                ImageFetchPolicy {
+                    max_per_document: 1,
+                    max_bytes: 4,
+                    ..ImageFetchPolicy::default()
+                },
+                store,
+            );
+        let res = small.documents(&programming(), 1).await.unwrap();
+        let body = res.into_iter().find_map(|d| d.unwrap().body).unwrap();
+        assert_eq!(body.attachments[0].size, Some(6));
+        assert_eq!(body.attachments[0].sha1, None);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+    #[actix_rt::test]
+    async fn drift_samples() {
+        let changed = include_str!("../assets/gallery.html")
+            .replace("class=\"gall_count\"", "class=\"gall_views\"");
+        let transport =
+            Rc::new(FixtureTransport::new().route("/board/lists", Response::ok(changed.clone())));
+        let root = std::env::temp_dir().join(format!("drift-{}", rand::random::<u64>()));
+        let corpus = DriftCorpus::new(&root);
+        let mut listing = crawler(&transport).drift_corpus(corpus.clone());
+        let rows = listing.document_indexes(&programming(), 1).await.unwrap();
+        assert!(rows.iter().all(|row| row.is_err()));
+        let samples = corpus.samples().unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].0, PageKind::List);
+        assert_eq!(std::fs::read_to_string(&samples[0].2).unwrap(), changed);
+        std::fs::remove_dir_all(root).unwrap();
+
+        // rows no longer found at all
+        let changed = include_str!("../assets/gallery.html").replace("us-post", "us-row");
+        let transport =
+            Rc::new(FixtureTransport::new().route("/board/lists", Response::ok(changed)));
+        let root = std::env::temp_dir().join(format!("drift-{}", rand::random::<u64>()));
+        let corpus = DriftCorpus::new(&root);
+        let mut listing = crawler(&transport).drift_corpus(corpus.clone());
+        assert!(listing
+            .document_indexes(&programming(), 1)
+            .await
+            .unwrap()
+            .is_empty());
+        let samples = corpus.samples().unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].1, crate::drift::key_dir(EMPTY_LIST));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+    #[actix_rt::test]
+    async fn revisit() {
+        let transport = fixtures();
+        let mut crawler = crawler(&transport);
+        let snapshot = DocumentSnapshot {
+            id: 1,
+            comment_count: 0,
+            like_count: 20,
+            view_count: 2000,
+            last_comment_id: None,
+            deleted_comment_ids: Vec::new(),
+            created_at: Utc::now(),
+        };
+        let update = crawler.revisit(&programming(), &snapshot).await.unwrap();
+        assert_eq!(update.comment_count, 28);
+        assert_eq!(update.comment_count_delta, 28);
+        assert_eq!(update.like_count_delta, 8);
+        assert_eq!(update.view_count_delta, -26);
+        assert!(!update.new_comments.is_empty());
+
+        let snapshot = DocumentSnapshot {
+            comment_count: 28,
+            last_comment_id: update.new_comments.iter().map(|c| c.id).max(),
+            ..snapshot
+        };
+        let update = crawler.revisit(&programming(), &snapshot).await.unwrap();
+        assert!(update.new_comments.is_empty());
+        assert!(update.deleted_comment_ids.is_empty());
+
+        let snapshot = DocumentSnapshot {
+            id: 404,
+            ..snapshot
+        };
+        let err = crawler
+            .revisit(&programming(), &snapshot)
+            .await
+            .unwrap_err();
+        assert!(err.is_deleted());
+    }
+    #[actix_rt::test]
+    async fn probe_document() {
+        let mut crawler = crawler(&fixtures());
+        assert!(crawler.probe_document(&programming(), 404).await.unwrap());
+        assert!(crawler.probe_document(&programming(), 405).await.unwrap());
+        assert!(!crawler.probe_document(&programming(), 1).await.unwrap());
+    }
 }
@@ -0,0 +1,106 @@
+//! Dates as dcinside writes them, always in Seoul time.
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+};
+use chrono_tz::Asia::Seoul;
+
+/// Reads `15:03` as of the day of `reference`, `01.10` and `01.10 17:20:43` as of its
+/// year, and `21.01.10`, `2021.01.10` or `2021-01-10 17:02:39` as they are. A day-month
+/// later than the day of `reference` is of the year before. Dates without a time are
+/// at their midnight.
+pub fn parse_kst(text: &str, reference: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let today = reference.with_timezone(&Seoul).naive_local().date();
+    let text = text.trim();
+    let (date, time) = match text.split_once(char::is_whitespace) {
+        Some((date, time)) => (Some(date), Some(time.trim())),
+        None if text.contains(':') => (None, Some(text)),
+        None => (Some(text), None),
+    };
+    let time = match time {
+        Some(time) => NaiveTime::parse_from_str(time, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+            .ok()?,
+        None => NaiveTime::from_hms_opt(0, 0, 0)?,
+    };
+    let date = match date {
+        None => today,
+        Some(date) => {
+            let parts = date
+                .split(&['.', '-', '/'][..])
+                .map(|part| part.parse().ok())
+                .collect::<Option<Vec<u32>>>()?;
+            match parts[..] {
+                [year, month, day] if year < 100 => {
+                    NaiveDate::from_ymd_opt(2000 + year as i32, month, day)?
+                }
+                [year, month, day] => NaiveDate::from_ymd_opt(year as i32, month, day)?,
+                [month, day] => match NaiveDate::from_ymd_opt(today.year(), month, day) {
+                    Some(date) if date <= today => date,
+                    // 02.29 of a leap year read in the year after
+                    _ => NaiveDate::from_ymd_opt(today.year() - 1, month, day)?,
+                },
+                _ => return None,
+            }
+        }
+    };
+    Some(kst_to_utc(&date.and_time(time)))
+}
+
+/// A local time of Seoul. The earlier of a repeated time is taken, and a skipped time
+/// is read an hour later, as clocks of the summer time of 1987 and 1988 did.
+pub fn kst_to_utc(local: &NaiveDateTime) -> DateTime<Utc> {
+    match Seoul.from_local_datetime(local) {
+        LocalResult::Single(date) | LocalResult::Ambiguous(date, _) => date.with_timezone(&Utc),
+        LocalResult::None => match Seoul.from_local_datetime(&(*local + Duration::hours(1))) {
+            LocalResult::Single(date) | LocalResult::Ambiguous(date, _) => date.with_timezone(&Utc),
+            LocalResult::None => Utc.from_utc_datetime(&(*local - Duration::hours(9))),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn it_parses_kst_dates() {
+        // 2021-01-10 18:00 in Seoul
+        let now = utc("2021-01-10T09:00:00Z");
+        let cases = [
+            ("15:03", "2021-01-10T06:03:00Z"),
+            ("17:20:43", "2021-01-10T08:20:43Z"),
+            ("01.10", "2021-01-09T15:00:00Z"),
+            ("01.10 17:20:43", "2021-01-10T08:20:43Z"),
+            ("12.31 23:59", "2020-12-31T14:59:00Z"),
+            ("20.12.31", "2020-12-30T15:00:00Z"),
+            ("2020.12.31 16:44:47", "2020-12-31T07:44:47Z"),
+            ("2021-01-10 17:02:39", "2021-01-10T08:02:39Z"),
+            (" 2019-03-02 ", "2019-03-01T15:00:00Z"),
+        ];
+        for (text, expected) in cases.iter() {
+            assert_eq!(parse_kst(text, now), Some(utc(expected)), "{}", text);
+        }
+        assert_eq!(parse_kst("02.29", now), Some(utc("2020-02-28T15:00:00Z")));
+        assert_eq!(parse_kst("어제", now), None);
+        assert_eq!(parse_kst("13.45.2021", now), None);
+        assert_eq!(parse_kst("25:00", now), None);
+    }
+
+    #[test]
+    fn it_resolves_summer_time() {
+        let now = utc("2021-01-10T09:00:00Z");
+        // clocks went from 02:00 to 03:00 on 1987-05-10, and back on 1987-10-11
+        assert_eq!(
+            parse_kst("1987.05.10 02:30", now),
+            Some(utc("1987-05-09T17:30:00Z"))
+        );
+        assert_eq!(
+            parse_kst("1987.10.11 02:30", now),
+            Some(utc("1987-10-10T16:30:00Z"))
+        );
+    }
+}
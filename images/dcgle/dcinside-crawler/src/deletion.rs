@@ -0,0 +1,53 @@
+/// Collects ids missing between documents of a listing, which are suspected to be deleted.
+/// Document ids of a gallery are sequential and listings go newest first, so any id
+/// skipped between two listed documents was posted and is gone or hidden now.
+#[derive(Debug, Clone)]
+pub struct IdGaps {
+    max_gap: usize,
+    last_id: Option<usize>,
+    suspects: Vec<usize>,
+}
+impl IdGaps {
+    /// Gaps wider than `max_gap` are skipped. Those are mostly cleanups of spam,
+    /// which are not worth probing id by id.
+    pub fn new(max_gap: usize) -> Self {
+        IdGaps {
+            max_gap,
+            last_id: None,
+            suspects: Vec::new(),
+        }
+    }
+    /// Notes a listed id. Ids are expected newest first, so an id not below the last
+    /// one, as when two pages overlap after new posts, is ignored.
+    pub fn observe(&mut self, id: usize) {
+        match self.last_id {
+            Some(last_id) if id >= last_id => return,
+            Some(last_id) => {
+                let missing = last_id - id - 1;
+                if missing > 0 && missing <= self.max_gap {
+                    self.suspects.extend((id + 1..last_id).rev());
+                }
+            }
+            None => {}
+        }
+        self.last_id = Some(id);
+    }
+    pub fn take(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.suspects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_collects_gaps() {
+        let mut gaps = IdGaps::new(3);
+        for id in &[100, 120, 99, 97, 96, 90, 89, 85] {
+            gaps.observe(*id);
+        }
+        assert_eq!(gaps.take(), vec![98, 88, 87, 86]);
+        assert!(gaps.take().is_empty());
+    }
+}
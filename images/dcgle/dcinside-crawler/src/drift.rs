@@ -0,0 +1,293 @@
+//! Pages the parsers failed on in a way that hints dcinside changed its markup,
+//! kept on disk to check parser updates against.
+use crate::mobile::*;
+use crate::parse::*;
+
+use chrono::Utc;
+use log::{info, warn};
+use std::path::PathBuf;
+
+/// Which parser a page is read by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PageKind {
+    List,
+    Body,
+    Comments,
+    MobileList,
+    MobileBody,
+    MobileComments,
+}
+impl PageKind {
+    pub const ALL: [PageKind; 6] = [
+        PageKind::List,
+        PageKind::Body,
+        PageKind::Comments,
+        PageKind::MobileList,
+        PageKind::MobileBody,
+        PageKind::MobileComments,
+    ];
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::List => "list",
+            Self::Body => "body",
+            Self::Comments => "comments",
+            Self::MobileList => "mobile_list",
+            Self::MobileBody => "mobile_body",
+            Self::MobileComments => "mobile_comments",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|kind| kind.name() == name).copied()
+    }
+}
+
+/// Key of a failure without a drift key, like a block page.
+const UNEXPECTED: &str = "unexpected";
+/// Key of a list page without any row, as when the row selector stops matching.
+pub const EMPTY_LIST: &str = "empty list";
+
+/// What the current parsers make of a page.
+#[derive(Debug, Default, PartialEq)]
+pub struct PageCheck {
+    /// Rows of a list page. Other pages count as a row.
+    pub rows: usize,
+    /// Drift keys of the rows that failed.
+    pub failures: Vec<&'static str>,
+}
+impl PageCheck {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+pub fn check_page(kind: PageKind, body: &str) -> PageCheck {
+    let now = Utc::now();
+    match kind {
+        PageKind::List => check_list(parse_document_indexes(body, "drift", now)),
+        PageKind::MobileList => check_list(parse_mobile_document_indexes(body, "drift", now)),
+        PageKind::Body => check_single(
+            parse_document_body(body, "drift", 0)
+                .map(drop)
+                .map_err(|err| err.drift_key()),
+        ),
+        PageKind::MobileBody => check_single(
+            parse_mobile_document_body(body)
+                .map(drop)
+                .map_err(|err| err.drift_key()),
+        ),
+        PageKind::Comments => check_single(
+            parse_comments(body, "drift", 0, now)
+                .map(drop)
+                .map_err(|err| err.drift_key()),
+        ),
+        PageKind::MobileComments => check_single(
+            parse_mobile_comments(body, None, now)
+                .map(drop)
+                .map_err(|err| err.drift_key()),
+        ),
+    }
+}
+
+fn check_list(
+    res: Result<
+        Vec<Result<DocumentIndex, crate::error::DocumentParseError>>,
+        crate::error::DocumentParseError,
+    >,
+) -> PageCheck {
+    match res {
+        Ok(rows) if rows.is_empty() => PageCheck {
+            rows: 1,
+            failures: vec![EMPTY_LIST],
+        },
+        Ok(rows) => PageCheck {
+            rows: rows.len(),
+            failures: rows
+                .iter()
+                .filter_map(|row| row.as_ref().err())
+                .map(|err| err.drift_key().unwrap_or(UNEXPECTED))
+                .collect(),
+        },
+        Err(err) => PageCheck {
+            rows: 1,
+            failures: vec![err.drift_key().unwrap_or(UNEXPECTED)],
+        },
+    }
+}
+
+fn check_single(res: Result<(), Option<&'static str>>) -> PageCheck {
+    PageCheck {
+        rows: 1,
+        failures: res
+            .err()
+            .map(|key| key.unwrap_or(UNEXPECTED))
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// Samples of failing pages under `root/<kind>/<drift key>/`, at most `max_per_key`
+/// a key. The oldest samples of a key make room for new ones.
+#[derive(Debug, Clone)]
+pub struct DriftCorpus {
+    root: PathBuf,
+    max_per_key: usize,
+    sample_rate: f64,
+}
+impl DriftCorpus {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        DriftCorpus {
+            root: root.into(),
+            max_per_key: 20,
+            sample_rate: 1.0,
+        }
+    }
+    pub fn max_per_key(mut self, v: usize) -> Self {
+        self.max_per_key = v;
+        self
+    }
+    /// Share of failing pages kept. Once markup changes every page fails.
+    pub fn sample_rate(mut self, v: f64) -> Self {
+        self.sample_rate = v;
+        self
+    }
+    /// Keeps `body` if `key` tells the markup changed and sampling picks it. Parse errors
+    /// only carry a snippet, so this is where the whole page of one is found.
+    pub fn sample(&self, kind: PageKind, key: Option<&str>, body: &str) -> Option<PathBuf> {
+        let key = key?;
+        if self.max_per_key == 0 || rand::random::<f64>() >= self.sample_rate {
+            return None;
+        }
+        match self.record(kind, key, body) {
+            Ok(path) => {
+                info!(
+                    "{} page failing at `{}` is dumped to {}",
+                    kind.name(),
+                    key,
+                    path.display()
+                );
+                Some(path)
+            }
+            Err(err) => {
+                warn!(
+                    "fail to record a {} page failing at `{}`: {}",
+                    kind.name(),
+                    key,
+                    err
+                );
+                None
+            }
+        }
+    }
+    pub fn record(&self, kind: PageKind, key: &str, body: &str) -> std::io::Result<PathBuf> {
+        let dir = self.root.join(kind.name()).join(key_dir(key));
+        std::fs::create_dir_all(&dir)?;
+        let mut samples = Self::files(&dir)?;
+        // names sort by age even for samples of the same millisecond
+        let millis = samples
+            .last()
+            .and_then(|last| last.file_name()?.to_str()?.get(..13)?.parse::<i64>().ok())
+            .map_or(Utc::now().timestamp_millis(), |last| {
+                Utc::now().timestamp_millis().max(last + 1)
+            });
+        while !samples.is_empty() && samples.len() >= self.max_per_key {
+            std::fs::remove_file(samples.remove(0))?;
+        }
+        let path = dir.join(format!("{:013}-{:08x}.html", millis, rand::random::<u32>()));
+        std::fs::write(&path, body)?;
+        Ok(path)
+    }
+    /// Samples on disk as their kind, key directory and path, oldest first in a key.
+    pub fn samples(&self) -> std::io::Result<Vec<(PageKind, String, PathBuf)>> {
+        let mut samples = Vec::new();
+        for kind in PageKind::ALL.iter() {
+            let kind_dir = self.root.join(kind.name());
+            if !kind_dir.is_dir() {
+                continue;
+            }
+            let mut keys = Self::files(&kind_dir)?;
+            keys.retain(|dir| dir.is_dir());
+            for dir in keys {
+                let key = dir
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                for path in Self::files(&dir)? {
+                    samples.push((*kind, key.clone(), path));
+                }
+            }
+        }
+        Ok(samples)
+    }
+    fn files(dir: &std::path::Path) -> std::io::Result<Vec<PathBuf>> {
+        let mut files = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// `.us-post .gall_count` is kept as `_us-post__gall_count`.
+pub fn key_dir(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_keeps_bounded_samples() {
+        let root = std::env::temp_dir().join(format!("drift-{}", rand::random::<u64>()));
+        let corpus = DriftCorpus::new(&root).max_per_key(2);
+        for i in 0..3 {
+            corpus
+                .record(PageKind::List, ".us-post .gall_count", &i.to_string())
+                .unwrap();
+        }
+        assert!(corpus
+            .sample(PageKind::Comments, Some("json"), "{}")
+            .is_some());
+        assert_eq!(corpus.sample(PageKind::Comments, None, "blocked"), None);
+        let samples = corpus.samples().unwrap();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[0].0, PageKind::List);
+        assert_eq!(samples[0].1, "_us-post__gall_count");
+        // the oldest made room
+        assert_eq!(std::fs::read_to_string(&samples[0].2).unwrap(), "1");
+        assert_eq!(samples[2].0, PageKind::Comments);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn it_checks_pages() {
+        let check = check_page(PageKind::List, include_str!("../assets/gallery.html"));
+        assert!(check.passed());
+        assert!(check.rows > 1);
+        let broken = include_str!("../assets/gallery.html")
+            .replace("class=\"gall_count\"", "class=\"gall_views\"");
+        let check = check_page(PageKind::List, &broken);
+        assert!(!check.passed());
+        assert!(check
+            .failures
+            .iter()
+            .all(|key| *key == ".us-post .gall_count"));
+        assert_eq!(
+            check_page(PageKind::MobileList, include_str!("../assets/body.html")).failures,
+            vec![EMPTY_LIST]
+        );
+        assert!(check_page(PageKind::Comments, include_str!("../assets/comments.json")).passed());
+        assert_eq!(
+            PageKind::from_name("mobile_body"),
+            Some(PageKind::MobileBody)
+        );
+    }
+}
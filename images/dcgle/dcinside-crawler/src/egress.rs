@@ -0,0 +1,537 @@
+use crate::error::*;
+use crate::parse::detect_block_page;
+use crate::transport::{ActixTransport, Request, Response, Transport, DEFAULT_USER_AGENT};
+
+use actix_connect::{Connect, ConnectError, Connection};
+use actix_rt::net::TcpStream;
+use actix_service::Service;
+use actix_web::client::{ClientBuilder, Connector};
+use actix_web::http::{StatusCode, Uri};
+use futures::future::{FutureExt, LocalBoxFuture};
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use std::cell::Cell;
+use std::io;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProxyKind {
+    Http,
+    Socks5,
+}
+
+/// `http://[user:pass@]host:port` or `socks5://[user:pass@]host:port`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Proxy {
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+    pub credentials: Option<(String, String)>,
+}
+impl FromStr for Proxy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = match s.find("://") {
+            Some(i) => (&s[..i], &s[i + 3..]),
+            None => return Err(format!("no scheme in proxy `{}`", s)),
+        };
+        let kind = match scheme {
+            "http" => ProxyKind::Http,
+            "socks5" => ProxyKind::Socks5,
+            _ => return Err(format!("unsupported proxy scheme `{}`", scheme)),
+        };
+        let rest = rest.trim_end_matches('/');
+        let (credentials, addr) = match rest.rfind('@') {
+            Some(i) => {
+                let (user, pass) = match rest[..i].find(':') {
+                    Some(j) => (&rest[..j], &rest[j + 1..i]),
+                    None => (&rest[..i], ""),
+                };
+                (Some((user.to_string(), pass.to_string())), &rest[i + 1..])
+            }
+            None => (None, rest),
+        };
+        let (host, port) = match addr.rfind(':') {
+            Some(i) => (
+                &addr[..i],
+                addr[i + 1..]
+                    .parse()
+                    .map_err(|_| format!("invalid proxy port in `{}`", s))?,
+            ),
+            None => (
+                addr,
+                match kind {
+                    ProxyKind::Http => 80,
+                    ProxyKind::Socks5 => 1080,
+                },
+            ),
+        };
+        Ok(Proxy {
+            kind,
+            host: host.to_string(),
+            port,
+            credentials,
+        })
+    }
+}
+
+async fn http_connect(
+    stream: &mut TcpStream,
+    proxy: &Proxy,
+    host: &str,
+    port: u16,
+) -> io::Result<()> {
+    let mut req = format!(
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n",
+        host = host,
+        port = port
+    );
+    if let Some((user, pass)) = &proxy.credentials {
+        req.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            base64::encode(format!("{}:{}", user, pass))
+        ));
+    }
+    req.push_str("\r\n");
+    stream.write_all(req.as_bytes()).await?;
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() > 8 * 1024 || stream.read(&mut buf).await? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "broken proxy response",
+            ));
+        }
+        head.push(buf[0]);
+    }
+    let status_line = String::from_utf8_lossy(&head);
+    match status_line.split_whitespace().nth(1) {
+        Some("200") => Ok(()),
+        status => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("proxy refused CONNECT: {:?}", status),
+        )),
+    }
+}
+
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    proxy: &Proxy,
+    host: &str,
+    port: u16,
+) -> io::Result<()> {
+    let refused = |msg: &str| io::Error::new(io::ErrorKind::ConnectionRefused, msg.to_string());
+    let method = if proxy.credentials.is_some() { 2 } else { 0 };
+    stream.write_all(&[5, 1, method]).await?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply != [5, method] {
+        return Err(refused("socks5 auth method rejected"));
+    }
+    if let Some((user, pass)) = &proxy.credentials {
+        let mut auth = vec![1, user.len() as u8];
+        auth.extend(user.as_bytes());
+        auth.push(pass.len() as u8);
+        auth.extend(pass.as_bytes());
+        stream.write_all(&auth).await?;
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0 {
+            return Err(refused("socks5 auth failed"));
+        }
+    }
+    let mut req = vec![5, 1, 0, 3, host.len() as u8];
+    req.extend(host.as_bytes());
+    req.extend(&port.to_be_bytes());
+    stream.write_all(&req).await?;
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[1] != 0 {
+        return Err(refused("socks5 connect failed"));
+    }
+    let addr_len = match head[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        _ => return Err(refused("socks5 unknown address type")),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+/// Tunnels client connections through a proxy. TLS is layered on top by the client.
+#[derive(Clone)]
+struct ProxyConnector {
+    proxy: Proxy,
+}
+impl Service for ProxyConnector {
+    type Request = Connect<Uri>;
+    type Response = Connection<Uri, TcpStream>;
+    type Error = ConnectError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+    fn call(&mut self, req: Connect<Uri>) -> Self::Future {
+        let proxy = self.proxy.clone();
+        let host = req.host().to_string();
+        let port = req.port();
+        async move {
+            let mut stream = TcpStream::connect((proxy.host.as_str(), proxy.port))
+                .await
+                .map_err(ConnectError::Io)?;
+            match proxy.kind {
+                ProxyKind::Http => http_connect(&mut stream, &proxy, &host, port).await,
+                ProxyKind::Socks5 => socks5_connect(&mut stream, &proxy, &host, port).await,
+            }
+            .map_err(ConnectError::Io)?;
+            let uri = format!("{}:{}", host, port)
+                .parse()
+                .map_err(|_| ConnectError::Unresolved)?;
+            Ok(Connection::new(stream, uri))
+        }
+        .boxed_local()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EgressConfig {
+    pub name: String,
+    /// Connects directly when `None`.
+    pub proxy: Option<Proxy>,
+    /// Picked at random per request. Falls back to `DEFAULT_USER_AGENT` when empty.
+    pub user_agents: Vec<String>,
+    /// How long a blocked egress sits out. Grows with consecutive blocks.
+    pub cooldown: Duration,
+}
+impl EgressConfig {
+    pub fn direct() -> Self {
+        EgressConfig {
+            name: String::from("direct"),
+            proxy: None,
+            user_agents: Vec::new(),
+            cooldown: Duration::from_secs(600),
+        }
+    }
+    pub fn proxy(proxy: Proxy) -> Self {
+        EgressConfig {
+            name: format!("{}:{}", proxy.host, proxy.port),
+            proxy: Some(proxy),
+            ..EgressConfig::direct()
+        }
+    }
+    pub fn user_agents(mut self, user_agents: Vec<String>) -> Self {
+        self.user_agents = user_agents;
+        self
+    }
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+    fn transport(&self) -> ActixTransport {
+        let builder = ClientBuilder::new();
+        let builder = match &self.proxy {
+            Some(proxy) => builder.connector(
+                Connector::new()
+                    .connector(ProxyConnector {
+                        proxy: proxy.clone(),
+                    })
+                    .timeout(Duration::from_secs(10))
+                    .finish(),
+            ),
+            None => builder,
+        };
+        ActixTransport::with_client(builder.finish())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct EgressState {
+    name: String,
+    cooldown: Duration,
+    requests: u64,
+    blocks: u64,
+    consecutive_blocks: u32,
+    benched_until: Option<Instant>,
+}
+impl EgressState {
+    fn is_benched(&self, now: Instant) -> bool {
+        self.benched_until.map(|t| t > now).unwrap_or(false)
+    }
+    fn bench(&mut self, now: Instant) {
+        self.blocks += 1;
+        self.consecutive_blocks += 1;
+        self.benched_until = Some(now + self.cooldown * self.consecutive_blocks);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct EgressStatus {
+    pub name: String,
+    pub requests: u64,
+    pub blocks: u64,
+    pub consecutive_blocks: u32,
+    pub benched_for_seconds: Option<u64>,
+}
+
+/// Shared view of the pool's health. Unlike the pool itself it can cross threads,
+/// so it can be served from an http handler.
+#[derive(Debug, Clone, Default)]
+pub struct EgressHealth(Arc<Mutex<Vec<EgressState>>>);
+impl EgressHealth {
+    pub fn snapshot(&self) -> Vec<EgressStatus> {
+        let now = Instant::now();
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| EgressStatus {
+                name: s.name.clone(),
+                requests: s.requests,
+                blocks: s.blocks,
+                consecutive_blocks: s.consecutive_blocks,
+                benched_for_seconds: s
+                    .benched_until
+                    .filter(|t| *t > now)
+                    .map(|t| (t - now).as_secs()),
+            })
+            .collect()
+    }
+}
+
+fn is_block_signature(res: &Response) -> bool {
+    if res.status == StatusCode::FORBIDDEN || res.status == StatusCode::TOO_MANY_REQUESTS {
+        return true;
+    }
+    // block pages are html. Attachments and other binaries are not scanned
+    let text = match res.header_value("Content-Type") {
+        Some(t) => t.starts_with("text/") || t.contains("json") || t.contains("javascript"),
+        None => true,
+    };
+    // maintenance is not the egress' fault, so it is left to the parser
+    text && !matches!(
+        detect_block_page(&String::from_utf8_lossy(&res.body)),
+        None | Some(BlockKind::Maintenance)
+    )
+}
+
+/// Spreads requests over several egresses round robin, benching the ones that get blocked.
+/// A blocked response comes back as `CrawlerError::EgressBlocked`, which is retryable,
+/// so the retry lands on another egress.
+pub struct EgressPool {
+    egresses: Vec<(EgressConfig, ActixTransport)>,
+    health: EgressHealth,
+    next: Cell<usize>,
+}
+impl EgressPool {
+    /// Panics without any egress to pick from.
+    pub fn new(configs: Vec<EgressConfig>) -> Self {
+        assert!(
+            !configs.is_empty(),
+            "an egress pool needs at least one egress"
+        );
+        let states = configs
+            .iter()
+            .map(|c| EgressState {
+                name: c.name.clone(),
+                cooldown: c.cooldown,
+                requests: 0,
+                blocks: 0,
+                consecutive_blocks: 0,
+                benched_until: None,
+            })
+            .collect();
+        EgressPool {
+            egresses: configs
+                .into_iter()
+                .map(|c| {
+                    let transport = c.transport();
+                    (c, transport)
+                })
+                .collect(),
+            health: EgressHealth(Arc::new(Mutex::new(states))),
+            next: Cell::new(0),
+        }
+    }
+    pub fn health(&self) -> EgressHealth {
+        self.health.clone()
+    }
+    /// The next egress that is not benched, or the one back the soonest if all are.
+    fn pick(&self) -> usize {
+        let states = self.health.0.lock().unwrap();
+        let now = Instant::now();
+        let len = self.egresses.len();
+        let start = self.next.get();
+        let i = (0..len)
+            .map(|k| (start + k) % len)
+            .find(|&i| !states[i].is_benched(now))
+            .unwrap_or_else(|| {
+                (0..len)
+                    .min_by_key(|&i| states[i].benched_until)
+                    .unwrap_or(0)
+            });
+        self.next.set((i + 1) % len);
+        i
+    }
+    fn bench_at(&self, i: usize) {
+        let mut states = self.health.0.lock().unwrap();
+        states[i].bench(Instant::now());
+    }
+}
+impl Transport for EgressPool {
+    fn send(&self, req: Request) -> LocalBoxFuture<'_, Result<Response, CrawlerError>> {
+        async move {
+            let i = self.pick();
+            let (config, transport) = &self.egresses[i];
+            let user_agent = if config.user_agents.is_empty() {
+                DEFAULT_USER_AGENT
+            } else {
+                config.user_agents[rand::random::<usize>() % config.user_agents.len()].as_str()
+            };
+            self.health.0.lock().unwrap()[i].requests += 1;
+            // the mobile backend asks for a mobile user agent of its own
+            let req = if req
+                .headers
+                .iter()
+                .any(|(k, _)| k.eq_ignore_ascii_case("User-Agent"))
+            {
+                req
+            } else {
+                req.header("User-Agent", user_agent)
+            };
+            let res = transport.send(req).await;
+            match res {
+                Ok(res) if !is_block_signature(&res) => {
+                    self.health.0.lock().unwrap()[i].consecutive_blocks = 0;
+                    Ok(res.egress(&config.name))
+                }
+                Ok(_) => {
+                    self.bench_at(i);
+                    Err(CrawlerError::EgressBlocked(config.name.clone()))
+                }
+                Err(err) => {
+                    // most likely a dead proxy
+                    if config.proxy.is_some() {
+                        self.bench_at(i);
+                    }
+                    Err(err)
+                }
+            }
+        }
+        .boxed_local()
+    }
+    fn bench(&self, egress: &str) {
+        if let Some(i) = self.egresses.iter().position(|(c, _)| c.name == egress) {
+            self.bench_at(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_proxies() {
+        assert_eq!(
+            "socks5://user:pa:ss@10.0.0.1:1081"
+                .parse::<Proxy>()
+                .unwrap(),
+            Proxy {
+                kind: ProxyKind::Socks5,
+                host: String::from("10.0.0.1"),
+                port: 1081,
+                credentials: Some((String::from("user"), String::from("pa:ss"))),
+            }
+        );
+        assert_eq!(
+            "http://proxy.local".parse::<Proxy>().unwrap(),
+            Proxy {
+                kind: ProxyKind::Http,
+                host: String::from("proxy.local"),
+                port: 80,
+                credentials: None,
+            }
+        );
+        assert!("ftp://proxy.local".parse::<Proxy>().is_err());
+        assert!("proxy.local:3128".parse::<Proxy>().is_err());
+    }
+
+    #[test]
+    fn it_detects_block_signatures() {
+        assert!(is_block_signature(
+            &Response::ok("").with_status(StatusCode::FORBIDDEN)
+        ));
+        assert!(is_block_signature(&Response::ok(
+            "<img src=\"/kcaptcha/image/?gall_id=programming\">"
+        )));
+        assert!(!is_block_signature(&Response::ok(include_str!(
+            "../assets/gallery.html"
+        ))));
+        assert!(!is_block_signature(&Response::ok(include_str!(
+            "../assets/body.html"
+        ))));
+        assert!(!is_block_signature(&Response::ok(include_str!(
+            "../assets/maintenance.html"
+        ))));
+        assert!(!is_block_signature(
+            &Response::ok("GIF89a kcaptcha").header("Content-Type", "image/gif")
+        ));
+        assert!(is_block_signature(
+            &Response::ok("<p>접근이 차단된 아이피입니다.</p>")
+                .header("Content-Type", "text/html; charset=UTF-8")
+        ));
+        assert!(!is_block_signature(&Response::ok(
+            include_str!("../assets/mobile_view.html").replace("답변 부탁", "접근이 차단")
+        )));
+    }
+
+    #[test]
+    fn it_reports_benched_pools_as_blocks() {
+        use crate::model::CrawlerErrorReport;
+        let err = CrawlerError::EgressBlocked(String::from("direct"));
+        assert!(err.is_block());
+        // still retried on another egress
+        assert!(err.is_retryable());
+        let report = CrawlerErrorReport::from(&err);
+        assert!(matches!(report, CrawlerErrorReport::EgressBlocked));
+        assert!(report.is_block());
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one egress")]
+    fn it_rejects_empty_pools() {
+        EgressPool::new(Vec::new());
+    }
+
+    #[actix_rt::test]
+    async fn it_rotates_and_benches() {
+        let pool = EgressPool::new(vec![
+            EgressConfig::direct(),
+            EgressConfig {
+                name: String::from("other"),
+                ..EgressConfig::direct()
+            },
+        ]);
+        assert_eq!(pool.pick(), 0);
+        assert_eq!(pool.pick(), 1);
+        pool.bench("direct");
+        assert_eq!(pool.pick(), 1);
+        assert_eq!(pool.pick(), 1);
+        pool.bench("other");
+        assert_eq!(pool.pick(), 0);
+        let health = pool.health().snapshot();
+        assert_eq!(health[0].blocks, 1);
+        assert!(health[0].benched_for_seconds.is_some());
+    }
+}
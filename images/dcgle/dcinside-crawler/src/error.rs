@@ -1,10 +1,74 @@
 use actix_web::client::{PayloadError, SendRequestError};
+use actix_web::http::StatusCode;
 use err_derive::Error;
 
+use std::fmt;
+use std::time::Duration;
+
+/// Pages served instead of the content once dcinside starts pushing back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockKind {
+    RateLimit,
+    Captcha,
+    Maintenance,
+    IpBan,
+}
+
+/// Longest html kept in a parse error, in chars. Whole pages are left to the drift corpus.
+pub const SNIPPET_LEN: usize = 256;
+
+/// `html` cut to `SNIPPET_LEN` chars.
+pub fn snippet(html: &str) -> String {
+    let html = html.trim();
+    match html.char_indices().nth(SNIPPET_LEN) {
+        Some((end, _)) => format!("{}...", &html[..end]),
+        None => html.to_string(),
+    }
+}
+
+/// Where a selector found nothing, with a snippet of the node it ran on.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SelectContext {
+    /// Index of the row of a listing.
+    pub row: Option<usize>,
+    pub document_id: Option<usize>,
+    pub snippet: String,
+}
+impl SelectContext {
+    pub fn new(html: &str) -> Self {
+        SelectContext {
+            snippet: snippet(html),
+            ..Default::default()
+        }
+    }
+    pub fn row(mut self, v: usize) -> Self {
+        self.row = Some(v);
+        self
+    }
+    pub fn document_id(mut self, v: usize) -> Self {
+        self.document_id = Some(v);
+        self
+    }
+}
+impl fmt::Display for SelectContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(row) = self.row {
+            write!(f, "at row {} ", row)?;
+        }
+        if let Some(id) = self.document_id {
+            write!(f, "of document {} ", id)?;
+        }
+        write!(f, "in `{}`", self.snippet)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum DocumentParseError {
-    #[error(display = "fail to select `{}`: html: {}", path, html)]
-    Select { path: &'static str, html: String },
+    #[error(display = "fail to select `{}` {}", path, context)]
+    Select {
+        path: &'static str,
+        context: SelectContext,
+    },
     #[error(display = "fail to parse `{}`", path)]
     NumberParse { path: &'static str },
     #[error(display = "fail to parse `{}`", path)]
@@ -19,10 +83,36 @@ pub enum DocumentParseError {
     MinorGalleryPromoted,
     #[error(display = "minor gallery access not allowed")]
     MinorGalleryAccessNotAllowed,
+    #[error(display = "closed mini gallery")]
+    MiniGalleryClosed,
+    #[error(display = "mini gallery access not allowed")]
+    MiniGalleryAccessNotAllowed,
+    #[error(display = "blocked: {:?}", _0)]
+    Blocked(BlockKind),
+    #[error(display = "deleted document")]
+    Deleted,
+}
+
+impl DocumentParseError {
+    /// What failed when the error hints the markup changed, like the selector that
+    /// found nothing. Pages dcinside serves on purpose have none.
+    pub fn drift_key(&self) -> Option<&'static str> {
+        match self {
+            DocumentParseError::Select { path, .. }
+            | DocumentParseError::NumberParse { path }
+            | DocumentParseError::DatetimeParse { path } => Some(path),
+            DocumentParseError::JsonParse(_) => Some("json"),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum CommentParseError {
+    #[error(display = "blocked: {:?}", _0)]
+    Blocked(BlockKind),
+    #[error(display = "e_s_n_o is refused")]
+    InvalidToken,
     #[error(display = "fail to select `{}`", path)]
     Select { path: &'static str },
     #[error(display = "fail to parse `{}`", path)]
@@ -34,22 +124,44 @@ pub enum CommentParseError {
         gallery_id,
         doc_id,
         source,
-        target
+        snippet
     )]
     JsonParse {
         source: serde_json::Error,
-        target: String,
+        snippet: String,
         doc_id: usize,
         gallery_id: String,
     },
 }
+impl CommentParseError {
+    pub fn drift_key(&self) -> Option<&'static str> {
+        match self {
+            CommentParseError::Select { path }
+            | CommentParseError::NumberParse { path }
+            | CommentParseError::DatetimeParse { path } => Some(path),
+            CommentParseError::JsonParse { .. } => Some("json"),
+            CommentParseError::Blocked(_) | CommentParseError::InvalidToken => None,
+        }
+    }
+}
 #[derive(Error, Debug)]
 pub enum DocumentBodyParseError {
-    #[error(display = "fail to select `{}`: html: {}", path, html)]
-    Select { path: &'static str, html: String },
+    #[error(display = "fail to select `{}` {}", path, context)]
+    Select {
+        path: &'static str,
+        context: SelectContext,
+    },
     #[error(display = "fail to parse page: {}", _0)]
     DocumentParseError(#[source] DocumentParseError),
 }
+impl DocumentBodyParseError {
+    pub fn drift_key(&self) -> Option<&'static str> {
+        match self {
+            DocumentBodyParseError::Select { path, .. } => Some(path),
+            DocumentBodyParseError::DocumentParseError(err) => err.drift_key(),
+        }
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum CrawlerError {
@@ -59,12 +171,24 @@ pub enum CrawlerError {
     Payload(#[source] PayloadError),
     #[error(display = "serde: {}", _0)]
     Serde(#[source] serde_json::Error),
+    #[error(display = "urlencode: {}", _0)]
+    UrlEncode(#[source] serde_urlencoded::ser::Error),
     #[error(display = "fmt: {}", _0)]
     Fmt(#[source] core::fmt::Error),
     #[error(display = "utf8: {}", _0)]
     Utf8(#[source] std::str::Utf8Error),
     #[error(display = "page not found(404)")]
     PageNotFound,
+    #[error(display = "egress `{}` is blocked", _0)]
+    EgressBlocked(String),
+    /// A body over the limit of its request, with the length the server gave if any.
+    #[error(display = "body over the limit ({:?} bytes)", _0)]
+    BodyTooLarge(Option<u64>),
+    #[error(display = "err http response: {}", status)]
+    Status {
+        status: StatusCode,
+        retry_after: Option<Duration>,
+    },
     #[error(display = "fail to parse root page: {}", _0)]
     DocumentParseError(#[source] DocumentParseError),
     #[error(display = "fail to parse comment: {}", _0)]
@@ -73,6 +197,83 @@ pub enum CrawlerError {
     DocumentBodyParseError(#[source] DocumentBodyParseError),
 }
 
+impl CrawlerError {
+    /// Whether sending the same request again may succeed.
+    pub fn is_retryable(&self) -> bool {
+        if let Some(kind) = self.block_kind() {
+            return kind == BlockKind::RateLimit;
+        }
+        match self {
+            CrawlerError::Status { status, .. } => {
+                status.is_server_error()
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+                    || *status == StatusCode::REQUEST_TIMEOUT
+            }
+            CrawlerError::PageNotFound
+            | CrawlerError::BodyTooLarge(_)
+            | CrawlerError::UrlEncode(_)
+            | CrawlerError::Fmt(_) => false,
+            // the same token would be refused again
+            CrawlerError::CommentParseError(CommentParseError::InvalidToken) => false,
+            CrawlerError::DocumentParseError(err)
+            | CrawlerError::DocumentBodyParseError(DocumentBodyParseError::DocumentParseError(
+                err,
+            )) => !matches!(
+                err,
+                DocumentParseError::AdultPage
+                    | DocumentParseError::MinorGalleryClosed
+                    | DocumentParseError::MinorGalleryPromoted
+                    | DocumentParseError::MinorGalleryAccessNotAllowed
+                    | DocumentParseError::MiniGalleryClosed
+                    | DocumentParseError::MiniGalleryAccessNotAllowed
+                    | DocumentParseError::Deleted
+            ),
+            // network errors and broken pages, which are mostly transient
+            _ => true,
+        }
+    }
+    /// Whether a view page showed the document is gone.
+    pub fn is_deleted(&self) -> bool {
+        matches!(
+            self,
+            CrawlerError::DocumentParseError(DocumentParseError::Deleted)
+        )
+    }
+    /// Whether the crawler rather than the page is refused, including every egress of
+    /// a pool being benched. Unlike `block_kind`, which picks what is retried.
+    pub fn is_block(&self) -> bool {
+        self.block_kind().is_some() || matches!(self, CrawlerError::EgressBlocked(_))
+    }
+    pub fn block_kind(&self) -> Option<BlockKind> {
+        match self {
+            CrawlerError::Status { status, .. } if *status == StatusCode::TOO_MANY_REQUESTS => {
+                Some(BlockKind::RateLimit)
+            }
+            CrawlerError::DocumentParseError(DocumentParseError::Blocked(kind))
+            | CrawlerError::CommentParseError(CommentParseError::Blocked(kind))
+            | CrawlerError::DocumentBodyParseError(DocumentBodyParseError::DocumentParseError(
+                DocumentParseError::Blocked(kind),
+            )) => Some(*kind),
+            _ => None,
+        }
+    }
+    /// The drift key of a parse error, to group failures by.
+    pub fn drift_key(&self) -> Option<&'static str> {
+        match self {
+            CrawlerError::DocumentParseError(err) => err.drift_key(),
+            CrawlerError::CommentParseError(err) => err.drift_key(),
+            CrawlerError::DocumentBodyParseError(err) => err.drift_key(),
+            _ => None,
+        }
+    }
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            CrawlerError::Status { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum LiveDirectoryError {
     #[error(display = "crawler error")]
@@ -80,19 +281,3 @@ pub enum LiveDirectoryError {
     #[error(display = "sled")]
     Sled(#[source] sled::Error),
 }
-
-#[derive(Error, Debug)]
-pub enum BackOffError {
-    #[error(display = "backoff error: {}", _0)]
-    CrawlerError(#[source] CrawlerError),
-    #[error(display = "backoff error(break): {}", _0)]
-    Break(CrawlerError),
-}
-impl From<BackOffError> for CrawlerError {
-    fn from(f: BackOffError) -> CrawlerError {
-        match f {
-            BackOffError::CrawlerError(e) => e,
-            BackOffError::Break(e) => e,
-        }
-    }
-}
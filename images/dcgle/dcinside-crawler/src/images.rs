@@ -0,0 +1,134 @@
+use sha1::{Digest, Sha1};
+
+use std::path::PathBuf;
+
+/// Which attachments of a fetched body are downloaded. Attachments are only seen
+/// with bodies, so this works on top of the body fetch policy.
+#[derive(Debug, Clone)]
+pub struct ImageFetchPolicy {
+    /// Attachments downloaded per document. 0 disables downloads.
+    pub max_per_document: usize,
+    /// Larger files are not read past the limit. Their size is noted if the server gives it.
+    pub max_bytes: usize,
+    /// Skips files that don't look like images by their name.
+    pub images_only: bool,
+}
+impl Default for ImageFetchPolicy {
+    fn default() -> Self {
+        ImageFetchPolicy {
+            max_per_document: 0,
+            max_bytes: 1024 * 1024 * 8,
+            images_only: true,
+        }
+    }
+}
+impl ImageFetchPolicy {
+    pub fn wants(&self, name: &str) -> bool {
+        !self.images_only || is_image_name(name)
+    }
+}
+
+pub fn is_image_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    [".jpg", ".jpeg", ".png", ".gif", ".webp", ".bmp"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+}
+
+/// Files on disk named by the sha1 of their content, so an image posted over and
+/// over across galleries is kept once.
+#[derive(Debug, Clone)]
+pub struct ImageStore {
+    root: PathBuf,
+}
+impl ImageStore {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        ImageStore { root: root.into() }
+    }
+    /// `ab/abcdef..` under the root. The first two digits spread files over directories.
+    pub fn path(&self, sha1: &str) -> PathBuf {
+        self.root.join(&sha1[..2]).join(sha1)
+    }
+    pub fn contains(&self, sha1: &str) -> bool {
+        self.path(sha1).exists()
+    }
+    /// Writes `bytes` unless they are stored already, and returns their sha1.
+    pub fn put(&self, bytes: &[u8]) -> std::io::Result<String> {
+        let sha1 = sha1_hex(bytes);
+        let path = self.path(&sha1);
+        if !path.exists() {
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            // a crash mid write leaves a temporary file, never a broken entry
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, bytes)?;
+            std::fs::rename(&tmp, &path)?;
+        }
+        Ok(sha1)
+    }
+}
+
+pub fn sha1_hex(bytes: &[u8]) -> String {
+    Sha1::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Difference hash of a 9x8 grayscale thumbnail given row by row: a bit per pixel
+/// brighter than its right neighbour.
+pub fn dhash(gray: &[u8]) -> u64 {
+    let mut hash = 0u64;
+    for row in gray.chunks(9).take(8) {
+        for pair in row.windows(2) {
+            hash = (hash << 1) | (pair[0] > pair[1]) as u64;
+        }
+    }
+    hash
+}
+
+/// Bits two perceptual hashes differ in. Resized or recompressed copies of an image
+/// are usually within 10.
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Perceptual hash of an encoded image, if it decodes.
+#[cfg(feature = "perceptual-hash")]
+pub fn perceptual_hash(bytes: &[u8]) -> Option<u64> {
+    let img = image::load_from_memory(bytes).ok()?;
+    let thumbnail = img.resize_exact(9, 8, image::imageops::FilterType::Triangle);
+    Some(dhash(&image::imageops::grayscale(&thumbnail).into_raw()))
+}
+#[cfg(not(feature = "perceptual-hash"))]
+pub fn perceptual_hash(_bytes: &[u8]) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_stores_by_content() {
+        let root = std::env::temp_dir().join(format!("image-store-{}", rand::random::<u64>()));
+        let store = ImageStore::new(&root);
+        let sha1 = store.put(b"gif89a").unwrap();
+        assert_eq!(sha1, sha1_hex(b"gif89a"));
+        assert_eq!(sha1.len(), 40);
+        assert!(store.contains(&sha1));
+        assert_eq!(store.put(b"gif89a").unwrap(), sha1);
+        assert_eq!(std::fs::read(store.path(&sha1)).unwrap(), b"gif89a");
+        std::fs::remove_dir_all(root).unwrap();
+    }
+    #[test]
+    fn it_hashes_gradients() {
+        let falling: Vec<u8> = (0..8).flat_map(|_| (0..9).rev().map(|x| x * 10)).collect();
+        let rising: Vec<u8> = (0..8).flat_map(|_| (0..9).map(|x| x * 10)).collect();
+        assert_eq!(dhash(&falling), u64::MAX);
+        assert_eq!(dhash(&rising), 0);
+        assert_eq!(hamming_distance(dhash(&falling), dhash(&rising)), 64);
+        assert!(is_image_name("a.JPG") && !is_image_name("a.zip"));
+        assert!(ImageFetchPolicy::default().wants("a.png"));
+        assert!(!ImageFetchPolicy::default().wants("a.zip"));
+    }
+}
@@ -1,7 +1,21 @@
+pub mod backfill;
+pub mod body;
 pub mod crawler;
+pub mod date;
+pub mod deletion;
+pub mod drift;
+pub mod egress;
 pub mod error;
+pub mod images;
+pub mod listing;
+pub mod mobile;
 pub mod model;
 pub mod parse;
+pub mod retry;
+pub mod revisit;
+pub mod session;
+pub mod stream;
+pub mod transport;
 //#[allow(dead_code, unused_imports)]
 //#[path = "../../flatbuffers-schemas/dcinside_generated.rs"]
 //pub mod dcinside_generated;
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+/// Which list of a gallery is walked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ListingMode {
+    #[default]
+    All,
+    /// 개념글 only.
+    Recommend,
+    Notice,
+}
+
+/// The listing variants dcinside offers. The default walks every document, 100 per page.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ListingFilter {
+    #[serde(default)]
+    pub mode: ListingMode,
+    /// Id of a 말머리, as in `listSearchHead(10)` of listing pages.
+    #[serde(default)]
+    pub search_head: Option<u32>,
+    /// Documents per page. dcinside serves 30, 50 or 100; others are rounded up.
+    #[serde(default)]
+    pub list_num: Option<usize>,
+}
+impl ListingFilter {
+    pub fn recommend() -> Self {
+        ListingFilter {
+            mode: ListingMode::Recommend,
+            ..Default::default()
+        }
+    }
+    pub fn notice() -> Self {
+        ListingFilter {
+            mode: ListingMode::Notice,
+            ..Default::default()
+        }
+    }
+    pub fn search_head(mut self, id: u32) -> Self {
+        self.search_head = Some(id);
+        self
+    }
+    pub fn list_num(mut self, v: usize) -> Self {
+        self.list_num = Some(v);
+        self
+    }
+    /// Whether only part of the documents are listed. Ids of such lists have gaps
+    /// and their first page may be empty.
+    pub fn is_partial(&self) -> bool {
+        self.mode != ListingMode::All || self.search_head.is_some()
+    }
+    fn documents_per_page(&self) -> usize {
+        match self.list_num {
+            Some(n) if n <= 30 => 30,
+            Some(n) if n <= 50 => 50,
+            _ => 100,
+        }
+    }
+    /// The query of a listing page, without the gallery id and page.
+    pub fn query(&self) -> String {
+        let mut query = format!("list_num={}", self.documents_per_page());
+        match self.mode {
+            ListingMode::All => {}
+            ListingMode::Recommend => query.push_str("&exception_mode=recommend"),
+            ListingMode::Notice => query.push_str("&exception_mode=notice"),
+        }
+        if let Some(id) = self.search_head {
+            query.push_str(&format!("&search_head={}", id));
+        }
+        query
+    }
+}
+
+/// Fields an in-gallery search looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SearchMode {
+    Title,
+    Content,
+    TitleContent,
+    Author,
+}
+impl SearchMode {
+    pub const fn s_type(&self) -> &'static str {
+        match self {
+            SearchMode::Title => "search_subject",
+            SearchMode::Content => "search_memo",
+            SearchMode::TitleContent => "search_subject_memo",
+            SearchMode::Author => "search_name",
+        }
+    }
+}
+impl std::str::FromStr for SearchMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "title" => Ok(SearchMode::Title),
+            "content" => Ok(SearchMode::Content),
+            "title_content" => Ok(SearchMode::TitleContent),
+            "author" => Ok(SearchMode::Author),
+            _ => Err(format!("unsupported search mode `{}`", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_builds_queries() {
+        assert_eq!(ListingFilter::default().query(), "list_num=100");
+        assert_eq!(
+            ListingFilter::recommend()
+                .search_head(10)
+                .list_num(40)
+                .query(),
+            "list_num=50&exception_mode=recommend&search_head=10"
+        );
+        assert_eq!(
+            ListingFilter::notice().list_num(10).query(),
+            "list_num=30&exception_mode=notice"
+        );
+        assert!(!ListingFilter::default().list_num(30).is_partial());
+        assert!(ListingFilter::default().search_head(0).is_partial());
+        let filter: ListingFilter = serde_json::from_str(r#"{"mode":"Recommend"}"#).unwrap();
+        assert_eq!(filter, ListingFilter::recommend());
+    }
+}
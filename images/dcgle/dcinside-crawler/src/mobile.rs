@@ -0,0 +1,373 @@
+//! Pages of the mobile site, m.dcinside.com. They are lighter than the PC pages
+//! and blocked apart from them, but show coarser dates and no `e_s_n_o`.
+use crate::date::parse_kst;
+use crate::error::*;
+use crate::listing::{ListingFilter, ListingMode};
+use crate::parse::*;
+use chrono::{DateTime, Utc};
+use dcinside_model::{Comment, DocumentBody, DocumentKind, GalleryKind, User};
+use select::{
+    document::Document as HTMLDocument,
+    predicate::{Class, Name, Predicate},
+};
+
+pub const MOBILE_USER_AGENT: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 14_3 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/14.0.3 Mobile/15E148 Safari/604.1";
+
+/// Minor galleries share `/board` with major ones on the mobile site.
+pub fn mobile_board_path(kind: GalleryKind) -> &'static str {
+    match kind {
+        GalleryKind::Major | GalleryKind::Minor => "/board",
+        GalleryKind::Mini => "/mini",
+    }
+}
+
+/// The query of a mobile listing page, without the page. The mobile site always
+/// lists 20 documents a page, so `list_num` is dropped.
+pub fn mobile_listing_query(filter: &ListingFilter) -> String {
+    let mut query = Vec::new();
+    match filter.mode {
+        ListingMode::All => {}
+        ListingMode::Recommend => query.push("recommend=1".to_string()),
+        ListingMode::Notice => query.push("notice=1".to_string()),
+    }
+    if let Some(id) = filter.search_head {
+        query.push(format!("headid={}", id));
+    }
+    query.join("&")
+}
+
+/// Reads a mobile listing page. Dates older than today have no time, and are
+/// taken as midnight in Seoul.
+pub fn parse_mobile_document_indexes(
+    body: &str,
+    gallery_id: &str,
+    now: DateTime<Utc>,
+) -> Result<Vec<Result<DocumentIndex, DocumentParseError>>, DocumentParseError> {
+    if let Some(kind) = detect_block_page(body) {
+        return Err(DocumentParseError::Blocked(kind));
+    }
+    let doc = HTMLDocument::from(body);
+    Ok(doc
+        .select(Class("gall-detail-lnktb"))
+        .enumerate()
+        .map(|(row, node)| -> Result<_, DocumentParseError> {
+            let link = node
+                .select(Name("a").and(Class("lt")))
+                .next()
+                .ok_or_else(|| DocumentParseError::Select {
+                    path: ".gall-detail-lnktb a.lt",
+                    context: SelectContext::new(&node.html()).row(row),
+                })?;
+            // https://m.dcinside.com/board/programming/1595404?page=1
+            let id = link
+                .attr("href")
+                .and_then(|href| href.split('?').next())
+                .and_then(|path| path.rsplit('/').next())
+                .and_then(|id| id.parse().ok())
+                .ok_or(DocumentParseError::NumberParse {
+                    path: ".gall-detail-lnktb a.lt@href",
+                })?;
+            let missing = |path: &'static str| DocumentParseError::Select {
+                path,
+                context: SelectContext::new(&node.html()).row(row).document_id(id),
+            };
+            let title = link
+                .select(Class("subjectin"))
+                .next()
+                .ok_or_else(|| missing(".gall-detail-lnktb .subjectin"))?
+                .text();
+            let icon = link
+                .select(Class("sp-lst"))
+                .next()
+                .and_then(|n| n.attr("class"))
+                .unwrap_or_default();
+            let kind = if icon.contains("img") {
+                DocumentKind::Picture
+            } else if icon.contains("play") {
+                DocumentKind::Video
+            } else {
+                DocumentKind::Text
+            };
+            let infos: Vec<_> = link.select(Class("ginfo").child(Name("li"))).collect();
+            let author_at = infos
+                .iter()
+                .position(|li| matches!(li.attr("class"), Some(c) if c.contains("blockInfo")))
+                .ok_or_else(|| missing(".gall-detail-lnktb .ginfo .blockInfo"))?;
+            let author_node = infos[author_at];
+            let author = mobile_user(
+                author_node
+                    .attr("data-name")
+                    .map(str::to_string)
+                    .unwrap_or_else(|| author_node.text()),
+                author_node.attr("data-info"),
+            );
+            let count = |label: &str, path: &'static str| {
+                infos
+                    .iter()
+                    .map(|li| li.text())
+                    .find_map(|text| {
+                        text.trim()
+                            .strip_prefix(label)
+                            .map(|n| n.trim().replace(',', ""))
+                    })
+                    .map(|n| {
+                        n.parse()
+                            .map_err(|_| DocumentParseError::NumberParse { path })
+                    })
+                    .unwrap_or(Ok(0))
+            };
+            let created_at = infos[author_at + 1..]
+                .iter()
+                .find_map(|li| parse_kst(&li.text(), now))
+                .ok_or(DocumentParseError::DatetimeParse {
+                    path: ".gall-detail-lnktb .ginfo li",
+                })?;
+            Ok(DocumentIndex {
+                gallery_id: gallery_id.to_string(),
+                id,
+                title: title.trim().to_string(),
+                subject: infos[..author_at]
+                    .first()
+                    .map(|li| li.text().trim().to_string()),
+                author,
+                comment_count: node
+                    .select(Class("ct"))
+                    .next()
+                    .and_then(|n| n.text().trim().parse().ok())
+                    .unwrap_or(0),
+                like_count: count("추천", ".gall-detail-lnktb .ginfo 추천")?,
+                view_count: count("조회", ".gall-detail-lnktb .ginfo 조회")?,
+                kind,
+                is_recommend: icon.contains("reco"),
+                created_at,
+            })
+        })
+        .collect())
+}
+
+/// Reads a page of the mobile comment list. Replies only follow their root there,
+/// so the last root of the page before is passed on.
+pub fn parse_mobile_comments(
+    body: &str,
+    last_root_comment_id: Option<usize>,
+    now: DateTime<Utc>,
+) -> Result<(Vec<Comment>, usize), CommentParseError> {
+    if let Some(kind) = detect_block_page(body) {
+        return Err(CommentParseError::Blocked(kind));
+    }
+    let doc = HTMLDocument::from(body);
+    let mut last_root_comment_id = last_root_comment_id;
+    let mut comments = Vec::new();
+    for li in doc.select(Class("all-comment-lst").child(Name("li"))) {
+        let depth = match li.attr("class") {
+            Some(c) if c.split_whitespace().any(|c| c == "comment-add") => 1,
+            Some(c) if c.split_whitespace().any(|c| c == "comment") => 0,
+            // ads and the comment boy
+            _ => continue,
+        };
+        let id: usize =
+            li.attr("no")
+                .and_then(|no| no.parse().ok())
+                .ok_or(CommentParseError::NumberParse {
+                    path: ".all-comment-lst li@no",
+                })?;
+        let nick = li
+            .select(Class("nick"))
+            .next()
+            .ok_or(CommentParseError::Select {
+                path: ".all-comment-lst .nick",
+            })?;
+        let nickname: String = nick.children().filter_map(|c| c.as_text()).collect();
+        let info = nick
+            .select(Class("blockCommentId"))
+            .next()
+            .and_then(|n| n.attr("data-info"));
+        let txt =
+            li.select(Name("p").and(Class("txt")))
+                .next()
+                .ok_or(CommentParseError::Select {
+                    path: ".all-comment-lst p.txt",
+                })?;
+        let contents = txt.inner_html().trim().to_string();
+        let content = comment_content_from_contents(&contents, None);
+        let parent_id = if depth == 0 {
+            last_root_comment_id = Some(id);
+            None
+        } else {
+            last_root_comment_id
+        };
+        comments.push(Comment {
+            id,
+            author: mobile_user(nickname, info),
+            depth,
+            deleted: DELETED_COMMENT_MARKERS.contains(&txt.text().trim()),
+            kind: content.kind(),
+            content,
+            contents,
+            parent_id,
+            created_at: li
+                .select(Class("date"))
+                .next()
+                .and_then(|n| parse_kst(&n.text(), now)),
+            mentioned_ids: Vec::new(),
+        });
+    }
+    let max_page = doc
+        .select(Class("paging-box").descendant(Name("em").or(Name("a"))))
+        .filter_map(|n| n.text().trim().parse::<usize>().ok())
+        .max()
+        .unwrap_or(0);
+    Ok((comments, max_page))
+}
+
+/// Reads the `.thum-txtin` of a mobile view page. The mobile page has no attachment box.
+pub fn parse_mobile_document_body(body: &str) -> Result<DocumentBody, DocumentBodyParseError> {
+    if let Some(kind) = detect_block_page(body) {
+        return Err(DocumentParseError::Blocked(kind).into());
+    }
+    let doc = HTMLDocument::from(body);
+    let div = doc
+        .select(Class("thum-txtin"))
+        .next()
+        .ok_or_else(|| DocumentParseError::Select {
+            path: ".thum-txtin",
+            context: SelectContext::new(body),
+        })?;
+    let mut parsed = DocumentBody {
+        html: Some(div.inner_html()),
+        ..Default::default()
+    };
+    read_body_node(div, &mut parsed);
+    parsed.text = tidy_body_text(&parsed.text);
+    Ok(parsed)
+}
+
+/// `data-info` is the ip prefix of anonymous users and the id of the others.
+fn mobile_user(nickname: String, info: Option<&str>) -> User {
+    let info = info.map(str::trim).filter(|i| !i.is_empty());
+    let (id, ip) = match info {
+        Some(i) if i.chars().all(|c| c.is_ascii_digit() || c == '.') => (None, Some(i)),
+        Some(i) => (Some(i), None),
+        None => (None, None),
+    };
+    User {
+        kind: userkind_from_id_and_ip(&id, &ip),
+        nickname: nickname.trim().to_string(),
+        id: id.map(str::to_string),
+        ip: ip.map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dcinside_model::{CommentContent, CommentKind, UserKind};
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+    fn now() -> DateTime<Utc> {
+        utc("2021-01-10T09:00:00Z")
+    }
+
+    #[test]
+    fn it_parses_mobile_document_indexes() {
+        let res = parse_mobile_document_indexes(
+            include_str!("../assets/mobile_list.html"),
+            "programming",
+            now(),
+        )
+        .unwrap();
+        let res: Vec<_> = res.into_iter().map(Result::unwrap).collect();
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].id, 1595404);
+        assert_eq!(res[0].title, "러스트 비동기 질문");
+        assert_eq!(res[0].subject.as_deref(), Some("질문"));
+        assert_eq!(res[0].author.ip.as_deref(), Some("118.235"));
+        assert_eq!(res[0].author.kind, UserKind::Dynamic);
+        assert_eq!(
+            (res[0].comment_count, res[0].view_count, res[0].like_count),
+            (3, 12, 0)
+        );
+        assert_eq!(res[0].created_at, utc("2021-01-10T06:03:00Z"));
+
+        assert_eq!(res[1].subject, None);
+        assert_eq!(res[1].author.id.as_deref(), Some("nasdaqtrader"));
+        assert_eq!(res[1].author.nickname, "오함마의현인.");
+        assert!(res[1].is_recommend);
+        assert_eq!(res[1].kind, DocumentKind::Picture);
+        assert_eq!(res[1].like_count, 25);
+
+        assert_eq!(res[2].title, "<코딩> 라이브");
+        assert_eq!(res[2].kind, DocumentKind::Video);
+        assert_eq!(res[2].author.kind, UserKind::Unknown);
+        assert_eq!(res[2].created_at, utc("2020-12-30T15:00:00Z"));
+
+        assert!(matches!(
+            parse_mobile_document_indexes("<h1>429 Too Many Requests</h1>", "programming", now()),
+            Err(DocumentParseError::Blocked(_))
+        ));
+    }
+
+    #[test]
+    fn it_parses_mobile_comments() {
+        let (res, max_page) =
+            parse_mobile_comments(include_str!("../assets/mobile_comments.html"), None, now())
+                .unwrap();
+        assert_eq!(max_page, 2);
+        assert_eq!(res.len(), 4);
+        assert_eq!(res[0].id, 13369033);
+        assert_eq!(res[0].author.nickname, "ㅇㅇ");
+        assert_eq!(res[0].author.ip.as_deref(), Some("119.195"));
+        assert_eq!(res[0].created_at, Some(utc("2021-01-10T08:20:00Z")));
+        assert_eq!((res[1].depth, res[1].parent_id), (1, Some(13369033)));
+        assert!(
+            matches!(&res[1].content, CommentContent::Text { mentions, .. } if mentions == &["ㅇㅇ"])
+        );
+        assert_eq!(res[2].kind, CommentKind::Con);
+        assert_eq!(res[2].author.id.as_deref(), Some("qweqweqweqwe2001"));
+        assert!(res[3].deleted);
+        assert_eq!(res[3].created_at, Some(utc("2020-12-31T14:59:00Z")));
+
+        // a reply opening a page belongs to the last root of the page before
+        let body = r#"<ul class="all-comment-lst"><li class="comment-add" no="5"><a class="nick">ㅇㅇ</a><p class="txt">ㅋ</p></li></ul>"#;
+        let (res, max_page) = parse_mobile_comments(body, Some(3), now()).unwrap();
+        assert_eq!((res[0].parent_id, max_page), (Some(3), 0));
+    }
+
+    #[test]
+    fn it_parses_mobile_document_body() {
+        let res = parse_mobile_document_body(include_str!("../assets/mobile_view.html")).unwrap();
+        assert_eq!(
+            res.text,
+            "tokio 0.2 에서 select 쓰는 법 아는 사람?\n\n답변 부탁\n감사"
+        );
+        assert_eq!(res.images.len(), 1);
+        assert!(res.images[0].thumbnail_url.ends_with("&orgExt"));
+    }
+
+    #[test]
+    fn it_parses_mobile_pages_quoting_block_markers() {
+        let quote = "접근이 차단된 아이피입니다. 잠시 후 다시 시도";
+        let list = include_str!("../assets/mobile_list.html").replace("러스트 비동기 질문", quote);
+        let rows = parse_mobile_document_indexes(&list, "programming", now()).unwrap();
+        assert!(!rows.is_empty());
+        assert!(rows.iter().all(|row| row.is_ok()));
+        let view = include_str!("../assets/mobile_view.html").replace("답변 부탁", quote);
+        let body = parse_mobile_document_body(&view).unwrap();
+        assert!(body.text.contains(quote));
+        let comments = include_str!("../assets/mobile_comments.html")
+            .replace("틀딱 들도 대거 들어올듯", quote);
+        let (comments, _) = parse_mobile_comments(&comments, None, now()).unwrap();
+        assert!(!comments.is_empty());
+    }
+
+    #[test]
+    fn it_builds_mobile_queries() {
+        assert_eq!(mobile_listing_query(&ListingFilter::default()), "");
+        assert_eq!(
+            mobile_listing_query(&ListingFilter::recommend().search_head(10).list_num(50)),
+            "recommend=1&headid=10"
+        );
+    }
+}
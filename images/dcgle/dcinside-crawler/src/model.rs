@@ -1,7 +1,10 @@
+use crate::backfill::BackfillCheckpoint;
 use crate::error::*;
-use crate::parse::GalleryIndex;
+use crate::listing::ListingFilter;
+use crate::parse::{CatalogInfo, GalleryIndex};
 
 use chrono::{DateTime, Utc};
+use dcinside_model::RankingEndpoint;
 use serde::{Deserialize, Serialize};
 
 fn default_as_true() -> bool {
@@ -24,6 +27,66 @@ pub struct GalleryState {
     pub last_published_at: Option<DateTime<Utc>>,
     #[serde(default)]
     pub registered_at: Option<DateTime<Utc>>,
+    /// Set once the gallery was seen in the gallery directory.
+    #[serde(default)]
+    pub catalog: Option<CatalogInfo>,
+    /// The list workers walk, for focused crawls of huge galleries.
+    #[serde(default)]
+    pub filter: ListingFilter,
+}
+impl GalleryState {
+    /// A gallery first seen at `now`, not crawled yet.
+    pub fn new(index: GalleryIndex, now: DateTime<Utc>) -> Self {
+        GalleryState {
+            index,
+            last_ranked: now,
+            last_crawled_at: None,
+            last_crawled_document_id: None,
+            visible: true,
+            last_error: None,
+            publish_duration_in_seconds: Some(0.0),
+            last_published_at: None,
+            registered_at: Some(now),
+            catalog: None,
+            filter: ListingFilter::default(),
+        }
+    }
+}
+
+/// A rank of a gallery in one read of a ranking endpoint.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RankPoint {
+    pub endpoint: RankingEndpoint,
+    pub rank: u32,
+    pub ranked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GalleryFilterForm {
+    pub id: String,
+    pub filter: ListingFilter,
+}
+
+/// Starts a backfill of a known gallery. See `BackfillJob` for the bounds.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackfillForm {
+    pub id: String,
+    #[serde(default)]
+    pub start_page: Option<usize>,
+    #[serde(default)]
+    pub start_id: Option<usize>,
+    #[serde(default)]
+    pub until_id: Option<usize>,
+    #[serde(default)]
+    pub until_date: Option<DateTime<Utc>>,
+}
+
+/// Progress of a backfill, reported after every page.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackfillReportForm {
+    pub worker_part: u64,
+    pub id: String,
+    pub checkpoint: BackfillCheckpoint,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -42,11 +105,44 @@ pub enum CrawlerErrorReport {
     MinorGalleryAccessNotAllowed,
     MinorGalleryClosed,
     MinorGalleryPromoted,
+    MiniGalleryAccessNotAllowed,
+    MiniGalleryClosed,
     PageNotFound,
+    RateLimited,
+    Captcha,
+    Maintenance,
+    IpBanned,
+    /// Every egress of the worker is benched.
+    EgressBlocked,
+    /// A selector or a field failed, likely as the markup changed. `path` is its drift key.
+    ParseFailed {
+        path: String,
+    },
+}
+impl CrawlerErrorReport {
+    /// Reported when the worker rather than the gallery is the problem.
+    pub fn is_block(&self) -> bool {
+        matches!(
+            self,
+            CrawlerErrorReport::RateLimited
+                | CrawlerErrorReport::Captcha
+                | CrawlerErrorReport::Maintenance
+                | CrawlerErrorReport::IpBanned
+                | CrawlerErrorReport::EgressBlocked
+        )
+    }
 }
 
 impl From<&CrawlerError> for CrawlerErrorReport {
     fn from(err: &CrawlerError) -> Self {
+        if let Some(kind) = err.block_kind() {
+            return match kind {
+                BlockKind::RateLimit => CrawlerErrorReport::RateLimited,
+                BlockKind::Captcha => CrawlerErrorReport::Captcha,
+                BlockKind::Maintenance => CrawlerErrorReport::Maintenance,
+                BlockKind::IpBan => CrawlerErrorReport::IpBanned,
+            };
+        }
         match err {
             CrawlerError::DocumentParseError(DocumentParseError::MinorGalleryPromoted) => {
                 CrawlerErrorReport::MinorGalleryPromoted
@@ -60,8 +156,20 @@ impl From<&CrawlerError> for CrawlerErrorReport {
             CrawlerError::DocumentParseError(DocumentParseError::MinorGalleryAccessNotAllowed) => {
                 CrawlerErrorReport::MinorGalleryAccessNotAllowed
             }
+            CrawlerError::DocumentParseError(DocumentParseError::MiniGalleryClosed) => {
+                CrawlerErrorReport::MiniGalleryClosed
+            }
+            CrawlerError::DocumentParseError(DocumentParseError::MiniGalleryAccessNotAllowed) => {
+                CrawlerErrorReport::MiniGalleryAccessNotAllowed
+            }
             CrawlerError::PageNotFound => CrawlerErrorReport::PageNotFound,
-            _ => CrawlerErrorReport::Unknown,
+            CrawlerError::EgressBlocked(_) => CrawlerErrorReport::EgressBlocked,
+            _ => match err.drift_key() {
+                Some(path) => CrawlerErrorReport::ParseFailed {
+                    path: path.to_string(),
+                },
+                None => CrawlerErrorReport::Unknown,
+            },
         }
     }
 }
@@ -72,4 +180,7 @@ pub struct GalleryCrawlErrorReportForm {
     pub id: String,
     pub last_crawled_at: Option<DateTime<Utc>>,
     pub error: CrawlerErrorReport,
+    /// The newest document published before the listing failed, so it is not sent again.
+    #[serde(default)]
+    pub last_crawled_document_id: Option<usize>,
 }
@@ -1,7 +1,9 @@
+use crate::date::parse_kst;
 use crate::error::*;
-use chrono::{DateTime, Datelike, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, Utc};
 use dcinside_model::{
-    Comment, CommentKind, Document, DocumentKind, Gallery, GalleryKind, User, UserKind,
+    Attachment, BodyImage, BodyVideo, Comment, CommentContent, CommentKind, Dccon, Document,
+    DocumentBody, DocumentKind, Gallery, GalleryInfo, GalleryKind, User, UserKind,
 };
 use serde::{
     de::{self, IgnoredAny, MapAccess, Visitor},
@@ -10,7 +12,7 @@ use serde::{
 
 use select::{
     document::Document as HTMLDocument,
-    predicate::{Class, Name, Predicate},
+    predicate::{Attr, Class, Name, Predicate},
 };
 
 #[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
@@ -44,11 +46,7 @@ pub struct DocumentIndex {
 
 impl From<GalleryIndex> for Gallery {
     fn from(o: GalleryIndex) -> Self {
-        Self {
-            id: o.id,
-            name: o.name,
-            kind: o.kind,
-        }
+        gallery_from_index(o)
     }
 }
 
@@ -66,6 +64,10 @@ pub fn gallery_from_index(o: GalleryIndex) -> Gallery {
         id: o.id,
         name: o.name,
         kind: o.kind,
+        info: o.rank.map(|rank| GalleryInfo {
+            rank: Some(rank as u32),
+            ..Default::default()
+        }),
     }
 }
 
@@ -73,7 +75,7 @@ pub fn document_from_indexes(
     gallery_index: GalleryIndex,
     doc_index: DocumentIndex,
     comments: Option<Vec<Comment>>,
-    body: Option<String>,
+    body: Option<DocumentBody>,
 ) -> Document {
     Document {
         gallery: gallery_index.into(),
@@ -109,13 +111,57 @@ pub fn userkind_from_id_and_ip<T1: AsRef<str>, T2: AsRef<str>>(
 }
 
 pub fn commentkind_from_contents(contents: &str) -> CommentKind {
-    if contents.starts_with("<img") {
-        CommentKind::Con
-    } else if contents.starts_with("vr/") {
-        CommentKind::Voice
-    } else {
-        CommentKind::Text
+    comment_content_from_contents(contents, None).kind()
+}
+
+/// Reads the `memo` of a comment. `voice` is the id of its recording, when the
+/// response tells it apart from the memo.
+pub fn comment_content_from_contents(contents: &str, voice: Option<&str>) -> CommentContent {
+    let voice = voice
+        .filter(|v| !v.is_empty())
+        .or_else(|| contents.strip_prefix("vr/"));
+    if let Some(id) = voice {
+        return CommentContent::Voice {
+            id: id.to_string(),
+            url: format!("https://gall.dcinside.com/voice/player?vr={}", id),
+        };
+    }
+    let doc = HTMLDocument::from(contents);
+    // dccons are an <img>, or a <video> for animated ones
+    let con = doc.select(Class("written_dccon")).next().or_else(|| {
+        doc.select(Name("img"))
+            .find(|img| matches!(img.attr("src"), Some(src) if src.contains("dccon.php")))
+    });
+    if let Some(con) = con {
+        if let Some(url) = con.attr("src").or_else(|| con.attr("data-src")) {
+            // onmousedown="mp4_overlay_dccon(this, '...',' conalt=잡아라 alt=잡아라 title=잡아라');"
+            let title = con.attr("title").map(str::to_string).or_else(|| {
+                con.attr("onmousedown")
+                    .and_then(|s| s.split_once(" title="))
+                    .and_then(|(_, rest)| rest.split('\'').next())
+                    .map(|title| title.trim().to_string())
+            });
+            return CommentContent::Con {
+                package_id: query_param(url, "package_idx").map(str::to_string),
+                image_id: query_param(url, "no").unwrap_or_default().to_string(),
+                url: url.to_string(),
+                title,
+            };
+        }
+    }
+    let mut body = DocumentBody::default();
+    if let Some(root) = doc.select(Name("body")).next() {
+        read_body_node(root, &mut body);
     }
+    let text = tidy_body_text(&body.text);
+    let mentions = text
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|name| name.trim_end_matches(|c: char| c.is_ascii_punctuation()))
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    CommentContent::Text { text, mentions }
 }
 
 #[derive(Debug, PartialEq)]
@@ -125,20 +171,30 @@ pub struct FromComment {
     pub depth: usize,
     pub contents: String,
     pub kind: CommentKind,
+    pub content: CommentContent,
     pub parent_id: Option<usize>,
-    pub created_at: Option<DateTime<Utc>>,
+    /// `reg_date` as it is, like `01.10 17:20:43`.
+    pub created_at: Option<String>,
+    pub deleted: bool,
+    pub mentioned_ids: Vec<usize>,
 }
 
-impl From<FromComment> for Comment {
-    fn from(o: FromComment) -> Comment {
+impl FromComment {
+    /// Dates without a year are read as of `reference`, the time the comments were fetched.
+    pub fn into_comment(self, reference: DateTime<Utc>) -> Comment {
         Comment {
-            id: o.id,
-            author: o.author,
-            depth: o.depth,
-            contents: o.contents,
-            kind: o.kind,
-            parent_id: o.parent_id,
-            created_at: o.created_at,
+            id: self.id,
+            author: self.author,
+            depth: self.depth,
+            contents: self.contents,
+            kind: self.kind,
+            content: self.content,
+            parent_id: self.parent_id,
+            created_at: self
+                .created_at
+                .and_then(|created_at| parse_kst(&created_at, reference)),
+            deleted: self.deleted,
+            mentioned_ids: self.mentioned_ids,
         }
     }
 }
@@ -173,9 +229,11 @@ impl<'de> Deserialize<'de> for FromComment {
                 let mut author_ip: Option<String> = None;
                 let mut author_nickname = None;
                 let mut depth = None;
-                let mut contents = None;
-                let mut kind = None;
+                let mut contents: Option<String> = None;
+                let mut voice: Option<StringOrInt> = None;
+                let mut reply_to: Option<StringOrInt> = None;
                 let mut created_at = None;
+                let mut deleted: Option<bool> = None;
                 while let Some(key) = map.next_key()? {
                     match key {
                         "no" => {
@@ -194,9 +252,14 @@ impl<'de> Deserialize<'de> for FromComment {
                             depth = Some(map.next_value()?);
                         }
                         "memo" => {
-                            let val = map.next_value::<String>()?;
-                            kind = Some(commentkind_from_contents(&val));
-                            contents = Some(val);
+                            contents = Some(map.next_value()?);
+                        }
+                        "voice" => {
+                            voice = map.next_value()?;
+                        }
+                        // the root a reply is written under. `parent` is the document
+                        "c_no" => {
+                            reply_to = map.next_value()?;
                         }
                         "reg_date" => {
                             created_at = Some(map.next_value::<String>()?);
@@ -204,31 +267,26 @@ impl<'de> Deserialize<'de> for FromComment {
                         "comment_date" => {
                             created_at = Some(map.next_value::<String>()?);
                         }
+                        "del_yn" => {
+                            deleted = map.next_value::<Option<String>>()?.map(|v| v == "Y");
+                        }
                         _ => {
                             map.next_value::<IgnoredAny>()?;
                         }
                     }
                 }
-                let created_at = created_at.and_then(|s| {
-                    NaiveDateTime::parse_from_str(&s, "%Y.%m.%d %H:%M:%S")
-                        .or_else(|_| {
-                            NaiveDateTime::parse_from_str(
-                                &format!(
-                                    "{}.{}",
-                                    Utc::now().with_timezone(&chrono_tz::Asia::Seoul).year(),
-                                    s
-                                ),
-                                "%Y.%m.%d %H:%M:%S",
-                            )
-                        })
-                        .map(|created_at_without_tz| {
-                            chrono_tz::Asia::Seoul
-                                .from_local_datetime(&created_at_without_tz)
-                                .unwrap()
-                                .with_timezone(&Utc)
-                        })
-                        .ok()
+                let contents = contents.ok_or_else(|| de::Error::missing_field("memo"))?;
+                let voice = voice.map(|v| match v {
+                    StringOrInt::String(s) => s,
+                    StringOrInt::Number(u) => u.to_string(),
                 });
+                let content = comment_content_from_contents(&contents, voice.as_deref());
+                let parent_id = match reply_to {
+                    Some(StringOrInt::String(s)) => s.parse().ok(),
+                    Some(StringOrInt::Number(u)) => Some(u),
+                    None => None,
+                }
+                .filter(|id| *id > 0);
                 Ok(FromComment {
                     id: match id.ok_or_else(|| de::Error::missing_field("no"))? {
                         StringOrInt::String(s) => s
@@ -244,10 +302,14 @@ impl<'de> Deserialize<'de> for FromComment {
                         id: author_id.and_then(|i| if i.is_empty() { None } else { Some(i) }),
                     },
                     depth: depth.ok_or_else(|| de::Error::missing_field("depth"))?,
-                    contents: contents.ok_or_else(|| de::Error::missing_field("memo"))?,
-                    kind: kind.ok_or_else(|| de::Error::missing_field("memo"))?,
+                    deleted: deleted
+                        .unwrap_or_else(|| DELETED_COMMENT_MARKERS.contains(&contents.trim())),
+                    kind: content.kind(),
+                    content,
+                    contents,
                     created_at,
-                    parent_id: None,
+                    parent_id,
+                    mentioned_ids: Vec::new(),
                 })
             }
         }
@@ -257,34 +319,314 @@ impl<'de> Deserialize<'de> for FromComment {
             "depth",
             "contents",
             "kind",
+            "content",
             "created_at",
             "parent_id",
+            "mentioned_ids",
         ];
         deserializer.deserialize_struct("FromComment", FIELDS, CommentVisitor)
     }
 }
 
+/// Contents shown in place of a deleted comment, for responses without `del_yn`.
+pub(crate) const DELETED_COMMENT_MARKERS: [&str; 3] = [
+    "삭제된 댓글입니다.",
+    "해당 댓글은 삭제되었습니다.",
+    "이 댓글은 게시물 작성자가 삭제하였습니다.",
+];
+
+const DELETED_DOCUMENT_MARKERS: [&str; 3] = ["삭제된 게시물", "게시물은 삭제", "게시물이 삭제"];
+
+const BLOCK_MARKERS: [(BlockKind, &[&str]); 4] = [
+    (
+        BlockKind::Captcha,
+        &["kcaptcha", "recaptcha", "자동입력 방지"],
+    ),
+    (BlockKind::IpBan, &["차단된 IP", "IP가 차단", "접근이 차단"]),
+    (
+        BlockKind::Maintenance,
+        &["서비스 점검", "시스템 점검", "점검 중입니다"],
+    ),
+    (
+        BlockKind::RateLimit,
+        &["Too Many Requests", "과도한 요청", "잠시 후 다시 시도"],
+    ),
+];
+
+/// Containers only regular mobile pages have: list rows, the comment list and the body.
+const MOBILE_PAGE_MARKERS: [&str; 3] = [
+    "class=\"gall-detail-lnktb\"",
+    "class=\"all-comment-lst\"",
+    "class=\"thum-txtin\"",
+];
+
+/// Recognizes the pages served in place of a list, a document or comments when blocked.
+/// Regular pages carry `e_s_n_o` or, on mobile, one of their containers, and comments
+/// are json, so none is ever taken for one, whatever users write in them.
+pub fn detect_block_page(body: &str) -> Option<BlockKind> {
+    let trimed = body.trim_start();
+    if trimed.starts_with('{')
+        || trimed.starts_with('[')
+        || body.contains("id=\"e_s_n_o\"")
+        || MOBILE_PAGE_MARKERS.iter().any(|m| body.contains(m))
+    {
+        return None;
+    }
+    BLOCK_MARKERS
+        .iter()
+        .find(|(_, markers)| markers.iter().any(|m| body.contains(m)))
+        .map(|(kind, _)| *kind)
+}
+
+/// The `e_s_n_o` token of listing and view pages.
+pub fn parse_e_s_n_o(body: &str) -> Result<String, DocumentParseError> {
+    Ok(HTMLDocument::from(body)
+        .select(Attr("id", "e_s_n_o"))
+        .next()
+        .ok_or_else(|| DocumentParseError::Select {
+            path: ".e_s_n_o",
+            context: SelectContext::new(body),
+        })?
+        .attr("value")
+        .ok_or_else(|| DocumentParseError::Select {
+            path: ".e_s_n_o@value",
+            context: SelectContext::new(body),
+        })?
+        .to_string())
+}
+
+/// Recognizes the page served in place of a deleted document. Like block pages,
+/// it never carries `e_s_n_o`.
+pub fn is_deleted_document_page(body: &str) -> bool {
+    !body.contains("id=\"e_s_n_o\"") && DELETED_DOCUMENT_MARKERS.iter().any(|m| body.contains(m))
+}
+
+/// Reads the `.write_div` of a document view page. `html` is always filled here.
 pub fn parse_document_body(
     body: &str,
     _gallery_id: &str,
-    _document_id: usize,
-) -> Result<String, DocumentBodyParseError> {
+    document_id: usize,
+) -> Result<DocumentBody, DocumentBodyParseError> {
+    if let Some(kind) = detect_block_page(body) {
+        return Err(DocumentParseError::Blocked(kind).into());
+    }
     let doc = HTMLDocument::from(body);
-    Ok(doc
+    let div = doc
         .select(Class("write_div"))
         .next()
-        .ok_or(DocumentParseError::Select {
+        .ok_or_else(|| DocumentParseError::Select {
             path: ".write_div",
-            html: body.to_string(),
-        })?
-        .inner_html())
-    //.next().ok_or(DocumentParseError::Select { path: ".write_div" })?
-    //.text().trim().to_string())
+            context: SelectContext::new(body).document_id(document_id),
+        })?;
+    let mut parsed = DocumentBody {
+        html: Some(div.inner_html()),
+        ..Default::default()
+    };
+    read_body_node(div, &mut parsed);
+    parsed.text = tidy_body_text(&parsed.text);
+    parsed.attachments = parse_attachments(&doc, &parsed.images);
+    Ok(parsed)
+}
+
+/// Files of `.appending_file`. Images share the `no` of their download link with
+/// the `imgPop` link of the body image.
+fn parse_attachments(doc: &HTMLDocument, images: &[BodyImage]) -> Vec<Attachment> {
+    doc.select(Class("appending_file").descendant(Name("a")))
+        .filter_map(|a| {
+            let download_url = a.attr("href")?;
+            let no = query_param(download_url, "no");
+            let image_url = images
+                .iter()
+                .filter_map(|image| image.original_url.as_deref())
+                .find(|url| no.is_some() && query_param(url, "no") == no)
+                .map(str::to_string);
+            Some(Attachment {
+                name: a.text().trim().to_string(),
+                download_url: download_url.to_string(),
+                image_url,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn read_body_node(node: select::node::Node, body: &mut DocumentBody) {
+    for child in node.children() {
+        if let Some(text) = child.as_text() {
+            // line breaks of the markup source are not line breaks of the text
+            body.text.push_str(&text.replace(char::is_whitespace, " "));
+            continue;
+        }
+        match child.name() {
+            Some("br") => body.text.push('\n'),
+            Some("img") => read_body_image(child, body),
+            Some("iframe") | Some("embed") | Some("video") => {
+                let src = child.attr("src").or_else(|| {
+                    child
+                        .select(Name("source"))
+                        .next()
+                        .and_then(|source| source.attr("src"))
+                });
+                if let Some(url) = src {
+                    body.videos.push(BodyVideo {
+                        url: url.to_string(),
+                        youtube_id: youtube_id(url),
+                    });
+                }
+            }
+            Some("script") | Some("style") => {}
+            Some(name) => {
+                if name == "a" {
+                    if let Some(href) = child.attr("href") {
+                        if href.starts_with("http") && !body.links.iter().any(|l| l == href) {
+                            body.links.push(href.to_string());
+                        }
+                    }
+                }
+                if name == "blockquote" {
+                    let quote = tidy_body_text(&child.text());
+                    if !quote.is_empty() {
+                        body.quotes.push(quote);
+                    }
+                }
+                let block = matches!(
+                    name,
+                    "p" | "div"
+                        | "blockquote"
+                        | "li"
+                        | "ul"
+                        | "ol"
+                        | "table"
+                        | "tr"
+                        | "h1"
+                        | "h2"
+                        | "h3"
+                );
+                if block {
+                    body.text.push('\n');
+                }
+                read_body_node(child, body);
+                if block {
+                    body.text.push('\n');
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+fn read_body_image(img: select::node::Node, body: &mut DocumentBody) {
+    let src = match img.attr("data-original").or_else(|| img.attr("src")) {
+        Some(src) if !src.is_empty() => src,
+        _ => return,
+    };
+    if src.contains("dccon.php") || matches!(img.attr("class"), Some(c) if c.contains("dccon")) {
+        body.dccons.push(Dccon {
+            url: src.to_string(),
+            title: img
+                .attr("title")
+                .or_else(|| img.attr("alt"))
+                .map(str::to_string),
+        });
+        return;
+    }
+    // onclick="javascript:imgPop('https://image.dcinside.com/viewimagePop.php?...','image',...)"
+    let original_url = img
+        .attr("onclick")
+        .and_then(|onclick| onclick.split_once("imgPop('"))
+        .and_then(|(_, rest)| rest.split_once('\''))
+        .map(|(url, _)| url.to_string());
+    body.images.push(BodyImage {
+        thumbnail_url: src.to_string(),
+        original_url,
+    });
+}
+
+/// Id of a YouTube video from its embed, watch or short url.
+fn youtube_id(url: &str) -> Option<String> {
+    let id = if let Some((_, rest)) = url.split_once("youtube.com/embed/") {
+        rest
+    } else if let Some((_, rest)) = url.split_once("youtu.be/") {
+        rest
+    } else if url.contains("youtube.com/watch") {
+        query_param(url, "v")?
+    } else {
+        return None;
+    };
+    let id = id.split(&['?', '&', '/', '#'][..]).next()?;
+    if id.is_empty() {
+        None
+    } else {
+        Some(id.to_string())
+    }
+}
+
+/// Collapses spaces in lines and keeps at most one blank line between them.
+pub(crate) fn tidy_body_text(text: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.split('\n') {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() && !matches!(lines.last(), Some(l) if !l.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while matches!(lines.last(), Some(l) if l.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DocumentCounts {
+    pub comment_count: u32,
+    pub like_count: u32,
+    pub view_count: u32,
+}
+
+/// Reads the counts in the head of a document view page.
+pub fn parse_document_counts(body: &str) -> Result<DocumentCounts, DocumentParseError> {
+    if let Some(kind) = detect_block_page(body) {
+        return Err(DocumentParseError::Blocked(kind));
+    }
+    if is_deleted_document_page(body) {
+        return Err(DocumentParseError::Deleted);
+    }
+    let doc = HTMLDocument::from(body);
+    let head =
+        doc.select(Class("gallview_head"))
+            .next()
+            .ok_or_else(|| DocumentParseError::Select {
+                path: ".gallview_head",
+                context: SelectContext::new(body),
+            })?;
+    let count = |class: &'static str, path: &'static str| -> Result<u32, DocumentParseError> {
+        head.select(Class(class))
+            .next()
+            .ok_or_else(|| DocumentParseError::Select {
+                path,
+                context: SelectContext::new(body),
+            })?
+            .text()
+            .chars()
+            .filter(|c| c.is_ascii_digit())
+            .collect::<String>()
+            .parse()
+            .map_err(|_| DocumentParseError::NumberParse { path })
+    };
+    Ok(DocumentCounts {
+        view_count: count("gall_count", ".gallview_head .gall_count")?,
+        like_count: count("gall_reply_num", ".gallview_head .gall_reply_num")?,
+        comment_count: count("gall_comment", ".gallview_head .gall_comment")?,
+    })
+}
+
+/// Rows of a listing page. A row without the full date in `.gall_date@title` is dated
+/// by its text, as of `now`.
 pub fn parse_document_indexes(
     body: &str,
     gallery_id: &str,
+    now: DateTime<Utc>,
 ) -> Result<Vec<Result<DocumentIndex, DocumentParseError>>, DocumentParseError> {
     let doc = HTMLDocument::from(body);
 
@@ -298,6 +640,12 @@ pub fn parse_document_indexes(
         } else {
             return Err(DocumentParseError::MinorGalleryAccessNotAllowed);
         }
+    } else if body.starts_with("<script type=\"text/javascript\">alert(\"해당 미니 갤러리는") {
+        if body.contains("폐쇄") {
+            return Err(DocumentParseError::MiniGalleryClosed);
+        } else {
+            return Err(DocumentParseError::MiniGalleryAccessNotAllowed);
+        }
     } else if body.starts_with("<script type=\"text/javascript\">location.replace(\"https://gall.dcinside.com/board/lists?") {
         return Err(DocumentParseError::MinorGalleryPromoted);
     } else if let Some(node) = doc.select(Class("migall_state")).next() {
@@ -306,46 +654,44 @@ pub fn parse_document_indexes(
                 return Err(DocumentParseError::MinorGalleryAccessNotAllowed);
             }
         }
+    } else if let Some(kind) = detect_block_page(body) {
+        return Err(DocumentParseError::Blocked(kind));
     }
 
     Ok(doc
         .select(Class("us-post"))
-        .map(|node| -> Result<_, DocumentParseError> {
+        .enumerate()
+        .map(|(row, node)| -> Result<_, DocumentParseError> {
             let id = node
                 .select(Class("gall_num"))
                 .next()
-                .ok_or(DocumentParseError::Select {
+                .ok_or_else(|| DocumentParseError::Select {
                     path: ".us-post .gall_num",
-                    html: body.to_string(),
+                    context: SelectContext::new(&node.html()).row(row),
                 })?
                 .text()
                 .parse()
                 .map_err(|_| DocumentParseError::NumberParse {
                     path: ".us-post .gall_num",
                 })?;
+            let missing = |path: &'static str| DocumentParseError::Select {
+                path,
+                context: SelectContext::new(&node.html()).row(row).document_id(id),
+            };
             let title = node
                 .select(Class("gall_tit").descendant(Name("a")))
                 .next()
-                .ok_or(DocumentParseError::Select {
-                    path: ".us-post .gall_tit",
-                    html: body.to_string(),
-                })?
+                .ok_or_else(|| missing(".us-post .gall_tit"))?
                 .text();
             let subject = node.select(Class("gall_subject")).next().map(|n| n.text());
             let author = {
-                let writer_node =
-                    node.select(Class("gall_writer"))
-                        .next()
-                        .ok_or(DocumentParseError::Select {
-                            path: ".us-post .gall_writer",
-                            html: body.to_string(),
-                        })?;
+                let writer_node = node
+                    .select(Class("gall_writer"))
+                    .next()
+                    .ok_or_else(|| missing(".us-post .gall_writer"))?;
                 let nickname = writer_node
                     .attr("data-nick")
-                    .ok_or(DocumentParseError::Select {
-                        path: ".us-post .gall_writer@data-nick",
-                        html: body.to_string(),
-                    })?;
+                    .ok_or_else(|| missing(".us-post .gall_writer@data-nick"))?;
                 let ip = writer_node.attr("data-ip");
                 let id = writer_node.attr("data-uid");
                 User {
@@ -381,10 +727,7 @@ pub fn parse_document_indexes(
             let like_count = node
                 .select(Class("gall_recommend"))
                 .next()
-                .ok_or(DocumentParseError::Select {
-                    path: ".us-post .gall_recommned",
-                    html: body.to_string(),
-                })?
+                .ok_or_else(|| missing(".us-post .gall_recommned"))?
                 .text()
                 .parse()
                 .map_err(|_| DocumentParseError::NumberParse {
@@ -393,10 +736,7 @@ pub fn parse_document_indexes(
             let view_count = node
                 .select(Class("gall_count"))
                 .next()
-                .ok_or(DocumentParseError::Select {
-                    path: ".us-post .gall_count",
-                    html: body.to_string(),
-                })?
+                .ok_or_else(|| missing(".us-post .gall_count"))?
                 .text()
                 .parse()
                 .map_err(|_| DocumentParseError::NumberParse {
@@ -422,27 +762,17 @@ pub fn parse_document_indexes(
                 .next()
                 .map(|_| true)
                 .unwrap_or(false);
-            let created_at_text = node
+            let date = node
                 .select(Class("gall_date"))
                 .next()
-                .ok_or(DocumentParseError::Select {
-                    path: ".us-post .gall_date",
-                    html: body.to_string(),
-                })?
+                .ok_or_else(|| missing(".us-post .gall_date"))?;
+            let created_at = date
                 .attr("title")
-                .ok_or(DocumentParseError::Select {
-                    path: ".us-post .gall_date@title",
-                    html: body.to_string(),
+                .and_then(|title| parse_kst(title, now))
+                .or_else(|| parse_kst(&date.text(), now))
+                .ok_or(DocumentParseError::DatetimeParse {
+                    path: ".us-post .gall_date",
                 })?;
-            let created_at_without_tz =
-                NaiveDateTime::parse_from_str(created_at_text.trim(), "%Y-%m-%d %H:%M:%S")
-                    .map_err(|_| DocumentParseError::DatetimeParse {
-                        path: ".us-post .gall_date@title",
-                    })?;
-            let created_at = chrono_tz::Asia::Seoul
-                .from_local_datetime(&created_at_without_tz)
-                .unwrap()
-                .with_timezone(&Utc);
             Ok(DocumentIndex {
                 id,
                 title,
@@ -465,16 +795,25 @@ struct _CommentsResponse {
     comments: Option<Vec<FromComment>>,
     pagination: Option<String>,
 }
+/// Comments of a page of a comment list. Replies name their root by `c_no`, so a
+/// page doesn't need the pages before it. Dates are read as of `now`.
 pub fn parse_comments(
     body: &str,
     gallery_id: &str,
     document_id: usize,
-    last_root_comment_id: Option<usize>,
+    now: DateTime<Utc>,
 ) -> Result<(Vec<Comment>, usize), CommentParseError> {
+    if let Some(kind) = detect_block_page(body) {
+        return Err(CommentParseError::Blocked(kind));
+    }
+    // a refused e_s_n_o gets an empty response instead of json
+    if body.trim().is_empty() {
+        return Err(CommentParseError::InvalidToken);
+    }
     let body: _CommentsResponse =
         serde_json::from_str(body).map_err(|e| CommentParseError::JsonParse {
             source: e,
-            target: body.to_string(),
+            snippet: snippet(body),
             gallery_id: gallery_id.to_string(),
             doc_id: document_id,
         })?;
@@ -487,19 +826,19 @@ pub fn parse_comments(
                 .map(|t| t.text().parse::<usize>().unwrap_or(0))
                 .fold(0usize, |acc, x| if acc > x { acc } else { x });
             if let Some(mut comments) = body.comments {
-                let mut last_root_comment_id = if let Some(id) = last_root_comment_id {
-                    id
-                } else {
-                    0usize
-                };
+                // responses without `c_no` list replies right after their root
+                let mut last_root_comment_id = None;
                 for c in comments.iter_mut() {
                     if c.depth == 0 && c.id > 0 {
-                        last_root_comment_id = c.id;
-                    } else if last_root_comment_id > 0 {
-                        c.parent_id = Some(last_root_comment_id);
+                        last_root_comment_id = Some(c.id);
+                    } else if c.depth > 0 && c.parent_id.is_none() {
+                        c.parent_id = last_root_comment_id;
                     }
                 }
-                Ok((comments.into_iter().map(Comment::from).collect(), max_page))
+                Ok((
+                    comments.into_iter().map(|c| c.into_comment(now)).collect(),
+                    max_page,
+                ))
             } else {
                 Ok((Vec::new(), max_page))
             }
@@ -507,9 +846,267 @@ pub fn parse_comments(
     }
 }
 
+/// Points mentions of text comments at the latest earlier comment by the mentioned
+/// nickname, looking in the same thread first. `comments` are all pages of a document.
+pub fn attach_mentions(comments: &mut [Comment]) {
+    let targets: Vec<_> = comments
+        .iter()
+        .map(|c| {
+            let mentions = match &c.content {
+                CommentContent::Text { mentions, .. } => mentions,
+                _ => return Vec::new(),
+            };
+            let thread = c.parent_id.unwrap_or(c.id);
+            mentions
+                .iter()
+                .filter_map(|nickname| {
+                    let earlier = || {
+                        comments.iter().filter(move |o| {
+                            o.id > 0 && o.id < c.id && &o.author.nickname == nickname
+                        })
+                    };
+                    earlier()
+                        .filter(|o| o.parent_id.unwrap_or(o.id) == thread)
+                        .map(|o| o.id)
+                        .max()
+                        .or_else(|| earlier().map(|o| o.id).max())
+                })
+                .collect()
+        })
+        .collect();
+    for (c, ids) in comments.iter_mut().zip(targets) {
+        c.mentioned_ids = ids;
+    }
+}
+
+/// A category of the gallery directory.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CatalogCategory {
+    pub id: String,
+    pub name: String,
+}
+
+/// What the gallery directory tells about a gallery besides its index.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CatalogInfo {
+    pub category: Option<String>,
+    pub description: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub manager_id: Option<String>,
+    pub manager_nickname: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CatalogEntry {
+    pub index: GalleryIndex,
+    pub info: CatalogInfo,
+}
+
+fn query_param<'a>(href: &'a str, key: &str) -> Option<&'a str> {
+    href.split_once('?')?
+        .1
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+        .filter(|v| !v.is_empty())
+}
+
+/// The categories listed on a directory page.
+pub fn parse_catalog_categories(body: &str) -> Result<Vec<CatalogCategory>, DocumentParseError> {
+    if let Some(kind) = detect_block_page(body) {
+        return Err(DocumentParseError::Blocked(kind));
+    }
+    let doc = HTMLDocument::from(body);
+    let list = doc
+        .select(Class("cate_list"))
+        .next()
+        .ok_or_else(|| DocumentParseError::Select {
+            path: ".cate_list",
+            context: SelectContext::new(body),
+        })?;
+    Ok(list
+        .select(Name("a"))
+        .filter_map(|a| {
+            Some(CatalogCategory {
+                id: query_param(a.attr("href")?, "cate")?.to_string(),
+                name: a.text().trim().to_string(),
+            })
+        })
+        .collect())
+}
+
+/// The galleries listed on a page of a directory category. An empty vec means
+/// the category has no more pages.
+pub fn parse_catalog_entries(
+    body: &str,
+    kind: GalleryKind,
+    category: Option<&str>,
+) -> Result<Vec<CatalogEntry>, DocumentParseError> {
+    if let Some(kind) = detect_block_page(body) {
+        return Err(DocumentParseError::Blocked(kind));
+    }
+    let doc = HTMLDocument::from(body);
+    if doc.select(Class("gall_catalog")).next().is_none() {
+        return Err(DocumentParseError::Select {
+            path: ".gall_catalog",
+            context: SelectContext::new(body),
+        });
+    }
+    let text_of = |item: &select::node::Node, class: &'static str| {
+        item.select(Class(class))
+            .next()
+            .map(|n| n.text().trim().to_string())
+            .filter(|t| !t.is_empty() && t != "-")
+    };
+    doc.select(Class("gall_catalog").descendant(Class("catalog_item")))
+        .map(|item| {
+            let link = item
+                .select(Class("gall_name").descendant(Name("a")))
+                .next()
+                .ok_or_else(|| DocumentParseError::Select {
+                    path: ".catalog_item .gall_name a",
+                    context: SelectContext::new(&item.html()),
+                })?;
+            let id = link
+                .attr("href")
+                .and_then(|href| query_param(href, "id"))
+                .ok_or_else(|| DocumentParseError::Select {
+                    path: ".catalog_item .gall_name a@href",
+                    context: SelectContext::new(&item.html()),
+                })?;
+            let created_at = match text_of(&item, "gall_date") {
+                Some(date) => Some(parse_kst(&date, Utc::now()).ok_or(
+                    DocumentParseError::DatetimeParse {
+                        path: ".catalog_item .gall_date",
+                    },
+                )?),
+                None => None,
+            };
+            let manager = item.select(Class("gall_manager")).next();
+            let manager_nickname = text_of(&item, "gall_manager");
+            let manager_id = manager_nickname
+                .as_ref()
+                .and(manager.and_then(|m| m.attr("data-uid")))
+                .map(str::to_string);
+            Ok(CatalogEntry {
+                index: GalleryIndex {
+                    id: id.to_string(),
+                    name: link.text().trim().to_string(),
+                    kind,
+                    rank: None,
+                },
+                info: CatalogInfo {
+                    category: category.map(str::to_string),
+                    description: text_of(&item, "gall_desc"),
+                    created_at,
+                    manager_id,
+                    manager_nickname,
+                },
+            })
+        })
+        .collect()
+}
+
+/// The manager id of a `.mng_nick`, either in the title of its inner span when the
+/// nickname is cut or in the parentheses after it.
+fn manager_id(nick: select::node::Node) -> Option<String> {
+    if let Some(id) = nick
+        .select(Attr("title", ()))
+        .next()
+        .and_then(|n| n.attr("title"))
+    {
+        return Some(id.to_string());
+    }
+    let text = nick.text();
+    let text = text.trim();
+    let start = text.rfind('(')? + 1;
+    let end = start + text[start..].find(')')?;
+    Some(&text[start..end])
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+}
+
+/// Reads the information box and the 말머리 tabs of a listing page. Major galleries
+/// show no information box, so only `subjects` may be filled for them.
+pub fn parse_gallery_info(body: &str) -> GalleryInfo {
+    let doc = HTMLDocument::from(body);
+    let mut info = GalleryInfo::default();
+    for cont in doc.select(Class("info_cont")) {
+        let (title, value) = match (
+            cont.select(Class("tit")).next(),
+            cont.select(Class("cont")).next(),
+        ) {
+            (Some(title), Some(value)) => (title.text(), value),
+            _ => continue,
+        };
+        match title.trim() {
+            "매니저" => info.manager_id = value.select(Class("mng_nick")).find_map(manager_id),
+            "부매니저" => {
+                info.sub_manager_ids = value
+                    .select(Class("mng_nick"))
+                    .filter_map(manager_id)
+                    .collect()
+            }
+            "개설일" => info.created_at = parse_kst(&value.text(), Utc::now()),
+            t if t.contains("멤버") || t.contains("구독") => {
+                info.member_count = value
+                    .text()
+                    .chars()
+                    .filter(|c| c.is_ascii_digit())
+                    .collect::<String>()
+                    .parse()
+                    .ok()
+            }
+            _ => {}
+        }
+    }
+    info.subjects = doc
+        .select(Class("center_box").descendant(Name("a")))
+        .filter(|a| matches!(a.attr("onclick"), Some(f) if f.starts_with("listSearchHead(")))
+        .map(|a| a.text().trim().to_string())
+        .filter(|t| !t.is_empty() && t != "전체")
+        .collect();
+    info
+}
+
+/// Where an in-gallery search goes after a result page. dcinside searches a chunk of
+/// documents at a time; `next_search_pos` starts the next older chunk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchPaging {
+    pub has_next_page: bool,
+    pub next_search_pos: Option<i64>,
+}
+
+pub fn parse_search_paging(body: &str, page: usize) -> SearchPaging {
+    let doc = HTMLDocument::from(body);
+    let next_page = (page + 1).to_string();
+    let links: Vec<_> = doc
+        .select(Class("bottom_paging_box").descendant(Name("a")))
+        .collect();
+    SearchPaging {
+        has_next_page: links.iter().any(|a| {
+            a.attr("href").and_then(|href| query_param(href, "page")) == Some(next_page.as_str())
+        }),
+        next_search_pos: links
+            .iter()
+            .find(|a| a.is(Class("search_next")))
+            .and_then(|a| query_param(a.attr("href")?, "search_pos")?.parse().ok()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
+    use dcinside_model::CommentThread;
+
+    /// A while after the comments in the fixtures were written.
+    fn crawled_at() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2021-01-20T23:00:00+09:00")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
 
     macro_rules! assert_err {
         ($expression:expr, $($pattern:tt)+) => {
@@ -522,8 +1119,12 @@ mod tests {
 
     #[test]
     fn it_parses_document_indexes() {
-        let res =
-            parse_document_indexes(include_str!("../assets/gallery.html"), "gallery_id").unwrap();
+        let res = parse_document_indexes(
+            include_str!("../assets/gallery.html"),
+            "gallery_id",
+            Utc::now(),
+        )
+        .unwrap();
         let res: Vec<_> = res.into_iter().map(|d| d.unwrap()).collect();
         assert!(!res.is_empty());
         assert!(res.len() >= 20);
@@ -535,6 +1136,7 @@ mod tests {
         let res = parse_document_indexes(
             include_str!("../assets/gallery-problemtic.html"),
             "gallery_id",
+            Utc::now(),
         )
         .unwrap();
         let res: Vec<_> = res.into_iter().map(|d| d.unwrap()).collect();
@@ -544,11 +1146,51 @@ mod tests {
         assert!(res.iter().any(|d| DocumentKind::Picture == d.kind));
     }
 
+    #[test]
+    fn it_dates_rows_without_title() {
+        let body = include_str!("../assets/minor_gallery.html")
+            .replace("gall_date\" title", "gall_date\" data-title");
+        let res = parse_document_indexes(&body, "gallery_id", crawled_at()).unwrap();
+        let dates: Vec<_> = res.into_iter().map(|d| d.unwrap().created_at).collect();
+        // `18:56` of the day of the crawl
+        assert!(dates.contains(
+            &DateTime::parse_from_rfc3339("2021-01-20T18:56:00+09:00")
+                .unwrap()
+                .with_timezone(&Utc)
+        ));
+    }
+    #[test]
+    fn it_keeps_parse_errors_short() {
+        let body = include_str!("../assets/gallery.html")
+            .replace("class=\"gall_count\"", "class=\"gall_views\"");
+        let res = parse_document_indexes(&body, "programming", Utc::now()).unwrap();
+        match res[0].as_ref().unwrap_err() {
+            DocumentParseError::Select { path, context } => {
+                assert_eq!(*path, ".us-post .gall_count");
+                assert_eq!(context.row, Some(0));
+                assert!(context.document_id.is_some());
+                assert!(context
+                    .snippet
+                    .starts_with("<tr class=\"ub-content us-post"));
+                assert!(context.snippet.chars().count() <= SNIPPET_LEN + 3);
+            }
+            err => panic!("unexpected {}", err),
+        }
+        assert!(res[1].as_ref().unwrap_err().to_string().len() < 1024);
+        assert_eq!(
+            snippet(&"가".repeat(SNIPPET_LEN + 1)),
+            format!("{}...", "가".repeat(SNIPPET_LEN))
+        );
+    }
+
     #[test]
     fn it_parses_minor_document_indexes() {
-        let res =
-            parse_document_indexes(include_str!("../assets/minor_gallery.html"), "gallery_id")
-                .unwrap();
+        let res = parse_document_indexes(
+            include_str!("../assets/minor_gallery.html"),
+            "gallery_id",
+            Utc::now(),
+        )
+        .unwrap();
         let res: Vec<_> = res.into_iter().map(|d| d.unwrap()).collect();
         assert!(!res.is_empty());
         assert!(res.len() >= 20);
@@ -561,7 +1203,7 @@ mod tests {
             include_str!("../assets/comments.json"),
             "gallery_id",
             1,
-            None,
+            crawled_at(),
         )
         .unwrap();
         assert!(!res.is_empty());
@@ -581,11 +1223,11 @@ mod tests {
             include_str!("../assets/comments.json"),
             "gallery_id",
             1,
-            None,
+            crawled_at(),
         )
         .unwrap();
         let res = serde_json::to_string(&res[0]).unwrap();
-        let expected = "{\"id\":13369033,\"author\":{\"id\":null,\"ip\":\"119.195\",\"nickname\":\"ㅇㅇ\",\"kind\":\"Dynamic\"},\"depth\":0,\"contents\":\"이제 뻑가,이슈왕 같은 랙카들이 청원하라고 한번 더 할듯ㅋㅋ  - dc App\",\"kind\":\"Text\",\"parent_id\":null,\"created_at\":\"2021-01-10T08:20:43Z\"}".to_string();
+        let expected = "{\"id\":13369033,\"author\":{\"id\":null,\"ip\":\"119.195\",\"nickname\":\"ㅇㅇ\",\"kind\":\"Dynamic\"},\"depth\":0,\"contents\":\"이제 뻑가,이슈왕 같은 랙카들이 청원하라고 한번 더 할듯ㅋㅋ  - dc App\",\"kind\":\"Text\",\"content\":{\"Text\":{\"text\":\"이제 뻑가,이슈왕 같은 랙카들이 청원하라고 한번 더 할듯ㅋㅋ - dc App\",\"mentions\":[]}},\"parent_id\":null,\"created_at\":\"2021-01-10T08:20:43Z\",\"deleted\":false,\"mentioned_ids\":[]}".to_string();
         assert_eq!(expected, res);
         //assert_eq!(!res[0], Comment{});
     }
@@ -595,40 +1237,343 @@ mod tests {
             include_str!("../assets/minor_comments.json"),
             "gallery_id",
             1,
-            None,
+            crawled_at(),
         )
         .unwrap();
         let res = serde_json::to_string(&res[0]).unwrap();
-        let expected = "{\"id\":4649463,\"author\":{\"id\":\"nasdaqtrader\",\"ip\":null,\"nickname\":\"오함마의현인.\",\"kind\":\"Static\"},\"depth\":0,\"contents\":\"개추\",\"kind\":\"Text\",\"parent_id\":null,\"created_at\":\"2020-12-31T07:44:47Z\"}".to_string();
+        let expected = "{\"id\":4649463,\"author\":{\"id\":\"nasdaqtrader\",\"ip\":null,\"nickname\":\"오함마의현인.\",\"kind\":\"Static\"},\"depth\":0,\"contents\":\"개추\",\"kind\":\"Text\",\"content\":{\"Text\":{\"text\":\"개추\",\"mentions\":[]}},\"parent_id\":null,\"created_at\":\"2020-12-31T07:44:47Z\",\"deleted\":false,\"mentioned_ids\":[]}".to_string();
         assert_eq!(expected, res);
         //assert_eq!(!res[0], Comment{});
     }
 
+    #[test]
+    fn it_parses_deleted_comments() {
+        let (res, _max_page) = parse_comments(
+            include_str!("../assets/minor_comments.json"),
+            "gallery_id",
+            1,
+            crawled_at(),
+        )
+        .unwrap();
+        let deleted: Vec<_> = res.iter().filter(|c| c.deleted).map(|c| c.id).collect();
+        assert_eq!(deleted, vec![4670004, 4670423, 4670742]);
+        let body = r#"{"comments":[{"no":"1","name":"ㅇㅇ","depth":0,"memo":"삭제된 댓글입니다."}],"pagination":"<em>1</em>"}"#;
+        let (res, _max_page) = parse_comments(body, "gallery_id", 1, crawled_at()).unwrap();
+        assert!(res[0].deleted);
+    }
+
+    #[test]
+    fn it_threads_comments() {
+        let (res, _max_page) = parse_comments(
+            include_str!("../assets/minor_comments.json"),
+            "gallery_id",
+            1,
+            crawled_at(),
+        )
+        .unwrap();
+        // a deleted root keeps its replies
+        let reply = res.iter().find(|c| c.id == 4670742).unwrap();
+        assert_eq!(reply.parent_id, Some(4670423));
+        assert!(res
+            .iter()
+            .filter(|c| c.depth == 0)
+            .all(|c| c.parent_id.is_none()));
+
+        // the root of a reply on a later page is on an earlier one
+        let body = r#"{"comments":[{"no":"12","c_no":"10","name":"ㅇㅇ","depth":1,"memo":"@고닉 ㄹㅇ"}],"pagination":"<a>1</a><em>2</em>"}"#;
+        let (page, _max_page) = parse_comments(body, "gallery_id", 1, crawled_at()).unwrap();
+        let body = r#"{"comments":[{"no":"10","c_no":0,"name":"고닉","depth":0,"memo":"ㅋㅋ"},{"no":"11","c_no":"10","name":"고닉","depth":1,"memo":"ㅎㅎ"}],"pagination":"<em>1</em><a>2</a>"}"#;
+        let (mut comments, _max_page) =
+            parse_comments(body, "gallery_id", 1, crawled_at()).unwrap();
+        comments.extend(page);
+        assert_eq!(comments[2].parent_id, Some(10));
+
+        attach_mentions(&mut comments);
+        assert_eq!(comments[2].mentioned_ids, vec![11]);
+        assert!(comments[0].mentioned_ids.is_empty());
+
+        let threads = CommentThread::from_comments(comments);
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].root.as_ref().map(|c| c.id), Some(10));
+        let replies: Vec<_> = threads[0].replies.iter().map(|c| c.id).collect();
+        assert_eq!(replies, vec![11, 12]);
+
+        let threads =
+            CommentThread::from_comments(res.into_iter().filter(|c| c.id != 4670423).collect());
+        let orphaned = threads.iter().find(|t| t.root_id == 4670423).unwrap();
+        assert!(orphaned.root.is_none());
+        assert_eq!(orphaned.replies.len(), 1);
+    }
+
+    #[test]
+    fn it_parses_comment_contents() {
+        let (res, _max_page) = parse_comments(
+            include_str!("../assets/comments.json"),
+            "gallery_id",
+            1,
+            crawled_at(),
+        )
+        .unwrap();
+        let con = res.iter().find(|c| c.id == 13370175).unwrap();
+        assert_eq!(con.kind, CommentKind::Con);
+        match &con.content {
+            CommentContent::Con {
+                image_id,
+                url,
+                title,
+                ..
+            } => {
+                assert!(url.starts_with("https://dcimg5.dcinside.com/dccon.php?no="));
+                assert!(url.ends_with(image_id.as_str()));
+                assert_eq!(title.as_deref(), Some("e3"));
+            }
+            c => panic!("{:?}", c),
+        }
+        let video_con = res.iter().find(|c| c.id == 13369592).unwrap();
+        assert_eq!(video_con.kind, CommentKind::Con);
+        assert!(matches!(&video_con.content,
+            CommentContent::Con { title: Some(title), .. } if title == "잡아라"));
+
+        let voice = comment_content_from_contents("vr/abc123", None);
+        assert_eq!(voice.kind(), CommentKind::Voice);
+        assert!(matches!(voice, CommentContent::Voice { id, .. } if id == "abc123"));
+
+        let text = comment_content_from_contents(
+            "@고닉 <a href=\"https://gall.dcinside.com/tenbagger/1\">링크</a> &amp; ㅋㅋ",
+            None,
+        );
+        assert_eq!(
+            text,
+            CommentContent::Text {
+                text: "@고닉 링크 & ㅋㅋ".to_string(),
+                mentions: vec!["고닉".to_string()],
+            }
+        );
+    }
+
     #[test]
     fn it_parses_document_body() {
         let res =
             parse_document_body(include_str!("../assets/body.html"), "gallery_id", 1).unwrap();
-        assert_eq!(res, "\n\t\t\t\t\t\t\t<p>\'올림픽\' 개최 위해 백신팀 극비리 가동<br><br>전국민 맞고 남을 만큼 확보했지만<br><br>\'국내 1~3차 임상 필수\' 규제에 발목<br><br>\"국산 백신은 왜 없나\" 비판도 일어<br><br>백신을 빨리 확보했음에도, 정작 접종시기는 2월말 - 한국과 차이 X<br><br>일본은 국내 임상 1,2,3차를 거쳐야 하는데, 모더나는 1월경 임상시험에 들어간다고 발표, 그러나 언제 접종할 지에 대해선 타임라인 제시 X</p><p><br></p><p><br></p><p style=\"text-align:left;\"><img src=\"https://dcimg8.dcinside.co.kr/viewimage.php?id=3dafdf21f7d335ab67b1d1&amp;no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364a956febc71a78fb28a8551648461846084596f34596bb9e7a7968b67c\" style=\"cursor:pointer;\" onclick=\"javascript:imgPop(\'https://image.dcinside.com/viewimagePop.php?id=3dafdf21f7d335ab67b1d1&amp;no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364ad33fbacc76da4d6aa29c4891b49fe3513541273923ef18\',\'image\',\'fullscreen=yes,scrollbars=yes,resizable=no,menubar=no,toolbar=no,location=no,status=no\');\" alt=\"viewimage.php?id=3dafdf21f7d335ab67b1d1&amp;no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364a956febc71a78fb28a8551648461846084596f34596bb9e7a7968b67c\"></p><p style=\"text-align:left;\">18시 현재 6,055명 (도쿄 1,494명 )<br><br>일요일 기준 최고치 갱신 중<br></p><p><br></p><p style=\"text-align:left;\"><img src=\"https://dcimg8.dcinside.co.kr/viewimage.php?id=3dafdf21f7d335ab67b1d1&amp;no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364a956febc71a78fb28a85516484618460810c0a219c177961ee0f408ae\" style=\"cursor:pointer;\" onclick=\"javascript:imgPop(\'https://image.dcinside.com/viewimagePop.php?id=3dafdf21f7d335ab67b1d1&amp;no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364ad33fbacc76da4d6aa29c48c4e2cebf0663432839231131\',\'image\',\'fullscreen=yes,scrollbars=yes,resizable=no,menubar=no,toolbar=no,location=no,status=no\');\" alt=\"viewimage.php?id=3dafdf21f7d335ab67b1d1&amp;no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364a956febc71a78fb28a85516484618460810c0a219c177961ee0f408ae\"></p><p><br></p>\t\t\t\t\t\t\t\t".to_string());
+        assert!(res.text.starts_with(
+            "'올림픽' 개최 위해 백신팀 극비리 가동\n\n전국민 맞고 남을 만큼 확보했지만\n"
+        ));
+        assert!(res
+            .text
+            .ends_with("18시 현재 6,055명 (도쿄 1,494명 )\n\n일요일 기준 최고치 갱신 중"));
+        assert_eq!(res.images.len(), 2);
+        assert!(res.images[0].thumbnail_url.starts_with(
+            "https://dcimg8.dcinside.co.kr/viewimage.php?id=3dafdf21f7d335ab67b1d1&no="
+        ));
+        assert!(res.images[0].original_url.as_ref().unwrap().starts_with(
+            "https://image.dcinside.com/viewimagePop.php?id=3dafdf21f7d335ab67b1d1&no="
+        ));
+        assert!(res.dccons.is_empty() && res.videos.is_empty() && res.links.is_empty());
+        assert_eq!(res.attachments.len(), 2);
+        assert_eq!(res.attachments[0].name, "176caccb15f504e70.jpg");
+        assert!(res.attachments[0]
+            .download_url
+            .starts_with("https://image.dcinside.com/download.php?no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364ad33fbacc76da4d6aa29c4891b49fe3513541273923ef18&f_no="));
+        assert_eq!(res.attachments[0].image_url, res.images[0].original_url);
+        assert_eq!(res.attachments[1].image_url, res.images[1].original_url);
+        assert_eq!(res.html.unwrap(), "\n\t\t\t\t\t\t\t<p>\'올림픽\' 개최 위해 백신팀 극비리 가동<br><br>전국민 맞고 남을 만큼 확보했지만<br><br>\'국내 1~3차 임상 필수\' 규제에 발목<br><br>\"국산 백신은 왜 없나\" 비판도 일어<br><br>백신을 빨리 확보했음에도, 정작 접종시기는 2월말 - 한국과 차이 X<br><br>일본은 국내 임상 1,2,3차를 거쳐야 하는데, 모더나는 1월경 임상시험에 들어간다고 발표, 그러나 언제 접종할 지에 대해선 타임라인 제시 X</p><p><br></p><p><br></p><p style=\"text-align:left;\"><img src=\"https://dcimg8.dcinside.co.kr/viewimage.php?id=3dafdf21f7d335ab67b1d1&amp;no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364a956febc71a78fb28a8551648461846084596f34596bb9e7a7968b67c\" style=\"cursor:pointer;\" onclick=\"javascript:imgPop(\'https://image.dcinside.com/viewimagePop.php?id=3dafdf21f7d335ab67b1d1&amp;no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364ad33fbacc76da4d6aa29c4891b49fe3513541273923ef18\',\'image\',\'fullscreen=yes,scrollbars=yes,resizable=no,menubar=no,toolbar=no,location=no,status=no\');\" alt=\"viewimage.php?id=3dafdf21f7d335ab67b1d1&amp;no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364a956febc71a78fb28a8551648461846084596f34596bb9e7a7968b67c\"></p><p style=\"text-align:left;\">18시 현재 6,055명 (도쿄 1,494명 )<br><br>일요일 기준 최고치 갱신 중<br></p><p><br></p><p style=\"text-align:left;\"><img src=\"https://dcimg8.dcinside.co.kr/viewimage.php?id=3dafdf21f7d335ab67b1d1&amp;no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364a956febc71a78fb28a85516484618460810c0a219c177961ee0f408ae\" style=\"cursor:pointer;\" onclick=\"javascript:imgPop(\'https://image.dcinside.com/viewimagePop.php?id=3dafdf21f7d335ab67b1d1&amp;no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364ad33fbacc76da4d6aa29c48c4e2cebf0663432839231131\',\'image\',\'fullscreen=yes,scrollbars=yes,resizable=no,menubar=no,toolbar=no,location=no,status=no\');\" alt=\"viewimage.php?id=3dafdf21f7d335ab67b1d1&amp;no=24b0d769e1d32ca73dec87fa11d0283123a3619b5f9530e0a1306168e0dcca0e8d266e8bd0d7e56ed9364a956febc71a78fb28a85516484618460810c0a219c177961ee0f408ae\"></p><p><br></p>\t\t\t\t\t\t\t\t".to_string());
+    }
+
+    #[test]
+    fn it_parses_document_body_embeds() {
+        let html = r#"<div class="write_div"><p>위<img class="written_dccon" src="https://dcimg5.dcinside.com/dccon.php?no=62b5" title="웃음"></p><blockquote><p>인용한   글</p></blockquote><div><iframe src="https://www.youtube.com/embed/dQw4w9WgXcQ?rel=0"></iframe></div><video><source src="https://dcm6.dcinside.com/movie.mp4"></video><p><a href="https://example.com/a">링크</a> <a href="https://example.com/a">또</a> <a href="javascript:;">x</a></p><script>var a = 1;</script></div>"#;
+        let res = parse_document_body(html, "gallery_id", 1).unwrap();
+        assert_eq!(res.text, "위\n\n인용한 글\n\n링크 또 x");
+        assert_eq!(
+            res.dccons,
+            vec![Dccon {
+                url: "https://dcimg5.dcinside.com/dccon.php?no=62b5".to_string(),
+                title: Some("웃음".to_string()),
+            }]
+        );
+        assert_eq!(res.quotes, vec!["인용한 글".to_string()]);
+        assert_eq!(res.videos.len(), 2);
+        assert_eq!(res.videos[0].youtube_id.as_deref(), Some("dQw4w9WgXcQ"));
+        assert_eq!(res.videos[1].url, "https://dcm6.dcinside.com/movie.mp4");
+        assert_eq!(res.videos[1].youtube_id, None);
+        assert_eq!(res.links, vec!["https://example.com/a".to_string()]);
+        assert!(res.images.is_empty());
+        assert_eq!(
+            youtube_id("https://youtu.be/abc?t=1").as_deref(),
+            Some("abc")
+        );
+        assert_eq!(
+            youtube_id("https://www.youtube.com/watch?v=abc&t=1").as_deref(),
+            Some("abc")
+        );
     }
 
     #[test]
     fn it_parses_adults() {
         let html = r#"<script type="text/javascript">location.replace("/error/adult/?s_url=https%3A%2F%2Fgall.dcinside.com%2Fmgallery%2Fboard%2Flists%3Fid%3Donahole%26list_num%3D100%26page%3D2");</script>"#;
-        let res = parse_document_indexes(html, "gallery_id");
+        let res = parse_document_indexes(html, "gallery_id", Utc::now());
         assert_err!(res, Err(DocumentParseError::AdultPage));
     }
 
     #[test]
     fn it_parses_prohibited() {
-        let res = parse_document_indexes(include_str!("../assets/prohibited.html"), "gallery_id");
+        let res = parse_document_indexes(
+            include_str!("../assets/prohibited.html"),
+            "gallery_id",
+            Utc::now(),
+        );
         assert_err!(res, Err(DocumentParseError::MinorGalleryAccessNotAllowed));
     }
 
+    #[test]
+    fn it_parses_document_counts() {
+        let res = parse_document_counts(include_str!("../assets/body.html")).unwrap();
+        assert_eq!(
+            res,
+            DocumentCounts {
+                comment_count: 28,
+                like_count: 28,
+                view_count: 1974,
+            }
+        );
+    }
+
+    #[test]
+    fn it_parses_gallery_info() {
+        let info = parse_gallery_info(include_str!("../assets/minor_gallery.html"));
+        assert_eq!(info.manager_id.as_deref(), Some("kimtpwjd"));
+        assert_eq!(
+            info.sub_manager_ids,
+            vec![
+                "cursefetish",
+                "killingme92",
+                "bitcoingo",
+                "nasdaqtrader",
+                "sowhat254"
+            ]
+        );
+        assert_eq!(
+            info.created_at,
+            Some(Utc.ymd(2017, 12, 17).and_hms(15, 0, 0))
+        );
+        assert_eq!(
+            info.subjects,
+            vec!["일반", "뉴스", "정보", "종목분석", "SPAC"]
+        );
+        assert_eq!(info.member_count, None);
+        let info = parse_gallery_info(include_str!("../assets/gallery.html"));
+        assert_eq!(info.manager_id, None);
+        assert!(info.sub_manager_ids.is_empty());
+    }
+
+    #[test]
+    fn it_parses_search_paging() {
+        let body = include_str!("../assets/search.html");
+        assert_eq!(
+            parse_search_paging(body, 1),
+            SearchPaging {
+                has_next_page: false,
+                next_search_pos: Some(-9950000),
+            }
+        );
+        let paging = parse_search_paging(include_str!("../assets/gallery.html"), 15);
+        assert!(paging.has_next_page);
+        assert_eq!(paging.next_search_pos, None);
+    }
+
+    #[test]
+    fn it_parses_catalog() {
+        let categories =
+            parse_catalog_categories(include_str!("../assets/catalog_categories.html")).unwrap();
+        assert_eq!(categories.len(), 4);
+        assert_eq!(
+            categories[3],
+            CatalogCategory {
+                id: "4".to_string(),
+                name: "교육/금융/IT".to_string(),
+            }
+        );
+        let entries = parse_catalog_entries(
+            include_str!("../assets/catalog.html"),
+            GalleryKind::Minor,
+            Some("교육/금융/IT"),
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].index.id, "github");
+        assert_eq!(entries[0].index.name, "깃허브");
+        assert_eq!(entries[0].index.kind, GalleryKind::Minor);
+        assert_eq!(entries[0].info.category.as_deref(), Some("교육/금융/IT"));
+        assert_eq!(
+            entries[0].info.description.as_deref(),
+            Some("깃허브 마이너 갤러리입니다")
+        );
+        assert_eq!(
+            entries[0].info.created_at,
+            Some(Utc.ymd(2019, 3, 1).and_hms(15, 0, 0))
+        );
+        assert_eq!(entries[0].info.manager_id.as_deref(), Some("octocat"));
+        assert_eq!(entries[0].info.manager_nickname.as_deref(), Some("옥토캣"));
+        assert_eq!(entries[1].index.id, "rust");
+        assert_eq!(entries[1].info.description, None);
+        assert_eq!(entries[1].info.manager_nickname, None);
+        assert!(parse_catalog_entries(
+            "<table class=\"gall_catalog\"></table>",
+            GalleryKind::Minor,
+            None
+        )
+        .unwrap()
+        .is_empty());
+    }
+
+    #[test]
+    fn it_parses_deleted_documents() {
+        let res = parse_document_counts(include_str!("../assets/deleted.html"));
+        assert_err!(res, Err(DocumentParseError::Deleted));
+        assert!(!is_deleted_document_page(include_str!(
+            "../assets/body.html"
+        )));
+    }
+
+    #[test]
+    fn it_detects_block_pages() {
+        let res = parse_document_indexes(
+            include_str!("../assets/blocked_captcha.html"),
+            "gallery_id",
+            Utc::now(),
+        );
+        assert_err!(res, Err(DocumentParseError::Blocked(BlockKind::Captcha)));
+        let res = parse_document_body(include_str!("../assets/maintenance.html"), "gallery_id", 1);
+        assert_err!(
+            res,
+            Err(DocumentBodyParseError::DocumentParseError(
+                DocumentParseError::Blocked(BlockKind::Maintenance)
+            ))
+        );
+        let res = parse_comments(
+            "<html><body><h1>429 Too Many Requests</h1></body></html>",
+            "gallery_id",
+            1,
+            crawled_at(),
+        );
+        assert_err!(res, Err(CommentParseError::Blocked(BlockKind::RateLimit)));
+        assert_eq!(
+            detect_block_page("<p>접근이 차단된 아이피입니다.</p>"),
+            Some(BlockKind::IpBan)
+        );
+        assert_eq!(detect_block_page(include_str!("../assets/body.html")), None);
+        assert_eq!(
+            detect_block_page(include_str!("../assets/comments.json")),
+            None
+        );
+    }
+
     #[test]
     fn it_parses_promoted() {
         let res = parse_document_indexes(
             r#"<script type="text/javascript">location.replace("https://gall.dcinside.com/board/lists?id=wln");</script>"#,
             "gallery_id",
+            Utc::now(),
         );
         assert_err!(res, Err(DocumentParseError::MinorGalleryPromoted));
     }
@@ -638,6 +1583,7 @@ mod tests {
         let res = parse_document_indexes(
             r#"<script type="text/javascript">alert("해당 마이너 갤러리는 매니저의 요청으로 폐쇄되었습니다.\n마이너 갤러리 메인으로 돌아갑니다.");</script><script type="text/javascript">location.replace("https://gall.dcinside.com/m");</script>"#,
             "gallery_id",
+            Utc::now(),
         );
         assert_err!(res, Err(DocumentParseError::MinorGalleryClosed));
     }
@@ -647,7 +1593,28 @@ mod tests {
         let res = parse_document_indexes(
             r#"<script type="text/javascript">alert("해당 마이너 갤러리는 운영원칙 위반(사유: )으로 폐쇄되었습니다.\n마이너 갤러리 메인으로 돌아갑니다.");</script><script type="text/javascript">location.replace("https://gall.dcinside.com/m");</script>"#,
             "gallery_id",
+            Utc::now(),
         );
         assert_err!(res, Err(DocumentParseError::MinorGalleryClosed));
     }
+
+    #[test]
+    fn it_pareses_mini_closed() {
+        let res = parse_document_indexes(
+            r#"<script type="text/javascript">alert("해당 미니 갤러리는 운영원칙 위반(사유: )으로 폐쇄되었습니다.\n미니 갤러리 메인으로 돌아갑니다.");</script><script type="text/javascript">location.replace("https://gall.dcinside.com/n");</script>"#,
+            "gallery_id",
+            Utc::now(),
+        );
+        assert_err!(res, Err(DocumentParseError::MiniGalleryClosed));
+    }
+
+    #[test]
+    fn it_pareses_mini_access_not_allowed() {
+        let res = parse_document_indexes(
+            r#"<script type="text/javascript">alert("해당 미니 갤러리는 접근이 제한되었습니다.");</script><script type="text/javascript">location.replace("https://gall.dcinside.com/n");</script>"#,
+            "gallery_id",
+            Utc::now(),
+        );
+        assert_err!(res, Err(DocumentParseError::MiniGalleryAccessNotAllowed));
+    }
 }
@@ -0,0 +1,233 @@
+use crate::error::*;
+
+use log::warn;
+use prometheus::{opts, IntCounterVec};
+
+use std::future::Future;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backoff {
+    /// `base_delay * attempt`
+    Linear,
+    /// `base_delay * 2^(attempt - 1)`
+    Exponential,
+    /// A random delay between zero and the exponential one.
+    Jittered,
+}
+
+pub fn backoff_from_str<T: AsRef<str>>(s: T) -> Backoff {
+    match s.as_ref() {
+        "linear" => Backoff::Linear,
+        "exponential" => Backoff::Exponential,
+        "jittered" => Backoff::Jittered,
+        _ => panic!("unsupported backoff"),
+    }
+}
+
+/// How the crawler retries a failed request. Only errors for which
+/// `CrawlerError::is_retryable` holds are retried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub backoff: Backoff,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Includes the first try.
+    pub max_attempts: usize,
+    /// Wait at least as long as the `Retry-After` header asks, up to `max_delay`.
+    pub respect_retry_after: bool,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            backoff: Backoff::Linear,
+            base_delay: Duration::from_millis(1000),
+            max_delay: Duration::from_millis(1000 * 10),
+            max_attempts: 11,
+            respect_retry_after: true,
+        }
+    }
+}
+impl RetryPolicy {
+    /// Delay before the `attempt`th retry, counting from 1.
+    pub fn delay(&self, attempt: usize, err: &CrawlerError) -> Duration {
+        let exponential = || {
+            let factor = 2u32.saturating_pow(attempt.saturating_sub(1) as u32);
+            self.base_delay
+                .checked_mul(factor)
+                .unwrap_or(self.max_delay)
+        };
+        let delay = match self.backoff {
+            Backoff::Linear => self
+                .base_delay
+                .checked_mul(attempt as u32)
+                .unwrap_or(self.max_delay),
+            Backoff::Exponential => exponential(),
+            Backoff::Jittered => exponential().mul_f64(rand::random::<f64>()),
+        };
+        let delay = match err.retry_after() {
+            Some(retry_after) if self.respect_retry_after => delay.max(retry_after),
+            _ => delay,
+        };
+        delay.min(self.max_delay)
+    }
+    pub async fn run<T, F, Fut>(
+        &self,
+        operation: &'static str,
+        metrics: Option<&RetryMetrics>,
+        mut f: F,
+    ) -> Result<T, CrawlerError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, CrawlerError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let err = match f().await {
+                Ok(res) => return Ok(res),
+                Err(err) => err,
+            };
+            let outcome = if !err.is_retryable() {
+                "fatal"
+            } else if attempt >= self.max_attempts {
+                "exhausted"
+            } else {
+                "retry"
+            };
+            if let Some(metrics) = metrics {
+                metrics
+                    .retry_total
+                    .with_label_values(&[operation, outcome])
+                    .inc();
+            }
+            if outcome != "retry" {
+                return Err(err);
+            }
+            let delay = self.delay(attempt, &err);
+            warn!(
+                "{} failed at attempt {}/{}, retry in {}ms: {}",
+                operation,
+                attempt,
+                self.max_attempts,
+                delay.as_millis(),
+                err
+            );
+            actix::clock::delay_for(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Failed requests by operation and outcome(`retry`, `exhausted` or `fatal`).
+#[derive(Clone)]
+pub struct RetryMetrics {
+    pub retry_total: IntCounterVec,
+}
+impl Default for RetryMetrics {
+    fn default() -> Self {
+        RetryMetrics {
+            retry_total: IntCounterVec::new(
+                opts!("dccrawler_retry_total", "dccrawler_retry_total"),
+                &["operation", "outcome"],
+            )
+            .unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use std::cell::Cell;
+
+    fn status(status: StatusCode, retry_after: Option<u64>) -> CrawlerError {
+        CrawlerError::Status {
+            status,
+            retry_after: retry_after.map(Duration::from_secs),
+        }
+    }
+
+    #[test]
+    fn it_computes_delays() {
+        let err = status(StatusCode::SERVICE_UNAVAILABLE, None);
+        let linear = RetryPolicy::default();
+        assert_eq!(linear.delay(1, &err), Duration::from_secs(1));
+        assert_eq!(linear.delay(3, &err), Duration::from_secs(3));
+        assert_eq!(linear.delay(30, &err), Duration::from_secs(10));
+        let exponential = RetryPolicy {
+            backoff: Backoff::Exponential,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(exponential.delay(1, &err), Duration::from_secs(1));
+        assert_eq!(exponential.delay(3, &err), Duration::from_secs(4));
+        assert_eq!(exponential.delay(64, &err), Duration::from_secs(10));
+        let jittered = RetryPolicy {
+            backoff: Backoff::Jittered,
+            ..RetryPolicy::default()
+        };
+        assert!(jittered.delay(3, &err) <= Duration::from_secs(4));
+    }
+
+    #[test]
+    fn it_respects_retry_after() {
+        let policy = RetryPolicy::default();
+        let err = status(StatusCode::TOO_MANY_REQUESTS, Some(5));
+        assert_eq!(policy.delay(1, &err), Duration::from_secs(5));
+        let err = status(StatusCode::TOO_MANY_REQUESTS, Some(60));
+        assert_eq!(policy.delay(1, &err), Duration::from_secs(10));
+        let ignore = RetryPolicy {
+            respect_retry_after: false,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(ignore.delay(1, &err), Duration::from_secs(1));
+    }
+
+    #[actix_rt::test]
+    async fn it_stops_at_fatal_errors() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(0),
+            ..RetryPolicy::default()
+        };
+        let metrics = RetryMetrics::default();
+        let calls = Cell::new(0);
+        let res: Result<(), _> = policy
+            .run("test", Some(&metrics), || async {
+                calls.set(calls.get() + 1);
+                if calls.get() < 3 {
+                    Err(status(StatusCode::BAD_GATEWAY, None))
+                } else {
+                    Err(CrawlerError::PageNotFound)
+                }
+            })
+            .await;
+        assert!(matches!(res, Err(CrawlerError::PageNotFound)));
+        assert_eq!(calls.get(), 3);
+        let retry_total = |outcome| {
+            metrics
+                .retry_total
+                .with_label_values(&["test", outcome])
+                .get()
+        };
+        assert_eq!(retry_total("retry"), 2);
+        assert_eq!(retry_total("fatal"), 1);
+    }
+
+    #[actix_rt::test]
+    async fn it_gives_up_after_max_attempts() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(0),
+            max_attempts: 4,
+            ..RetryPolicy::default()
+        };
+        let calls = Cell::new(0);
+        let res: Result<(), _> = policy
+            .run("test", None, || async {
+                calls.set(calls.get() + 1);
+                Err(status(StatusCode::SERVICE_UNAVAILABLE, None))
+            })
+            .await;
+        assert!(res.is_err());
+        assert_eq!(calls.get(), 4);
+    }
+}
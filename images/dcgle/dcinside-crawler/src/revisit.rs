@@ -0,0 +1,341 @@
+use crate::parse::{DocumentCounts, GalleryIndex};
+use dcinside_model::{Comment, Document, DocumentUpdate};
+
+use chrono::{DateTime, Duration, Utc};
+
+/// What was known about a document at its last visit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentSnapshot {
+    pub id: usize,
+    pub comment_count: u32,
+    pub like_count: u32,
+    pub view_count: u32,
+    pub last_comment_id: Option<usize>,
+    pub deleted_comment_ids: Vec<usize>,
+    pub created_at: DateTime<Utc>,
+}
+impl From<&Document> for DocumentSnapshot {
+    fn from(doc: &Document) -> Self {
+        DocumentSnapshot {
+            id: doc.id,
+            comment_count: doc.comment_count,
+            like_count: doc.like_count,
+            view_count: doc.view_count,
+            last_comment_id: doc
+                .comments
+                .as_ref()
+                .and_then(|comms| comms.iter().map(|c| c.id).max()),
+            deleted_comment_ids: doc
+                .comments
+                .iter()
+                .flatten()
+                .filter(|c| c.deleted)
+                .map(|c| c.id)
+                .collect(),
+            created_at: doc.created_at,
+        }
+    }
+}
+impl DocumentSnapshot {
+    /// The update from this snapshot to what was seen now. `comments` may be all
+    /// comments of the document or none of them.
+    pub fn diff(
+        &self,
+        gallery_id: &str,
+        counts: DocumentCounts,
+        comments: Vec<Comment>,
+        now: DateTime<Utc>,
+    ) -> DocumentUpdate {
+        let deleted_comment_ids = comments
+            .iter()
+            .filter(|c| {
+                c.deleted
+                    && Some(c.id) <= self.last_comment_id
+                    && !self.deleted_comment_ids.contains(&c.id)
+            })
+            .map(|c| c.id)
+            .collect();
+        let new_comments = comments
+            .into_iter()
+            .filter(|c| Some(c.id) > self.last_comment_id)
+            .collect();
+        DocumentUpdate {
+            gallery_id: gallery_id.to_string(),
+            id: self.id,
+            comment_count: counts.comment_count,
+            like_count: counts.like_count,
+            view_count: counts.view_count,
+            comment_count_delta: counts.comment_count as i64 - self.comment_count as i64,
+            like_count_delta: counts.like_count as i64 - self.like_count as i64,
+            view_count_delta: counts.view_count as i64 - self.view_count as i64,
+            new_comments,
+            deleted_comment_ids,
+            revisited_at: now,
+        }
+    }
+    pub fn apply(&mut self, update: &DocumentUpdate) {
+        self.comment_count = update.comment_count;
+        self.like_count = update.like_count;
+        self.view_count = update.view_count;
+        self.last_comment_id = update
+            .new_comments
+            .iter()
+            .map(|c| c.id)
+            .max()
+            .max(self.last_comment_id);
+        self.deleted_comment_ids.extend(
+            update.deleted_comment_ids.iter().copied().chain(
+                update
+                    .new_comments
+                    .iter()
+                    .filter(|c| c.deleted)
+                    .map(|c| c.id),
+            ),
+        );
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Revisit {
+    pub gallery: GalleryIndex,
+    pub snapshot: DocumentSnapshot,
+    pub due: DateTime<Utc>,
+    step: usize,
+}
+
+/// Documents to look at again, each at fixed offsets after its `created_at`.
+#[derive(Debug, Clone)]
+pub struct RevisitQueue {
+    schedule: Vec<Duration>,
+    sample_rate: f64,
+    capacity: usize,
+    revisits: Vec<Revisit>,
+}
+impl Default for RevisitQueue {
+    fn default() -> Self {
+        RevisitQueue::new(vec![
+            Duration::hours(1),
+            Duration::hours(6),
+            Duration::hours(24),
+        ])
+    }
+}
+impl RevisitQueue {
+    pub fn new(mut schedule: Vec<Duration>) -> Self {
+        schedule.sort();
+        RevisitQueue {
+            schedule,
+            sample_rate: 1.0,
+            capacity: 10000,
+            revisits: Vec::new(),
+        }
+    }
+    /// Share of crawled documents to revisit.
+    pub fn sample_rate(mut self, v: f64) -> Self {
+        self.sample_rate = v;
+        self
+    }
+    /// New documents are not sampled while this many wait.
+    pub fn capacity(mut self, v: usize) -> Self {
+        self.capacity = v;
+        self
+    }
+    pub fn len(&self) -> usize {
+        self.revisits.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.revisits.is_empty()
+    }
+    fn schedule_from(
+        &self,
+        mut revisit: Revisit,
+        step: usize,
+        now: DateTime<Utc>,
+    ) -> Option<Revisit> {
+        let (step, offset) = self
+            .schedule
+            .iter()
+            .enumerate()
+            .skip(step)
+            .find(|(_, offset)| revisit.snapshot.created_at + **offset > now)?;
+        revisit.step = step;
+        revisit.due = revisit.snapshot.created_at + *offset;
+        Some(revisit)
+    }
+    /// Samples `doc` for revisits. Returns false if it was not taken.
+    pub fn push(&mut self, gallery: &GalleryIndex, doc: &Document, now: DateTime<Utc>) -> bool {
+        if self.revisits.len() >= self.capacity || rand::random::<f64>() >= self.sample_rate {
+            return false;
+        }
+        let revisit = Revisit {
+            gallery: gallery.clone(),
+            snapshot: doc.into(),
+            due: now,
+            step: 0,
+        };
+        match self.schedule_from(revisit, 0, now) {
+            Some(revisit) => {
+                self.revisits.push(revisit);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Takes out the revisits due at `now`, the most overdue first.
+    pub fn pop_due(&mut self, now: DateTime<Utc>) -> Vec<Revisit> {
+        let (mut due, rest) = self.revisits.drain(..).partition(|r| r.due <= now);
+        self.revisits = rest;
+        due.sort_by_key(|r: &Revisit| r.due);
+        due
+    }
+    /// Queues the next step of a revisit done at `now`, if any.
+    pub fn reschedule(
+        &mut self,
+        mut revisit: Revisit,
+        update: &DocumentUpdate,
+        now: DateTime<Utc>,
+    ) {
+        revisit.snapshot.apply(update);
+        let step = revisit.step + 1;
+        if let Some(revisit) = self.schedule_from(revisit, step, now) {
+            self.revisits.push(revisit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dcinside_model::*;
+
+    fn comment(id: usize, deleted: bool) -> Comment {
+        Comment {
+            id,
+            author: User {
+                id: None,
+                ip: Some("1.1".to_string()),
+                nickname: "ㅇㅇ".to_string(),
+                kind: UserKind::Dynamic,
+            },
+            depth: 0,
+            contents: "ㅋㅋ".to_string(),
+            kind: CommentKind::Text,
+            content: Default::default(),
+            mentioned_ids: Vec::new(),
+            parent_id: None,
+            created_at: None,
+            deleted,
+        }
+    }
+
+    fn document(created_at: DateTime<Utc>) -> Document {
+        Document {
+            gallery: Gallery {
+                id: "programming".to_string(),
+                name: "프로그래밍".to_string(),
+                kind: GalleryKind::Major,
+                info: None,
+            },
+            gallery_id: "programming".to_string(),
+            id: 1,
+            title: "title".to_string(),
+            subject: None,
+            author: User {
+                id: None,
+                ip: Some("1.1".to_string()),
+                nickname: "ㅇㅇ".to_string(),
+                kind: UserKind::Dynamic,
+            },
+            comment_count: 1,
+            like_count: 2,
+            view_count: 3,
+            kind: DocumentKind::Text,
+            is_recommend: false,
+            created_at,
+            comments: Some(vec![comment(10, false)]),
+            body: None,
+        }
+    }
+
+    #[test]
+    fn it_revisits_on_schedule() {
+        let created_at = Utc::now();
+        let gallery = GalleryIndex::default();
+        let mut queue = RevisitQueue::default();
+        assert!(queue.push(&gallery, &document(created_at), created_at));
+        assert!(queue.pop_due(created_at + Duration::minutes(59)).is_empty());
+
+        let now = created_at + Duration::minutes(61);
+        let mut due = queue.pop_due(now);
+        assert_eq!(due.len(), 1);
+        let revisit = due.remove(0);
+        let update = revisit.snapshot.diff(
+            "programming",
+            DocumentCounts {
+                comment_count: 1,
+                like_count: 5,
+                view_count: 30,
+            },
+            Vec::new(),
+            now,
+        );
+        assert_eq!(update.like_count_delta, 3);
+        assert_eq!(update.view_count_delta, 27);
+        assert_eq!(update.comment_count_delta, 0);
+        queue.reschedule(revisit, &update, now);
+        assert!(!queue.is_empty());
+
+        // a day later the 6h step is overdue and only the 24h one is left after it
+        let now = created_at + Duration::hours(23);
+        let revisit = queue.pop_due(now).remove(0);
+        assert_eq!(revisit.snapshot.like_count, 5);
+        assert_eq!(revisit.snapshot.last_comment_id, Some(10));
+        queue.reschedule(revisit, &update, created_at + Duration::hours(25));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn it_skips_old_documents() {
+        let now = Utc::now();
+        let mut queue = RevisitQueue::default();
+        let gallery = GalleryIndex::default();
+        assert!(!queue.push(&gallery, &document(now - Duration::days(2)), now));
+        assert!(!RevisitQueue::default()
+            .sample_rate(0.0)
+            .push(&gallery, &document(now), now));
+    }
+
+    #[test]
+    fn it_diffs_deleted_comments() {
+        let now = Utc::now();
+        let mut doc = document(now);
+        doc.comments = Some(vec![
+            comment(10, false),
+            comment(11, true),
+            comment(12, false),
+        ]);
+        let mut snapshot = DocumentSnapshot::from(&doc);
+        assert_eq!(snapshot.deleted_comment_ids, vec![11]);
+        let counts = DocumentCounts {
+            comment_count: 3,
+            like_count: 2,
+            view_count: 3,
+        };
+        let update = snapshot.diff(
+            "programming",
+            counts,
+            vec![
+                comment(10, true),
+                comment(11, true),
+                comment(12, false),
+                comment(13, true),
+            ],
+            now,
+        );
+        assert_eq!(update.deleted_comment_ids, vec![10]);
+        assert_eq!(update.new_comments, vec![comment(13, true)]);
+        snapshot.apply(&update);
+        assert_eq!(snapshot.last_comment_id, Some(13));
+        assert_eq!(snapshot.deleted_comment_ids, vec![11, 10, 13]);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::transport::{Request, Response};
+
+use std::time::{Duration, Instant};
+
+/// What dcinside ties to a visitor of one gallery: the `e_s_n_o` token that
+/// comment requests must echo, and the cookies set along with it.
+#[derive(Debug, Clone)]
+pub struct GallerySession {
+    gallery_id: String,
+    e_s_n_o: Option<String>,
+    issued_at: Option<Instant>,
+    ttl: Duration,
+    cookies: Vec<(String, String)>,
+}
+impl GallerySession {
+    pub fn new(gallery_id: &str) -> Self {
+        GallerySession {
+            gallery_id: gallery_id.to_string(),
+            e_s_n_o: None,
+            issued_at: None,
+            ttl: Duration::from_secs(30 * 60),
+            cookies: Vec::new(),
+        }
+    }
+    /// How long a token is used before it is fetched again.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+    pub fn gallery_id(&self) -> &str {
+        &self.gallery_id
+    }
+    /// The token, unless missing or expired at `now`.
+    pub fn token(&self, now: Instant) -> Option<&str> {
+        match (&self.e_s_n_o, self.issued_at) {
+            (Some(token), Some(issued_at)) if now.duration_since(issued_at) < self.ttl => {
+                Some(token.as_str())
+            }
+            _ => None,
+        }
+    }
+    pub fn set_token(&mut self, token: String, now: Instant) {
+        self.e_s_n_o = Some(token);
+        self.issued_at = Some(now);
+    }
+    /// Drops the token after the site refused it.
+    pub fn invalidate(&mut self) {
+        self.e_s_n_o = None;
+        self.issued_at = None;
+    }
+    /// Keeps the cookies `res` sets. Expired ones are removed.
+    pub fn store_cookies(&mut self, res: &Response) {
+        for (_, set_cookie) in res
+            .headers
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case("Set-Cookie"))
+        {
+            let mut attrs = set_cookie.split(';').map(str::trim);
+            let (name, value) = match attrs.next().and_then(|pair| {
+                let mut kv = pair.splitn(2, '=');
+                Some((kv.next()?.trim(), kv.next()?.trim()))
+            }) {
+                Some((name, value)) if !name.is_empty() => (name, value),
+                _ => continue,
+            };
+            let expired = value.is_empty()
+                || value == "deleted"
+                || attrs.any(|attr| attr.eq_ignore_ascii_case("Max-Age=0"));
+            self.cookies.retain(|(k, _)| k != name);
+            if !expired {
+                self.cookies.push((name.to_string(), value.to_string()));
+            }
+        }
+    }
+    pub fn cookie_header(&self) -> Option<String> {
+        if self.cookies.is_empty() {
+            return None;
+        }
+        Some(
+            self.cookies
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    }
+    /// Sends the stored cookies with `req`.
+    pub fn apply(&self, req: Request) -> Request {
+        match self.cookie_header() {
+            Some(cookie) => req.header("Cookie", cookie),
+            None => req,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_expires_tokens() {
+        let now = Instant::now();
+        let mut session = GallerySession::new("programming").ttl(Duration::from_secs(60));
+        assert_eq!(session.token(now), None);
+        session.set_token("abc".to_string(), now);
+        assert_eq!(session.token(now + Duration::from_secs(59)), Some("abc"));
+        assert_eq!(session.token(now + Duration::from_secs(60)), None);
+        session.set_token("def".to_string(), now);
+        session.invalidate();
+        assert_eq!(session.token(now), None);
+    }
+
+    #[test]
+    fn it_keeps_cookies() {
+        let mut session = GallerySession::new("programming");
+        session.store_cookies(
+            &Response::ok("")
+                .header("Set-Cookie", "PHPSESSID=1234; path=/; HttpOnly")
+                .header(
+                    "set-cookie",
+                    "ci_c=abcd; expires=Thu, 01-Jan-2099 00:00:00 GMT",
+                ),
+        );
+        assert_eq!(
+            session.cookie_header(),
+            Some("PHPSESSID=1234; ci_c=abcd".to_string())
+        );
+        session.store_cookies(
+            &Response::ok("")
+                .header("Set-Cookie", "ci_c=deleted; Max-Age=0")
+                .header("Set-Cookie", "PHPSESSID=5678; path=/"),
+        );
+        let req = session.apply(Request::get("https://gall.dcinside.com/board/comment"));
+        assert_eq!(
+            req.headers,
+            vec![("Cookie".to_string(), "PHPSESSID=5678".to_string())]
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use crate::error::*;
+use dcinside_model::Document;
+
+use futures::stream::{LocalBoxStream, Stream};
+
+use std::cell::{Cell, RefCell};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CrawlProgress {
+    /// Listing page the yielded documents come from.
+    pub page: usize,
+    /// Id of the document yielded last. Listings go newest first, so this only decreases.
+    pub last_document_id: Option<usize>,
+    pub newest_document_id: Option<usize>,
+    pub crawled_document_count: usize,
+    /// Set when a listing page failed. The failure is the last item of the stream.
+    pub aborted: bool,
+}
+
+/// Documents of a gallery, yielded as soon as their comments are fetched.
+/// Dropping the stream stops the crawl.
+pub struct DocumentStream<'a> {
+    inner: LocalBoxStream<'a, Result<Document, CrawlerError>>,
+    progress: Rc<Cell<CrawlProgress>>,
+    listed_ids: Rc<RefCell<Vec<usize>>>,
+}
+impl<'a> DocumentStream<'a> {
+    pub(crate) fn new(
+        inner: LocalBoxStream<'a, Result<Document, CrawlerError>>,
+        progress: Rc<Cell<CrawlProgress>>,
+        listed_ids: Rc<RefCell<Vec<usize>>>,
+    ) -> Self {
+        DocumentStream {
+            inner,
+            progress,
+            listed_ids,
+        }
+    }
+    pub fn progress(&self) -> CrawlProgress {
+        self.progress.get()
+    }
+    /// Ids of the listed rows queued since the last call, in listing order, whether
+    /// their documents were fetched or not.
+    pub fn take_listed_ids(&self) -> Vec<usize> {
+        std::mem::take(&mut *self.listed_ids.borrow_mut())
+    }
+}
+impl<'a> Stream for DocumentStream<'a> {
+    type Item = Result<Document, CrawlerError>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
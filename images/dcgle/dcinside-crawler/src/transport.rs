@@ -0,0 +1,302 @@
+use crate::error::*;
+
+use actix_web::{
+    client::{Client, ClientBuilder, PayloadError},
+    http::{Method, StatusCode},
+};
+use futures::future::{FutureExt, LocalBoxFuture};
+use serde::Serialize;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_USER_AGENT: &str =
+    "Mozilla/5.0 (X11; Linux x86_64; rv:84.0) Gecko/20100101 Firefox/84.0";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub form: Option<String>,
+    /// Largest body read, in bytes, in place of the transport's own limit.
+    pub body_limit: Option<usize>,
+}
+impl Request {
+    pub fn get<T: Into<String>>(url: T) -> Self {
+        Request {
+            method: Method::GET,
+            url: url.into(),
+            headers: Vec::new(),
+            form: None,
+            body_limit: None,
+        }
+    }
+    pub fn post_form<T: Into<String>, F: Serialize>(
+        url: T,
+        form: &F,
+    ) -> Result<Self, CrawlerError> {
+        Ok(Request {
+            method: Method::POST,
+            url: url.into(),
+            headers: Vec::new(),
+            form: Some(serde_urlencoded::to_string(form)?),
+            body_limit: None,
+        })
+    }
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.headers.push((key.into(), value.into()));
+        self
+    }
+    pub fn body_limit(mut self, v: usize) -> Self {
+        self.body_limit = Some(v);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Name of the egress that served the response, if sent through an `EgressPool`.
+    pub egress: Option<String>,
+}
+impl Response {
+    pub fn ok<T: Into<Vec<u8>>>(body: T) -> Self {
+        Response {
+            status: StatusCode::OK,
+            headers: Vec::new(),
+            body: body.into(),
+            egress: None,
+        }
+    }
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.headers.push((key.into(), value.into()));
+        self
+    }
+    pub fn egress(mut self, name: &str) -> Self {
+        self.egress = Some(name.to_string());
+        self
+    }
+    pub fn header_value(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+    /// Turns a non-2xx status into an error, with the `Retry-After` seconds if given.
+    pub fn error_for_status(self) -> Result<Self, CrawlerError> {
+        if self.status.is_success() {
+            return Ok(self);
+        }
+        if self.status == StatusCode::NOT_FOUND {
+            return Err(CrawlerError::PageNotFound);
+        }
+        Err(CrawlerError::Status {
+            status: self.status,
+            retry_after: self
+                .header_value("Retry-After")
+                .and_then(|v| v.trim().parse().ok())
+                .map(Duration::from_secs),
+        })
+    }
+    pub fn text(&self) -> Result<&str, CrawlerError> {
+        Ok(std::str::from_utf8(&self.body)?)
+    }
+}
+
+/// Sends crawler requests. `Crawler` talks to dcinside only through this trait,
+/// so the live client can be swapped with fixtures in tests.
+pub trait Transport {
+    fn send(&self, req: Request) -> LocalBoxFuture<'_, Result<Response, CrawlerError>>;
+    /// Takes an egress out of rotation after the caller found its response blocked.
+    fn bench(&self, _egress: &str) {}
+}
+
+impl<T: Transport + ?Sized> Transport for Rc<T> {
+    fn send(&self, req: Request) -> LocalBoxFuture<'_, Result<Response, CrawlerError>> {
+        (**self).send(req)
+    }
+    fn bench(&self, egress: &str) {
+        (**self).bench(egress)
+    }
+}
+
+#[derive(Clone)]
+pub struct ActixTransport {
+    client: Client,
+    body_limit: usize,
+}
+impl ActixTransport {
+    pub fn new() -> Self {
+        let client = ClientBuilder::new()
+            .header("User-Agent", DEFAULT_USER_AGENT)
+            .finish();
+        ActixTransport::with_client(client)
+    }
+    pub fn with_client(client: Client) -> Self {
+        ActixTransport {
+            client,
+            body_limit: 1024 * 1024 * 8,
+        }
+    }
+}
+impl Default for ActixTransport {
+    fn default() -> Self {
+        ActixTransport::new()
+    }
+}
+impl Transport for ActixTransport {
+    fn send(&self, req: Request) -> LocalBoxFuture<'_, Result<Response, CrawlerError>> {
+        async move {
+            let body_limit = req.body_limit.unwrap_or(self.body_limit);
+            let mut builder = self.client.request(req.method, req.url.as_str());
+            for (k, v) in req.headers.iter() {
+                builder = builder.set_header(k.as_str(), v.as_str());
+            }
+            let mut res = match req.form {
+                Some(form) => {
+                    builder
+                        .content_type("application/x-www-form-urlencoded; charset=UTF-8")
+                        .send_body(form)
+                        .await?
+                }
+                None => builder.send().await?,
+            };
+            // refused by its Content-Length before any of it is read
+            let len = res
+                .headers()
+                .get("Content-Length")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok());
+            if len.map_or(false, |len| len > body_limit as u64) {
+                return Err(CrawlerError::BodyTooLarge(len));
+            }
+            let headers = res
+                .headers()
+                .iter()
+                .filter_map(|(k, v)| {
+                    v.to_str()
+                        .ok()
+                        .map(|v| (k.as_str().to_string(), v.to_string()))
+                })
+                .collect();
+            let body = match res.body().limit(body_limit).await {
+                Err(PayloadError::Overflow) => return Err(CrawlerError::BodyTooLarge(None)),
+                res => res?,
+            };
+            Ok(Response {
+                status: res.status(),
+                headers,
+                body: body.to_vec(),
+                egress: None,
+            })
+        }
+        .boxed_local()
+    }
+}
+
+/// Spaces requests through `inner` evenly to stay under a requests-per-second ceiling.
+/// Requests take their slots in the order they are sent, however many tasks share it.
+pub struct Throttled<T> {
+    inner: T,
+    interval: Duration,
+    next: Cell<Instant>,
+}
+impl<T: Transport> Throttled<T> {
+    /// A ceiling that is not positive, or NaN, lets requests through unthrottled.
+    pub fn new(inner: T, requests_per_second: f64) -> Self {
+        Throttled {
+            inner,
+            interval: if requests_per_second > 0.0 {
+                Duration::from_secs_f64(1.0 / requests_per_second)
+            } else {
+                Duration::from_secs(0)
+            },
+            next: Cell::new(Instant::now()),
+        }
+    }
+}
+impl<T: Transport> Transport for Throttled<T> {
+    fn send(&self, req: Request) -> LocalBoxFuture<'_, Result<Response, CrawlerError>> {
+        let now = Instant::now();
+        let slot = self.next.get().max(now);
+        self.next.set(slot + self.interval);
+        async move {
+            if slot > now {
+                actix::clock::delay_for(slot - now).await;
+            }
+            self.inner.send(req).await
+        }
+        .boxed_local()
+    }
+    fn bench(&self, egress: &str) {
+        self.inner.bench(egress)
+    }
+}
+
+/// Replays canned responses instead of hitting the network.
+/// Routes are matched in registration order by url substring; unmatched
+/// requests get a 404. Every request is recorded for later inspection.
+#[derive(Default)]
+pub struct FixtureTransport {
+    routes: Vec<(String, Response)>,
+    requests: RefCell<Vec<Request>>,
+}
+impl FixtureTransport {
+    pub fn new() -> Self {
+        FixtureTransport::default()
+    }
+    pub fn route<T: Into<String>>(mut self, pattern: T, res: Response) -> Self {
+        self.routes.push((pattern.into(), res));
+        self
+    }
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.borrow().clone()
+    }
+}
+impl Transport for FixtureTransport {
+    fn send(&self, req: Request) -> LocalBoxFuture<'_, Result<Response, CrawlerError>> {
+        let res = self
+            .routes
+            .iter()
+            .find(|(pattern, _)| req.url.contains(pattern.as_str()))
+            .map(|(_, res)| res.clone())
+            .unwrap_or_else(|| Response::ok(Vec::new()).with_status(StatusCode::NOT_FOUND));
+        let too_large = req.body_limit.map_or(false, |limit| res.body.len() > limit);
+        self.requests.borrow_mut().push(req);
+        if too_large {
+            let len = res.body.len() as u64;
+            return futures::future::ready(Err(CrawlerError::BodyTooLarge(Some(len))))
+                .boxed_local();
+        }
+        futures::future::ready(Ok(res)).boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn it_throttles_requests() {
+        let transport = Throttled::new(FixtureTransport::new(), 20.0);
+        let started = Instant::now();
+        let requests = (0..5).map(|_| transport.send(Request::get("https://localhost/")));
+        futures::future::join_all(requests).await;
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(transport.inner.requests().len(), 5);
+        for rps in [0.0, -1.0, f64::NAN].iter() {
+            assert_eq!(
+                Throttled::new(FixtureTransport::new(), *rps).interval,
+                Duration::from_secs(0)
+            );
+        }
+    }
+}